@@ -7,14 +7,7 @@ fn fifo_fib(n: u64, cache: &FIFOCache<u64, u64>) -> u64 {
     if n == 1 {
         return 1;
     }
-    match cache.get(&n) {
-        Some(v) => *v,
-        None => {
-            let result = fifo_fib(n - 1, cache) + fifo_fib(n - 2, cache);
-            cache.set(n, result);
-            result
-        }
-    }
+    *cache.get_or_insert_with(n, || fifo_fib(n - 1, cache) + fifo_fib(n - 2, cache))
 }
 
 fn main() {
@@ -7,14 +7,7 @@ fn lru_fib(n: u64, cache: &LRUCache<u64, u64>) -> u64 {
     if n == 1 {
         return 1;
     }
-    match cache.get(&n) {
-        Some(v) => *v,
-        None => {
-            let result = lru_fib(n - 1, cache) + lru_fib(n - 2, cache);
-            cache.set(n, result);
-            result
-        }
-    }
+    *cache.get_or_insert_with(n, || lru_fib(n - 1, cache) + lru_fib(n - 2, cache))
 }
 
 fn main() {
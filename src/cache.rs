@@ -1,15 +1,163 @@
-use std::hash::Hash;
-use std::sync::Arc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use core::hash::BuildHasher;
+use core::hash::Hash;
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use linked_hash_map::LinkedHashMap;
+
+/// The HashMap used by the caches, hashbrown's when the crate is built without the standard library.
+#[cfg(not(feature = "std"))]
+pub(crate) use hashbrown::HashMap;
+#[cfg(feature = "std")]
+pub(crate) use std::collections::HashMap;
 
 /// CacheStats contains cache statistics
 #[derive(Debug, Clone)]
 pub struct CacheStats {
     pub hits: u64,
     pub misses: u64,
+    /// The number of items removed to make room or because they expired, items removed explicitly are not counted.
+    pub evictions: u64,
+    /// The number of items held by the cache. For a cache with a TTL this includes expired items which have not been removed yet, so it can be larger than `len`, which only counts live items.
     pub size: u64,
     pub capacity: u64,
 }
 
+impl CacheStats {
+    /// Get the fraction of lookups which were hits, this is 0.0 if there have been no lookups.
+    pub fn hit_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+
+    /// Get the fraction of lookups which were misses, this is 0.0 if there have been no lookups.
+    pub fn miss_ratio(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.misses as f64 / lookups as f64
+        }
+    }
+}
+
+/// Format the statistics as a single line for logging, for example `hits=42 misses=8 size=50/100 hit_ratio=84.0%`.
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hits={} misses={} size={}/{} hit_ratio={:.1}%",
+            self.hits,
+            self.misses,
+            self.size,
+            self.capacity,
+            self.hit_ratio() * 100.0
+        )
+    }
+}
+
+/// The weight of the newest lookup in the moving average of the hit ratio used by `StatsCounters::new`, so the average mostly reflects about the last hundred lookups.
+const DEFAULT_HIT_RATIO_DECAY: f64 = 0.01;
+
+/// The hit and miss counts of a cache, kept outside of the cache lock so they can be updated and read without taking it.
+///
+/// An exponentially weighted moving average of the hit ratio is kept alongside the counts so a recent change in behaviour is visible after a long run. The average and the decay are stored as the bits of an `f64`, the average is NaN until the first lookup.
+#[derive(Debug)]
+pub(crate) struct StatsCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    recent_hit_ratio: AtomicU64,
+    decay: AtomicU64,
+}
+
+impl Default for StatsCounters {
+    fn default() -> Self {
+        StatsCounters {
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            recent_hit_ratio: AtomicU64::new(f64::NAN.to_bits()),
+            decay: AtomicU64::new(DEFAULT_HIT_RATIO_DECAY.to_bits()),
+        }
+    }
+}
+
+impl StatsCounters {
+    /// Create new StatsCounters with every count at zero.
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count the result of a lookup as a hit if it found a value and a miss otherwise, returning the result unchanged.
+    pub(crate) fn record<T>(&self, result: Option<T>) -> Option<T> {
+        if result.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+        let sample = if result.is_some() { 1.0 } else { 0.0 };
+        let decay = f64::from_bits(self.decay.load(Ordering::Relaxed));
+        let _ = self
+            .recent_hit_ratio
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                let average = f64::from_bits(bits);
+                let average = if average.is_nan() {
+                    sample
+                } else {
+                    average + decay * (sample - average)
+                };
+                Some(average.to_bits())
+            });
+        result
+    }
+
+    /// Get the number of hits.
+    pub(crate) fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of misses.
+    pub(crate) fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Get the moving average of the hit ratio, this is 0.0 if there have been no lookups.
+    #[cfg(feature = "std")]
+    pub(crate) fn recent_hit_ratio(&self) -> f64 {
+        let average = f64::from_bits(self.recent_hit_ratio.load(Ordering::Relaxed));
+        if average.is_nan() {
+            0.0
+        } else {
+            average
+        }
+    }
+
+    /// Set the weight of the newest lookup in the moving average of the hit ratio, clamped to between 0.0 and 1.0.
+    #[cfg(feature = "std")]
+    pub(crate) fn set_decay(&self, decay: f64) {
+        let decay = if decay.is_nan() {
+            DEFAULT_HIT_RATIO_DECAY
+        } else {
+            decay.clamp(0.0, 1.0)
+        };
+        self.decay.store(decay.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Reset the hit and miss counts to zero and forget the moving average.
+    pub(crate) fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        self.recent_hit_ratio
+            .store(f64::NAN.to_bits(), Ordering::Relaxed);
+    }
+}
+
 /// Cache trait defines the methods that a cache should implement and provides a shared interface for different cache implementations
 ///
 /// The cache trait is useful for defining generic functions that can work with any cache implementation
@@ -37,19 +185,307 @@ pub struct CacheStats {
 ///     do_something(lfu_cache);
 /// }
 /// ```
+///
+/// The trait is object safe, so caches with different policies can be chosen at runtime behind a `Box<dyn Cache<K, V>>`. Methods which take a closure, such as `retain`, `compute`, `get_or_insert_with` and `get_or_try_insert_with`, require `Self: Sized` and are not available on a trait object.
+///
+/// ```
+/// use arcache::{Cache, LRUCache, TTLCache};
+/// use std::time::Duration;
+///
+/// let expire = true;
+/// let cache: Box<dyn Cache<u64, String>> = if expire {
+///     Box::new(TTLCache::new(Duration::from_secs(60), 100))
+/// } else {
+///     Box::new(LRUCache::new(100))
+/// };
+/// cache.set(1, "one".to_string());
+/// assert_eq!(*cache.get(&1).unwrap(), "one".to_string());
+/// ```
 pub trait Cache<K: Eq + Hash + Clone + Send + Sync, V: Send + Sync>: Send + Sync {
     fn get(&self, key: &K) -> Option<Arc<V>>;
-    fn set(&self, key: K, value: V) -> Option<Arc<V>>;
+
+    /// Get several values from the cache, the results are in the same order as the keys.
+    ///
+    /// The default implementation calls `get` for each key, the caches in this crate override it to resolve every key under a single lock.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    /// Get a value from the cache without affecting the eviction order or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>>;
+
+    /// Check whether a key is in the cache without affecting the eviction order or the cache statistics.
+    fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    /// Set a value in the cache, returning the previous value if the key was already present.
+    ///
+    /// A cache with a capacity of zero stores nothing, so caching can be turned off through the capacity alone and every `get` is counted as a miss.
+    fn set(&self, key: K, value: V) -> Option<Arc<V>> {
+        self.set_arc(key, Arc::new(value))
+    }
+
+    /// Set a value in the cache and return whether it was stored, a cache may decline to store an item, for example an `LRUCache` with an admission filter or any cache with a capacity of zero.
+    ///
+    /// The default implementation checks for the key after setting it, the caches in this crate which can reject items do both under a single lock. Either way another thread may remove the item straight afterwards.
+    fn try_set(&self, key: K, value: V) -> bool {
+        self.set(key.clone(), value);
+        self.contains_key(&key)
+    }
+
+    /// Set a value only if the key is missing, returning the value which is in the cache afterwards: the existing value if the key was present, which is left unchanged, or the new value otherwise.
+    ///
+    /// This is the non-closure sibling of `get_or_insert_with` and does not update the eviction order or the cache statistics. The default implementation peeks and then sets, so another thread could set the key in between, the caches in this crate which override it do both under a single lock.
+    fn put_if_absent(&self, key: K, value: V) -> Arc<V> {
+        if let Some(existing) = self.peek(&key) {
+            return existing;
+        }
+        let value = Arc::new(value);
+        self.set_arc(key, value.clone());
+        value
+    }
+
+    /// Set a value only if the key is present, returning the value it replaced, or `None` without inserting anything if the key was missing.
+    ///
+    /// The replacement is treated like a `set` of the key, so it updates the eviction order but not the cache statistics. The default implementation checks and then sets, the caches in this crate which override it do both under a single lock.
+    fn replace(&self, key: &K, value: V) -> Option<Arc<V>> {
+        if !self.contains_key(key) {
+            return None;
+        }
+        self.set(key.clone(), value)
+    }
+
+    /// Set a value which is already wrapped in an Arc, this allows the same Arc to be shared between caches without reallocating the value.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>>;
+
+    /// Set several values in the cache, returning the previous values in the same order as the items.
+    ///
+    /// The default implementation calls `set` for each item, the caches in this crate override it to insert every item under a single lock. Capacity is enforced after each insertion, so if there are more items than the capacity only the last items are kept.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        items
+            .into_iter()
+            .map(|(key, value)| self.set(key, value))
+            .collect()
+    }
+
     fn remove(&self, key: &K) -> Option<Arc<V>>;
     fn clear(&self);
     fn stats(&self) -> CacheStats;
+
+    /// Reset the hit, miss and eviction counts to zero without changing the items in the cache.
+    fn reset_stats(&self);
+
+    /// Get the number of items in the cache.
+    ///
+    /// The default implementation reads the size from `stats`, the caches in this crate override it to avoid building the full statistics.
+    fn len(&self) -> u64 {
+        self.stats().size
+    }
+
+    /// Get the maximum number of items in the cache, including any change made with `change_capacity`.
+    ///
+    /// The default implementation reads the capacity from `stats`.
+    fn capacity(&self) -> u64 {
+        self.stats().capacity
+    }
+
+    /// Check whether the cache holds no items.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     fn change_capacity(&self, capacity: u64);
+
+    /// Remove every item for which the predicate returns false.
+    ///
+    /// The predicate is called while the cache is locked, so it must not call back into the cache.
+    fn retain<F>(&self, f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+        Self: Sized;
+
+    /// Update the value of a key in place with `f` without affecting the eviction order or the cache statistics, returning whether the key was present.
+    ///
+    /// The value is updated with `Arc::make_mut`, so if it is shared with a caller who holds an Arc from an earlier `get` it is cloned first and that caller keeps seeing the old value. `f` is called while the cache is locked, so it must not call back into the cache.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+        Self: Sized;
+
+    /// Replace the value of a key with `new` only if it currently equals `expected`, so several writers can update a shared value without an external lock.
+    ///
+    /// Returns `Ok(())` if the value was swapped, otherwise `Err` with the current value, or `None` if the key is missing. Like `compute` the swap does not affect the eviction order or the cache statistics. The default implementation compares and swaps inside `compute`, so it is atomic in every cache but the current value returned on failure is read afterwards.
+    fn compare_and_swap(&self, key: &K, expected: &V, new: V) -> Result<(), Option<Arc<V>>>
+    where
+        V: Clone + PartialEq,
+        Self: Sized,
+    {
+        let mut swapped = false;
+        self.compute(key, |value| {
+            if *value == *expected {
+                *value = new;
+                swapped = true;
+            }
+        });
+        if swapped {
+            Ok(())
+        } else {
+            Err(self.peek(key))
+        }
+    }
+
+    /// Get a value from the cache, or compute it with `f`, insert it and return it if the key is missing.
+    ///
+    /// `f` is called at most once and no lock is held while it runs, so it is safe for `f` to use the cache (for example in recursive memoisation). A missing key is counted as a miss.
+    fn get_or_insert_with<F>(&self, key: K, f: F) -> Arc<V>
+    where
+        F: FnOnce() -> V,
+        Self: Sized,
+    {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        let value = Arc::new(f());
+        self.set_arc(key, value.clone());
+        value
+    }
+
+    /// Get a value from the cache, or compute it with the fallible `f`, insert it and return it if the key is missing.
+    ///
+    /// If `f` returns an error nothing is inserted and the error is returned, so a failed load from a backend is tried again on the next call. Like `get_or_insert_with`, no lock is held while `f` runs and a missing key is counted as a miss.
+    fn get_or_try_insert_with<F, E>(&self, key: K, f: F) -> Result<Arc<V>, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+        Self: Sized,
+    {
+        if let Some(value) = self.get(&key) {
+            return Ok(value);
+        }
+        let value = Arc::new(f()?);
+        self.set_arc(key, value.clone());
+        Ok(value)
+    }
+}
+
+/// Update a stored value in place with `f`, cloning it first if it is shared, and return whether there was a value.
+pub(crate) fn update_in_place<V: Clone, F: FnOnce(&mut V)>(
+    value: Option<&mut Arc<V>>,
+    f: F,
+) -> bool {
+    match value {
+        Some(value) => {
+            f(Arc::make_mut(value));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Replace a stored value with `new` if it equals `expected`, returning the current value if it does not.
+pub(crate) fn swap_if_equal<V: PartialEq>(
+    value: Option<&mut Arc<V>>,
+    expected: &V,
+    new: V,
+) -> Result<(), Option<Arc<V>>> {
+    match value {
+        Some(value) if **value == *expected => {
+            *value = Arc::new(new);
+            Ok(())
+        }
+        Some(value) => Err(Some(value.clone())),
+        None => Err(None),
+    }
+}
+
+/// Remove every entry of a LinkedHashMap for which the predicate returns false, preserving the order of the remaining entries.
+#[cfg(feature = "std")]
+pub(crate) fn retain_linked_hash_map<K: Eq + Hash + Clone, V, S: BuildHasher, F>(
+    map: &mut LinkedHashMap<K, V, S>,
+    mut f: F,
+) -> Vec<(K, V)>
+where
+    F: FnMut(&K, &V) -> bool,
+{
+    let rejected: Vec<K> = map
+        .iter()
+        .filter(|(key, value)| !f(key, value))
+        .map(|(key, _)| key.clone())
+        .collect();
+    rejected
+        .into_iter()
+        .filter_map(|key| map.remove(&key).map(|value| (key, value)))
+        .collect()
 }
 
+#[cfg(feature = "std")]
+pub mod adaptive;
+#[cfg(feature = "std")]
+pub mod approx_lru;
+#[cfg(feature = "std")]
+pub mod arc;
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod eviction;
 pub mod fifo;
+#[cfg(feature = "std")]
+pub mod gdsf;
+#[cfg(feature = "std")]
+pub mod grouped;
+#[cfg(feature = "std")]
+pub mod intern;
+#[cfg(feature = "std")]
 pub mod lfu;
 pub mod lifo;
+#[cfg(feature = "std")]
+pub mod loading;
+#[cfg(feature = "std")]
+pub mod local;
+#[cfg(feature = "std")]
 pub mod lru;
+#[cfg(not(feature = "std"))]
+pub mod lru_no_std;
+#[cfg(feature = "std")]
 pub mod mru;
+#[cfg(feature = "std")]
+pub mod negative;
+#[cfg(not(feature = "std"))]
+pub(crate) mod ordered_map;
+#[cfg(feature = "std")]
+pub mod policy;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+#[cfg(feature = "std")]
 pub mod random_replacement;
+#[cfg(feature = "std")]
+pub mod sharded;
+pub mod sieve;
+#[cfg(feature = "std")]
+pub mod single_flight;
+#[cfg(feature = "std")]
+pub(crate) mod sketch;
+#[cfg(feature = "std")]
+pub mod slru;
+#[cfg(feature = "std")]
+pub mod snapshot;
+pub(crate) mod sync;
+#[cfg(feature = "std")]
+pub mod tiered;
+pub mod time;
+#[cfg(feature = "std")]
+pub(crate) mod timer_wheel;
+#[cfg(feature = "std")]
 pub mod ttl;
+#[cfg(not(feature = "std"))]
+pub mod ttl_no_std;
+#[cfg(feature = "std")]
+pub mod two_queue;
+pub mod unbounded;
+#[cfg(feature = "std")]
+pub mod weak;
+#[cfg(feature = "std")]
+pub mod weighted_lru;
+#[cfg(feature = "std")]
+pub mod write_back;
+#[cfg(feature = "std")]
+pub mod wtinylfu;
@@ -0,0 +1,277 @@
+use crate::cache::sync::Mutex;
+use crate::cache::{Cache, CacheStats};
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// How far above the target the hit ratio of a window must be before `tune` shrinks the cache, so the capacity does not flip between two sizes.
+const SHRINK_MARGIN: f64 = 0.05;
+
+/// AdaptiveCache wraps any cache and tunes its capacity towards a target hit ratio, for workloads whose working set drifts over time.
+///
+/// Each call to `tune` looks at the hits and misses since the previous call. If the hit ratio of that window is below the target the capacity is grown by a quarter, if it is more than 5 percentage points above the target the capacity is shrunk by a quarter, and the capacity always stays between the minimum and maximum. Call `tune` periodically, for example from a timer in the application.
+///
+/// Every other operation is passed straight to the wrapped cache.
+///
+/// Example:
+/// ```
+/// use arcache::{AdaptiveCache, Cache, LRUCache};
+///
+/// let cache = AdaptiveCache::new(LRUCache::<u64, u64>::new(10), 10, 1000, 0.9);
+/// for i in 0..100 {
+///     cache.get_or_insert_with(i % 20, || i);
+/// }
+/// // the hit ratio was below the target so the capacity grows
+/// assert_eq!(cache.tune(), 12);
+/// ```
+pub struct AdaptiveCache<K, V, C> {
+    cache: C,
+    min_capacity: u64,
+    max_capacity: u64,
+    target_hit_ratio: f64,
+    /// The hits and misses of the wrapped cache when the current window started.
+    window_start: Mutex<(u64, u64)>,
+    _marker: PhantomData<fn(K, V)>,
+}
+
+impl<K, V, C> AdaptiveCache<K, V, C>
+where
+    K: Eq + Hash + Clone + Send + Sync,
+    V: Send + Sync,
+    C: Cache<K, V>,
+{
+    /// Create a new AdaptiveCache which keeps the capacity of `cache` between `min_capacity` and `max_capacity` and tunes it towards `target_hit_ratio`, a fraction between 0.0 and 1.0.
+    ///
+    /// The capacity of `cache` is moved into the bounds straight away. Panics if `min_capacity` is greater than `max_capacity`.
+    pub fn new(cache: C, min_capacity: u64, max_capacity: u64, target_hit_ratio: f64) -> Self {
+        assert!(
+            min_capacity <= max_capacity,
+            "the minimum capacity must not be greater than the maximum capacity"
+        );
+        let capacity = cache.capacity().clamp(min_capacity, max_capacity);
+        if capacity != cache.capacity() {
+            cache.change_capacity(capacity);
+        }
+        let stats = cache.stats();
+        AdaptiveCache {
+            cache,
+            min_capacity,
+            max_capacity,
+            target_hit_ratio,
+            window_start: Mutex::new((stats.hits, stats.misses)),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Get the wrapped cache.
+    pub fn cache(&self) -> &C {
+        &self.cache
+    }
+
+    /// Compare the hit ratio since the previous call to the target and grow or shrink the capacity of the wrapped cache, returning the new capacity.
+    ///
+    /// The capacity is left unchanged if there were no lookups since the previous call.
+    pub fn tune(&self) -> u64 {
+        let stats = self.cache.stats();
+        let mut window_start = self.window_start.lock();
+        let (start_hits, start_misses) = *window_start;
+        *window_start = (stats.hits, stats.misses);
+        // the counts start again from zero if the statistics of the wrapped cache were reset
+        let (hits, misses) = if stats.hits < start_hits || stats.misses < start_misses {
+            (stats.hits, stats.misses)
+        } else {
+            (stats.hits - start_hits, stats.misses - start_misses)
+        };
+        let capacity = self.cache.capacity();
+        if hits + misses == 0 {
+            return capacity;
+        }
+
+        let hit_ratio = hits as f64 / (hits + misses) as f64;
+        let step = (capacity / 4).max(1);
+        let new_capacity = if hit_ratio < self.target_hit_ratio {
+            capacity.saturating_add(step).min(self.max_capacity)
+        } else if hit_ratio > self.target_hit_ratio + SHRINK_MARGIN {
+            capacity.saturating_sub(step).max(self.min_capacity)
+        } else {
+            capacity
+        };
+        if new_capacity != capacity {
+            self.cache.change_capacity(new_capacity);
+        }
+        new_capacity
+    }
+}
+
+impl<K, V, C> Cache<K, V> for AdaptiveCache<K, V, C>
+where
+    K: Eq + Hash + Clone + Send + Sync,
+    V: Send + Sync,
+    C: Cache<K, V>,
+{
+    /// Get a value from the wrapped cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        self.cache.get(key)
+    }
+
+    /// Get several values from the wrapped cache.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        self.cache.get_many(keys)
+    }
+
+    /// Peek at a value in the wrapped cache.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        self.cache.peek(key)
+    }
+
+    /// Check whether a key is in the wrapped cache.
+    fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    /// Set a value in the wrapped cache.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        self.cache.set_arc(key, value)
+    }
+
+    /// Set a value in the wrapped cache and return whether it was stored.
+    fn try_set(&self, key: K, value: V) -> bool {
+        self.cache.try_set(key, value)
+    }
+
+    /// Set a value in the wrapped cache if the key is missing.
+    fn put_if_absent(&self, key: K, value: V) -> Arc<V> {
+        self.cache.put_if_absent(key, value)
+    }
+
+    /// Replace a value in the wrapped cache if the key is present.
+    fn replace(&self, key: &K, value: V) -> Option<Arc<V>> {
+        self.cache.replace(key, value)
+    }
+
+    /// Set several values in the wrapped cache.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        self.cache.set_many(items)
+    }
+
+    /// Remove a value from the wrapped cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        self.cache.remove(key)
+    }
+
+    /// Clear the wrapped cache.
+    fn clear(&self) {
+        self.cache.clear();
+    }
+
+    /// Get the statistics of the wrapped cache.
+    fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Reset the statistics of the wrapped cache, which also starts a new tuning window.
+    fn reset_stats(&self) {
+        let mut window_start = self.window_start.lock();
+        self.cache.reset_stats();
+        *window_start = (0, 0);
+    }
+
+    /// Get the number of items in the wrapped cache.
+    fn len(&self) -> u64 {
+        self.cache.len()
+    }
+
+    /// Get the capacity of the wrapped cache.
+    fn capacity(&self) -> u64 {
+        self.cache.capacity()
+    }
+
+    /// Change the capacity of the wrapped cache, kept within the minimum and maximum capacity.
+    fn change_capacity(&self, capacity: u64) {
+        self.cache
+            .change_capacity(capacity.clamp(self.min_capacity, self.max_capacity));
+    }
+
+    /// Remove every item for which the predicate returns false from the wrapped cache.
+    fn retain<F>(&self, f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        self.cache.retain(f);
+    }
+
+    /// Update a value in place in the wrapped cache.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        self.cache.compute(key, f)
+    }
+
+    /// Compare and swap a value in the wrapped cache.
+    fn compare_and_swap(&self, key: &K, expected: &V, new: V) -> Result<(), Option<Arc<V>>>
+    where
+        V: Clone + PartialEq,
+    {
+        self.cache.compare_and_swap(key, expected, new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LRUCache;
+
+    #[test]
+    fn test_adaptive_cache_grows_and_shrinks_within_bounds() {
+        let cache = AdaptiveCache::new(LRUCache::new(100), 4, 10, 0.5);
+        // the capacity is moved into the bounds
+        assert_eq!(cache.capacity(), 10);
+        assert_eq!(cache.tune(), 10);
+
+        for i in 0..20 {
+            cache.get(&i);
+        }
+        // every lookup missed but the capacity is already at the maximum
+        assert_eq!(cache.tune(), 10);
+
+        cache.set(1, 1);
+        for _ in 0..20 {
+            cache.get(&1);
+        }
+        // only the hits since the last tune count, so the window is well above the target
+        assert_eq!(cache.tune(), 8);
+        for _ in 0..20 {
+            cache.get(&1);
+        }
+        assert_eq!(cache.tune(), 6);
+        for _ in 0..20 {
+            cache.get(&1);
+        }
+        assert_eq!(cache.tune(), 5);
+        for _ in 0..20 {
+            cache.get(&1);
+        }
+        assert_eq!(cache.tune(), 4);
+        for _ in 0..20 {
+            cache.get(&2);
+        }
+        assert_eq!(cache.tune(), 5);
+    }
+
+    #[test]
+    fn test_adaptive_cache_new_window_after_reset() {
+        let cache = AdaptiveCache::new(LRUCache::new(8), 1, 100, 0.5);
+        for i in 0..10 {
+            cache.get(&i);
+        }
+        cache.reset_stats();
+        cache.set(1, 1);
+        cache.get(&1);
+        // the misses before the reset are forgotten
+        assert_eq!(cache.tune(), 6);
+        cache.change_capacity(1000);
+        assert_eq!(cache.capacity(), 100);
+    }
+}
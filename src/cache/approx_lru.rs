@@ -0,0 +1,374 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::cache::sync::RwLock;
+use crate::cache::{update_in_place, Cache, CacheStats, StatsCounters};
+
+/// The number of items sampled on each eviction when no sample size is given, the same default as Redis.
+const DEFAULT_SAMPLE_SIZE: usize = 5;
+
+/// A value with the tick of its last access and its position in the list of keys.
+struct Entry<V> {
+    value: Arc<V>,
+    last_access: AtomicU64,
+    index: usize,
+}
+
+/// ApproxLRUCacheInner contains the inner data structure for the ApproxLRUCache.
+struct ApproxLRUCacheInner<K: Eq + Hash + Send, V: Send + Sync> {
+    capacity: u64,
+    sample_size: usize,
+    key_value_map: HashMap<K, Entry<V>>,
+    keys: Vec<K>,
+    tick: AtomicU64,
+    rng: StdRng,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Send, V: Send + Sync> ApproxLRUCacheInner<K, V> {
+    /// Create a new ApproxLRUCacheInner with the given capacity and sample size, internally capacity is reserved for the necessary data structures.
+    fn new(capacity: u64, sample_size: usize) -> Self {
+        ApproxLRUCacheInner {
+            capacity,
+            sample_size: sample_size.max(1),
+            key_value_map: HashMap::with_capacity(capacity as usize),
+            keys: Vec::with_capacity(capacity as usize),
+            tick: AtomicU64::new(0),
+            rng: StdRng::from_os_rng(),
+            evictions: 0,
+        }
+    }
+
+    /// Get the next access tick, ticks only ever increase so a smaller tick means an older access.
+    fn next_tick(&self) -> u64 {
+        self.tick.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Look up a key and record the access, this only needs a shared reference so it can be done under a read lock.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let entry = self.key_value_map.get(key)?;
+        entry.last_access.store(self.next_tick(), Ordering::Relaxed);
+        Some(entry.value.clone())
+    }
+
+    /// Remove the key at a position in the list of keys, moving the last key into its place.
+    fn remove_at(&mut self, index: usize) -> Option<Arc<V>> {
+        let key = self.keys.swap_remove(index);
+        if let Some(moved) = self.keys.get(index) {
+            if let Some(entry) = self.key_value_map.get_mut(moved) {
+                entry.index = index;
+            }
+        }
+        self.key_value_map.remove(&key).map(|entry| entry.value)
+    }
+
+    /// Remove a key from the cache.
+    fn remove(&mut self, key: &K) -> Option<Arc<V>> {
+        let index = self.key_value_map.get(key)?.index;
+        self.remove_at(index)
+    }
+
+    /// Evict the least recently used of a random sample of items, every item is considered if there are no more items than the sample size.
+    fn evict_sampled(&mut self) {
+        if self.keys.is_empty() {
+            return;
+        }
+        let ApproxLRUCacheInner {
+            key_value_map,
+            keys,
+            rng,
+            sample_size,
+            ..
+        } = self;
+        let age = |index: &usize| {
+            key_value_map[&keys[*index]]
+                .last_access
+                .load(Ordering::Relaxed)
+        };
+        let victim = if keys.len() <= *sample_size {
+            (0..keys.len()).min_by_key(age)
+        } else {
+            rand::seq::index::sample(rng, keys.len(), *sample_size)
+                .into_iter()
+                .min_by_key(age)
+        };
+        if let Some(index) = victim {
+            self.remove_at(index);
+            self.evictions += 1;
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone + Send, V: Send + Sync> ApproxLRUCacheInner<K, V> {
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let tick = self.next_tick();
+        if let Some(entry) = self.key_value_map.get_mut(&key) {
+            *entry.last_access.get_mut() = tick;
+            return Some(std::mem::replace(&mut entry.value, value));
+        }
+        if self.key_value_map.len() as u64 >= self.capacity {
+            self.evict_sampled();
+        }
+        self.keys.push(key.clone());
+        self.key_value_map.insert(
+            key,
+            Entry {
+                value,
+                last_access: AtomicU64::new(tick),
+                index: self.keys.len() - 1,
+            },
+        );
+        None
+    }
+}
+
+/// ApproxLRUCache is a cache which approximates LRU eviction by sampling, in the same way as Redis.
+///
+/// Each item records the time of its last access, and when the cache is full a random sample of items is taken and the least recently used of them is evicted. A `get` only updates the access time of the item, so unlike `LRUCache` it does not reorder a list and only takes a read lock, which makes reads cheaper and lowers lock contention on large caches. The cost is accuracy, a larger sample size evicts closer to true LRU order but makes each eviction slower. The default sample size is 5.
+///
+/// All mutability is handled internally with a RwLock, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{ApproxLRUCache, Cache};
+///
+/// let cache = ApproxLRUCache::<&str, String>::new(10);
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct ApproxLRUCache<K: Eq + Hash + Send, V: Send + Sync> {
+    inner: RwLock<ApproxLRUCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Sync + Send, V: Send + Sync> ApproxLRUCache<K, V> {
+    /// Create a new ApproxLRUCache with the given capacity and the default sample size of 5.
+    pub fn new(capacity: u64) -> Self {
+        Self::with_sample_size(capacity, DEFAULT_SAMPLE_SIZE)
+    }
+
+    /// Create a new ApproxLRUCache with the given capacity which samples `sample_size` items on each eviction, a sample size of 0 is treated as 1.
+    pub fn with_sample_size(capacity: u64, sample_size: usize) -> Self {
+        ApproxLRUCache {
+            inner: RwLock::new(ApproxLRUCacheInner::new(capacity, sample_size)),
+            counters: StatsCounters::new(),
+        }
+    }
+
+    /// Get the number of items sampled on each eviction.
+    pub fn sample_size(&self) -> usize {
+        let inner = self.inner.read();
+        inner.sample_size
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for ApproxLRUCache<K, V> {
+    /// Get a value from the cache and record the access, this only takes a read lock.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.read();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single read lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let inner = self.inner.read();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without recording the access or updating the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.read();
+        inner
+            .key_value_map
+            .get(key)
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Set a value in the cache.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.write();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.write();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.write();
+        inner.remove(key)
+    }
+
+    /// Clear the cache.
+    fn clear(&self) {
+        let mut inner = self.inner.write();
+        inner.key_value_map.clear();
+        inner.keys.clear();
+    }
+
+    /// Get cache statistics.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.read();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.key_value_map.len() as u64,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.write();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.read();
+        inner.key_value_map.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.read();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, if the new capacity is smaller than the current size, items are evicted by sampling until it fits.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.write();
+        let old_capacity = inner.capacity;
+        inner.capacity = capacity;
+        while inner.key_value_map.len() as u64 > inner.capacity {
+            inner.evict_sampled();
+        }
+
+        if inner.capacity > old_capacity {
+            let additional = (inner.capacity - old_capacity) as usize;
+            inner.key_value_map.reserve(additional);
+        }
+    }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.write();
+        let rejected: Vec<K> = inner
+            .key_value_map
+            .iter()
+            .filter(|(key, entry)| !f(key, &entry.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in rejected {
+            inner.remove(&key);
+        }
+    }
+
+    /// Update a value in place without recording the access or updating the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.write();
+        update_in_place(
+            inner
+                .key_value_map
+                .get_mut(key)
+                .map(|entry| &mut entry.value),
+            f,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_approx_lru_cache() {
+        let cache = ApproxLRUCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        cache.set(3, 3);
+        // every item is sampled when there are no more than the sample size, so this is exact LRU
+        assert_eq!(cache.peek(&2), None);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+        assert_eq!(cache.stats().evictions, 1);
+        assert_eq!(cache.sample_size(), 5);
+    }
+
+    #[test]
+    fn test_approx_lru_cache_keeps_hot_key() {
+        let cache = ApproxLRUCache::new(100);
+        cache.set(0, 0);
+        for i in 1..1000 {
+            cache.set(i, i);
+            assert_eq!(cache.get(&0).map(|v| *v), Some(0));
+        }
+        // the hot key is never the oldest item in a sample of several distinct items
+        assert_eq!(cache.len(), 100);
+        assert_eq!(cache.stats().evictions, 900);
+    }
+
+    #[test]
+    fn test_approx_lru_cache_remove_and_retain() {
+        let cache = ApproxLRUCache::with_sample_size(10, 0);
+        assert_eq!(cache.sample_size(), 1);
+        for i in 0..10 {
+            cache.set(i, i);
+        }
+        assert_eq!(cache.remove(&3).map(|v| *v), Some(3));
+        assert_eq!(cache.remove(&3), None);
+        cache.retain(|key, _| key % 2 == 0);
+        assert_eq!(cache.len(), 5);
+        for i in 0..10 {
+            assert_eq!(cache.peek(&i).is_some(), i % 2 == 0);
+        }
+        cache.change_capacity(2);
+        assert_eq!(cache.len(), 2);
+        let key = (0..10).find(|key| cache.contains_key(key)).unwrap();
+        assert!(cache.compute(&key, |value| *value += 1));
+        assert_eq!(cache.peek(&key).map(|v| *v), Some(key + 1));
+    }
+
+    #[test]
+    fn test_approx_lru_cache_zero_capacity_stores_nothing() {
+        let cache = ApproxLRUCache::new(0);
+        assert_eq!(cache.set(1, 1), None);
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().misses, 1);
+    }
+}
@@ -0,0 +1,376 @@
+use crate::cache::sync::Mutex;
+use crate::cache::{retain_linked_hash_map, update_in_place, Cache, CacheStats, StatsCounters};
+use linked_hash_map::LinkedHashMap;
+use linked_hash_set::LinkedHashSet;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// The inner data structure for the ARCCache.
+///
+/// + `t1` holds items seen once recently, `t2` holds items seen at least twice recently.
+/// + `b1` and `b2` are ghost lists holding only the keys of items recently evicted from `t1` and `t2`.
+/// + `p` is the adaptive target size of `t1`.
+struct ARCCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    capacity: u64,
+    p: u64,
+    t1: LinkedHashMap<K, Arc<V>>,
+    t2: LinkedHashMap<K, Arc<V>>,
+    b1: LinkedHashSet<K>,
+    b2: LinkedHashSet<K>,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> ARCCacheInner<K, V> {
+    /// Create a new ARCCacheInner with the given capacity, internally capacity is reserved for the necessary data structures.
+    fn new(capacity: u64) -> Self {
+        ARCCacheInner {
+            capacity,
+            p: 0,
+            t1: LinkedHashMap::with_capacity(capacity as usize),
+            t2: LinkedHashMap::with_capacity(capacity as usize),
+            b1: LinkedHashSet::with_capacity(capacity as usize),
+            b2: LinkedHashSet::with_capacity(capacity as usize),
+            evictions: 0,
+        }
+    }
+
+    /// The number of resident items.
+    fn len(&self) -> u64 {
+        (self.t1.len() + self.t2.len()) as u64
+    }
+
+    /// The number of tracked keys, resident and ghost.
+    fn directory_len(&self) -> u64 {
+        self.len() + (self.b1.len() + self.b2.len()) as u64
+    }
+
+    /// Evict a resident item into its ghost list if the cache is full, choosing between `t1` and `t2` based on the target size `p`.
+    fn replace(&mut self, in_b2: bool) {
+        if self.len() < self.capacity {
+            return;
+        }
+        let t1_len = self.t1.len() as u64;
+        let evict_t1 = t1_len > 0 && (t1_len > self.p || (in_b2 && t1_len == self.p));
+        if evict_t1 || self.t2.is_empty() {
+            if let Some((key, _)) = self.t1.pop_front() {
+                self.b1.insert(key);
+                self.evictions += 1;
+            }
+        } else if let Some((key, _)) = self.t2.pop_front() {
+            self.b2.insert(key);
+            self.evictions += 1;
+        }
+    }
+
+    /// Trim the ghost lists so that the directory never tracks more than twice the capacity.
+    fn enforce_ghost_capacity(&mut self) {
+        while self.t1.len() as u64 + self.b1.len() as u64 > self.capacity {
+            if self.b1.pop_front().is_none() {
+                break;
+            }
+        }
+        while self.directory_len() > 2 * self.capacity {
+            if self.b2.pop_front().is_none() {
+                break;
+            }
+        }
+    }
+
+    /// Look up a key, updating the eviction order.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        if let Some(value) = self.t1.remove(key) {
+            self.t2.insert(key.clone(), value.clone());
+            Some(value)
+        } else {
+            self.t2.get_refresh(key).cloned()
+        }
+    }
+
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if let Some(previous) = self.t1.remove(&key) {
+            self.t2.insert(key, value);
+            return Some(previous);
+        }
+        if let Some(entry) = self.t2.get_refresh(&key) {
+            return Some(std::mem::replace(entry, value));
+        }
+        if self.capacity == 0 {
+            return None;
+        }
+
+        if self.b1.contains(&key) {
+            let delta = (self.b2.len() / self.b1.len()).max(1) as u64;
+            self.p = (self.p + delta).min(self.capacity);
+            self.replace(false);
+            self.b1.remove(&key);
+            self.t2.insert(key, value);
+            return None;
+        }
+        if self.b2.contains(&key) {
+            let delta = (self.b1.len() / self.b2.len()).max(1) as u64;
+            self.p = self.p.saturating_sub(delta);
+            self.replace(true);
+            self.b2.remove(&key);
+            self.t2.insert(key, value);
+            return None;
+        }
+
+        let t1_directory_len = (self.t1.len() + self.b1.len()) as u64;
+        if t1_directory_len >= self.capacity {
+            if (self.t1.len() as u64) < self.capacity {
+                self.b1.pop_front();
+                self.replace(false);
+            } else if self.t1.pop_front().is_some() {
+                self.evictions += 1;
+            }
+        } else if self.directory_len() >= self.capacity {
+            if self.directory_len() >= 2 * self.capacity {
+                self.b2.pop_front();
+            }
+            self.replace(false);
+        }
+        self.t1.insert(key, value);
+        None
+    }
+}
+
+/// ARCCache is a cache that uses the Adaptive Replacement Cache (ARC) algorithm to evict items.
+///
+/// ARC keeps two LRU lists of resident items, one for items seen once and one for items seen at least twice, along with two ghost lists of recently evicted keys. Hits in the ghost lists are used to adapt how much of the capacity is given to recency versus frequency, so the cache tunes itself to the workload and resists being flushed by scans.
+///
+/// All mutability is handled internally with a Mutex, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, ARCCache};
+///
+/// let cache = ARCCache::<&str, String>::new(10);
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct ARCCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    inner: Mutex<ARCCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> ARCCache<K, V> {
+    /// Create a new ARCCache with the given capacity.
+    pub fn new(capacity: u64) -> Self {
+        ARCCache {
+            inner: Mutex::new(ARCCacheInner::new(capacity)),
+            counters: StatsCounters::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for ARCCache<K, V> {
+    /// Get a value from the cache, a hit on an item seen once promotes it to the frequently used list.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner.t1.get(key).or_else(|| inner.t2.get(key)).cloned()
+    }
+
+    /// Set a value in the cache, keys found in a ghost list adapt the target size of the recency list before being inserted.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.t1.remove(key).or_else(|| inner.t2.remove(key))
+    }
+
+    /// Clear the cache, removing all items and ghost entries.
+    fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.t1.clear();
+        inner.t2.clear();
+        inner.b1.clear();
+        inner.b2.clear();
+        inner.p = 0;
+    }
+
+    /// Get the cache statistics.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.len(),
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.len()
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, the target size `p` is rescaled proportionally and items are evicted into the ghost lists until the resident items fit.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.lock();
+        let old_capacity = inner.capacity;
+        inner.p = if old_capacity == 0 {
+            0
+        } else {
+            (inner.p as u128 * capacity as u128 / old_capacity as u128) as u64
+        };
+        inner.capacity = capacity;
+
+        while inner.len() > inner.capacity {
+            inner.replace(false);
+        }
+        inner.enforce_ghost_capacity();
+
+        if inner.capacity > old_capacity {
+            let additional = (inner.capacity - old_capacity) as usize;
+            inner.t1.reserve(additional);
+            inner.t2.reserve(additional);
+        }
+    }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        retain_linked_hash_map(&mut inner.t1, &mut f);
+        retain_linked_hash_map(&mut inner.t2, &mut f);
+    }
+
+    /// Update a value in place without affecting the eviction order or the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        let ARCCacheInner { t1, t2, .. } = &mut *inner;
+        update_in_place(t1.get_mut(key).or_else(|| t2.get_mut(key)), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arc_cache() {
+        let cache = ARCCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        cache.set(3, 3);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+        assert_eq!(cache.stats().size, 2);
+    }
+
+    #[test]
+    fn test_arc_cache_scan_resistance() {
+        let arc_cache = ARCCache::new(4);
+        let lru_cache = crate::LRUCache::new(4);
+        for cache in [&arc_cache as &dyn Cache<i32, i32>, &lru_cache] {
+            cache.set(0, 0);
+            cache.get(&0);
+            for i in 1..20 {
+                cache.set(i, i);
+            }
+        }
+        assert_eq!(arc_cache.get(&0).map(|v| *v), Some(0));
+        assert_eq!(lru_cache.get(&0), None);
+    }
+
+    #[test]
+    fn test_arc_cache_ghost_hit_adapts() {
+        let cache = ARCCache::new(2);
+        cache.set(1, 1);
+        cache.get(&1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        assert_eq!(cache.inner.lock().p, 0);
+        // 2 was evicted into the recency ghost list, setting it again grows the recency target
+        cache.set(2, 2);
+        assert_eq!(cache.inner.lock().p, 1);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.stats().size, 2);
+    }
+
+    #[test]
+    fn test_arc_cache_change_capacity() {
+        let cache = ARCCache::new(4);
+        for i in 0..4 {
+            cache.set(i, i);
+        }
+        cache.get(&0);
+        cache.change_capacity(2);
+        assert_eq!(cache.stats().size, 2);
+        assert_eq!(cache.get(&0).map(|v| *v), Some(0));
+        let inner = cache.inner.lock();
+        assert!(inner.len() <= inner.capacity);
+        assert!(inner.directory_len() <= 2 * inner.capacity);
+    }
+
+    #[test]
+    fn test_arc_cache_clear() {
+        let cache = ARCCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.clear();
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), None);
+    }
+}
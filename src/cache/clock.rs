@@ -0,0 +1,368 @@
+use crate::cache::sync::RwLock;
+use crate::cache::{update_in_place, Cache, CacheStats, HashMap, StatsCounters};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A slot in the circular buffer of the ClockCache.
+struct ClockSlot<K, V> {
+    key: K,
+    value: Arc<V>,
+    referenced: AtomicBool,
+}
+
+/// The inner data structure for the ClockCache.
+struct ClockCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    capacity: u64,
+    slots: Vec<Option<ClockSlot<K, V>>>,
+    index: HashMap<K, usize>,
+    free: Vec<usize>,
+    hand: usize,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> ClockCacheInner<K, V> {
+    /// Create a new ClockCacheInner with the given capacity, internally capacity is reserved for the necessary data structures.
+    fn new(capacity: u64) -> Self {
+        ClockCacheInner {
+            capacity,
+            slots: Vec::with_capacity(capacity as usize),
+            index: HashMap::with_capacity(capacity as usize),
+            free: Vec::new(),
+            hand: 0,
+            evictions: 0,
+        }
+    }
+
+    /// Sweep the hand around the buffer, clearing reference bits, until an unreferenced item is found and evicted.
+    ///
+    /// Returns the index of the freed slot.
+    fn evict(&mut self) -> Option<usize> {
+        if self.index.is_empty() {
+            return None;
+        }
+        loop {
+            let position = self.hand;
+            self.hand = (self.hand + 1) % self.slots.len();
+            if let Some(slot) = &self.slots[position] {
+                if slot.referenced.swap(false, Ordering::Relaxed) {
+                    continue;
+                }
+                self.index.remove(&slot.key);
+                self.slots[position] = None;
+                self.evictions += 1;
+                return Some(position);
+            }
+        }
+    }
+
+    /// Find a slot for a new item, evicting an item if the cache is full.
+    fn claim_slot(&mut self) -> Option<usize> {
+        if (self.index.len() as u64) < self.capacity {
+            if let Some(position) = self.free.pop() {
+                return Some(position);
+            }
+            if (self.slots.len() as u64) < self.capacity {
+                self.slots.push(None);
+                return Some(self.slots.len() - 1);
+            }
+        }
+        self.evict()
+    }
+
+    /// Rebuild the circular buffer without any empty slots.
+    fn compact(&mut self) {
+        let slots = core::mem::take(&mut self.slots);
+        self.slots = slots.into_iter().flatten().map(Some).collect();
+        self.index.clear();
+        for (position, slot) in self.slots.iter().enumerate() {
+            if let Some(slot) = slot {
+                self.index.insert(slot.key.clone(), position);
+            }
+        }
+        self.free.clear();
+        self.hand = 0;
+    }
+
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if let Some(position) = self.index.get(&key).copied() {
+            if let Some(slot) = self.slots[position].as_mut() {
+                slot.referenced.store(true, Ordering::Relaxed);
+                return Some(core::mem::replace(&mut slot.value, value));
+            }
+        }
+
+        let position = self.claim_slot()?;
+        self.index.insert(key.clone(), position);
+        self.slots[position] = Some(ClockSlot {
+            key,
+            value,
+            referenced: AtomicBool::new(false),
+        });
+        None
+    }
+}
+
+/// ClockCache is a cache that uses the CLOCK (second chance) algorithm to evict items.
+///
+/// Items are stored in a circular buffer of slots, each with a reference bit which is set whenever the item is read. When the cache is full a hand sweeps around the buffer clearing reference bits until it finds an item which has not been referenced since the last sweep and evicts it. This approximates LRU without reordering a list on every `get`.
+///
+/// Mutability is handled internally with a RwLock. Because a `get` only needs to set an atomic reference bit and bump an atomic counter it takes a shared read lock, so concurrent readers do not block each other. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, ClockCache};
+///
+/// let cache = ClockCache::<&str, String>::new(10);
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct ClockCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    inner: RwLock<ClockCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> ClockCache<K, V> {
+    /// Create a new ClockCache with the given capacity.
+    pub fn new(capacity: u64) -> Self {
+        ClockCache {
+            inner: RwLock::new(ClockCacheInner::new(capacity)),
+            counters: StatsCounters::new(),
+        }
+    }
+
+    /// Look up a key, setting its reference bit and updating the hit and miss counts.
+    fn lookup(&self, inner: &ClockCacheInner<K, V>, key: &K) -> Option<Arc<V>> {
+        let result = inner
+            .index
+            .get(key)
+            .and_then(|position| inner.slots[*position].as_ref())
+            .map(|slot| {
+                slot.referenced.store(true, Ordering::Relaxed);
+                slot.value.clone()
+            });
+
+        self.counters.record(result)
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for ClockCache<K, V> {
+    /// Get a value from the cache, setting its reference bit.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.read();
+        self.lookup(&inner, key)
+    }
+
+    /// Get several values from the cache under a single read lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let inner = self.inner.read();
+        keys.iter().map(|key| self.lookup(&inner, key)).collect()
+    }
+
+    /// Peek at a value in the cache without setting its reference bit or updating the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.read();
+        inner
+            .index
+            .get(key)
+            .and_then(|position| inner.slots[*position].as_ref())
+            .map(|slot| slot.value.clone())
+    }
+
+    /// Set a value in the cache.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.write();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.write();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.write();
+        let position = inner.index.remove(key)?;
+        inner.free.push(position);
+        inner.slots[position].take().map(|slot| slot.value)
+    }
+
+    /// Clear the cache, removing all items.
+    fn clear(&self) {
+        let mut inner = self.inner.write();
+        inner.slots.clear();
+        inner.index.clear();
+        inner.free.clear();
+        inner.hand = 0;
+    }
+
+    /// Get the cache statistics.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.read();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.index.len() as u64,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.write();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.read();
+        inner.index.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.read();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, if the new capacity is smaller than the current size the hand sweeps the buffer evicting unreferenced items until the cache fits.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.write();
+        let old_capacity = inner.capacity;
+        inner.capacity = capacity;
+        while inner.index.len() as u64 > inner.capacity {
+            inner.evict();
+        }
+        if inner.slots.len() as u64 > inner.capacity {
+            inner.compact();
+        }
+
+        if inner.capacity > old_capacity {
+            let additional = (inner.capacity - old_capacity) as usize;
+            inner.slots.reserve(additional);
+            inner.index.reserve(additional);
+        }
+    }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.write();
+        for position in 0..inner.slots.len() {
+            let rejected = match &inner.slots[position] {
+                Some(slot) => !f(&slot.key, &slot.value),
+                None => false,
+            };
+            if rejected {
+                if let Some(slot) = inner.slots[position].take() {
+                    inner.index.remove(&slot.key);
+                    inner.free.push(position);
+                }
+            }
+        }
+    }
+
+    /// Update a value in place without setting its reference bit or updating the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.write();
+        let ClockCacheInner { slots, index, .. } = &mut *inner;
+        let value = index
+            .get(key)
+            .and_then(|position| slots[*position].as_mut())
+            .map(|slot| &mut slot.value);
+        update_in_place(value, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_cache() {
+        let cache = ClockCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        // 1 has its reference bit set so it gets a second chance and 2 is evicted
+        cache.set(3, 3);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+    }
+
+    #[test]
+    fn test_clock_cache_remove_reuses_slot() {
+        let cache = ClockCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.remove(&1).map(|v| *v), Some(1));
+        cache.set(3, 3);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+        assert_eq!(cache.stats().size, 2);
+    }
+
+    #[test]
+    fn test_clock_cache_change_capacity() {
+        let cache = ClockCache::new(3);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        cache.get(&2);
+        cache.change_capacity(1);
+        assert_eq!(cache.stats().size, 1);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        cache.change_capacity(2);
+        cache.set(4, 4);
+        assert_eq!(cache.stats().size, 2);
+    }
+
+    #[test]
+    fn test_clock_cache_clear() {
+        let cache = ClockCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.clear();
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[test]
+    fn test_clock_cache_stats() {
+        let cache = ClockCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&1);
+        cache.get(&2);
+        cache.get(&3);
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.size, 2);
+        assert_eq!(stats.capacity, 2);
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+
+/// The reason an item left a cache, passed to eviction listeners.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionReason {
+    /// The item was evicted to make room because the cache was at capacity.
+    Capacity,
+    /// The item was removed because its time-to-live had passed.
+    Expired,
+    /// The value was overwritten by a new value for the same key.
+    Replaced,
+    /// The item was removed explicitly, for example with `remove`, `retain` or `clear`.
+    Manual,
+}
+
+/// A function called with every item which leaves a cache.
+pub(crate) type EvictionListener<K, V> = Arc<dyn Fn(&K, &Arc<V>, EvictionReason) + Send + Sync>;
+
+/// An item which left a cache, sent to the receivers returned by `eviction_events`.
+pub type EvictionEvent<K, V> = (K, Arc<V>, EvictionReason);
+
+/// The number of events an eviction event channel holds before further events are dropped.
+pub(crate) const EVICTION_EVENT_BOUND: usize = 1024;
+
+/// Collects the items which leave a cache while it is locked, so the listener can be called once the lock has been released.
+///
+/// Nothing is collected unless a listener has been set or a channel subscribed, so caches without either do not pay for cloning keys.
+pub(crate) struct EvictionQueue<K, V> {
+    listener: Option<EvictionListener<K, V>>,
+    subscribers: Vec<SyncSender<EvictionEvent<K, V>>>,
+    pending: Vec<(K, Arc<V>, EvictionReason)>,
+}
+
+impl<K: Clone, V> EvictionQueue<K, V> {
+    /// Create a new EvictionQueue with no listener.
+    pub(crate) fn new() -> Self {
+        EvictionQueue {
+            listener: None,
+            subscribers: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Set the listener, replacing any previous one.
+    pub(crate) fn set_listener(&mut self, listener: EvictionListener<K, V>) {
+        self.listener = Some(listener);
+    }
+
+    /// Subscribe a new channel which receives every item that leaves the cache from now on, holding up to `bound` events which have not been received.
+    pub(crate) fn subscribe(&mut self, bound: usize) -> Receiver<EvictionEvent<K, V>> {
+        let (sender, receiver) = mpsc::sync_channel(bound);
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Check whether a listener has been set or a channel subscribed.
+    pub(crate) fn is_listening(&self) -> bool {
+        self.listener.is_some() || !self.subscribers.is_empty()
+    }
+
+    /// Record an item which left the cache, the key is only cloned if a listener has been set.
+    pub(crate) fn push(&mut self, key: &K, value: Arc<V>, reason: EvictionReason) {
+        if self.is_listening() {
+            self.pending.push((key.clone(), value, reason));
+        }
+    }
+
+    /// Send the recorded items to the subscribed channels and take them so they can be passed to the listener after the cache lock is released.
+    ///
+    /// Sending never blocks, an event is dropped for a channel which is full and a channel whose receiver has been dropped is unsubscribed.
+    pub(crate) fn take(&mut self) -> PendingEvictions<K, V> {
+        if !self.subscribers.is_empty() && !self.pending.is_empty() {
+            let pending = &self.pending;
+            self.subscribers.retain(|sender| {
+                pending.iter().all(|(key, value, reason)| {
+                    !matches!(
+                        sender.try_send((key.clone(), value.clone(), *reason)),
+                        Err(TrySendError::Disconnected(_))
+                    )
+                })
+            });
+        }
+        let evicted = std::mem::take(&mut self.pending);
+        PendingEvictions {
+            listener: self.listener.clone(),
+            evicted: if self.listener.is_some() {
+                evicted
+            } else {
+                Vec::new()
+            },
+        }
+    }
+}
+
+/// Items taken from an EvictionQueue which have not yet been passed to the listener.
+#[must_use = "the listener is only called when the pending evictions are notified"]
+pub(crate) struct PendingEvictions<K, V> {
+    listener: Option<EvictionListener<K, V>>,
+    evicted: Vec<(K, Arc<V>, EvictionReason)>,
+}
+
+impl<K, V> PendingEvictions<K, V> {
+    /// Call the listener with every pending item, this must be called without holding the cache lock so the listener is free to use the cache.
+    pub(crate) fn notify(self) {
+        if let Some(listener) = self.listener {
+            for (key, value, reason) in self.evicted {
+                listener(&key, &value, reason);
+            }
+        }
+    }
+}
@@ -1,16 +1,17 @@
-use std::collections::{HashMap, VecDeque};
-use std::hash::Hash;
-use std::sync::{Arc, Mutex};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hash::Hash;
 
-use crate::cache::{Cache, CacheStats};
+use crate::cache::sync::Mutex;
+use crate::cache::{update_in_place, Cache, CacheStats, HashMap, StatsCounters};
 
 /// FIFOCacheInner contains the inner data structure for the FIFOCache.
 struct FIFOCacheInner<K: Eq + Hash + Send, V: Send + Sync> {
     capacity: u64,
     key_value_map: HashMap<K, Arc<V>>,
     fifo: VecDeque<K>,
-    hits: u64,
-    misses: u64,
+    evictions: u64,
 }
 
 impl<K: Eq + Hash + Send, V: Send + Sync> FIFOCacheInner<K, V> {
@@ -20,10 +21,36 @@ impl<K: Eq + Hash + Send, V: Send + Sync> FIFOCacheInner<K, V> {
             capacity,
             key_value_map: HashMap::with_capacity(capacity as usize),
             fifo: VecDeque::with_capacity(capacity as usize),
-            hits: 0,
-            misses: 0,
+            evictions: 0,
         }
     }
+
+    /// Look up a key.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        self.key_value_map.get(key).cloned()
+    }
+}
+
+impl<K: Eq + Hash + Clone + Send, V: Send + Sync> FIFOCacheInner<K, V> {
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value. Replacing the value of a key keeps its place in the queue.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        if let Some(entry) = self.key_value_map.get_mut(&key) {
+            return Some(core::mem::replace(entry, value));
+        }
+        if self.key_value_map.len() as u64 >= self.capacity {
+            if let Some(oldest_key) = self.fifo.pop_front() {
+                if self.key_value_map.remove(&oldest_key).is_some() {
+                    self.evictions += 1;
+                }
+            }
+        }
+        let result = self.key_value_map.insert(key.clone(), value);
+        self.fifo.push_back(key);
+        result
+    }
 }
 
 /// FIFOCache is a first-in-first-out cache implementation.
@@ -50,6 +77,7 @@ impl<K: Eq + Hash + Send, V: Send + Sync> FIFOCacheInner<K, V> {
 /// ```
 pub struct FIFOCache<K: Eq + Hash + Send, V: Send + Sync> {
     inner: Mutex<FIFOCacheInner<K, V>>,
+    counters: StatsCounters,
 }
 
 impl<K: Eq + Hash + Sync + Send, V: Send + Sync> FIFOCache<K, V> {
@@ -57,40 +85,64 @@ impl<K: Eq + Hash + Sync + Send, V: Send + Sync> FIFOCache<K, V> {
     pub fn new(capacity: u64) -> Self {
         FIFOCache {
             inner: Mutex::new(FIFOCacheInner::new(capacity)),
+            counters: StatsCounters::new(),
         }
     }
 }
 
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> FIFOCache<K, V> {
+    /// Get the key of the oldest item, which is the next to be evicted, for example to log what is about to go under memory pressure.
+    pub fn oldest_key(&self) -> Option<K> {
+        let inner = self.inner.lock();
+        inner.fifo.front().cloned()
+    }
+
+    /// Get the key of the newest item, which was inserted most recently.
+    pub fn newest_key(&self) -> Option<K> {
+        let inner = self.inner.lock();
+        inner.fifo.back().cloned()
+    }
+}
+
 impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for FIFOCache<K, V> {
     /// Get a value from the cache.
     fn get(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        let result = inner.key_value_map.get(key).cloned();
-        if result.is_some() {
-            inner.hits += 1;
-        } else {
-            inner.misses += 1;
-        }
-        result
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner.key_value_map.get(key).cloned()
     }
 
     /// Set a value in the cache.
-    fn set(&self, key: K, value: V) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        if inner.key_value_map.len() as u64 >= inner.capacity {
-            if let Some(oldest_key) = inner.fifo.pop_front() {
-                inner.key_value_map.remove(&oldest_key);
-            }
-        }
-        let arc_value = Arc::new(value);
-        let result = inner.key_value_map.insert(key.clone(), arc_value);
-        inner.fifo.push_back(key);
-        result
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
     }
 
     /// Remove a value from the cache.
     fn remove(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         let result = inner.key_value_map.remove(key);
         if let Some(pos) = inner.fifo.iter().position(|k| k == key) {
             inner.fifo.remove(pos);
@@ -100,30 +152,52 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for FIFOCac
 
     /// Clear the cache.
     fn clear(&self) {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         inner.key_value_map.clear();
         inner.fifo.clear();
     }
 
     /// Get cache statistics.
     fn stats(&self) -> CacheStats {
-        let inner = self.inner.lock().unwrap();
+        let inner = self.inner.lock();
         CacheStats {
-            hits: inner.hits,
-            misses: inner.misses,
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
             size: inner.key_value_map.len() as u64,
             capacity: inner.capacity,
         }
     }
 
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.key_value_map.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
     /// Change the capacity of the cache, if the new capacity is smaller than the current size, the oldest items are removed.
     fn change_capacity(&self, capacity: u64) {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         let old_capacity = inner.capacity;
         inner.capacity = capacity;
         while inner.key_value_map.len() as u64 > inner.capacity {
             if let Some(oldest_key) = inner.fifo.pop_front() {
-                inner.key_value_map.remove(&oldest_key);
+                if inner.key_value_map.remove(&oldest_key).is_some() {
+                    inner.evictions += 1;
+                }
             }
         }
 
@@ -133,6 +207,31 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for FIFOCac
             inner.fifo.reserve(additional);
         }
     }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        inner.key_value_map.retain(|key, value| f(key, value));
+        let FIFOCacheInner {
+            key_value_map,
+            fifo,
+            ..
+        } = &mut *inner;
+        fifo.retain(|key| key_value_map.contains_key(key));
+    }
+
+    /// Update a value in place without affecting the insertion order or the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        update_in_place(inner.key_value_map.get_mut(key), f)
+    }
 }
 
 #[cfg(test)]
@@ -173,4 +272,93 @@ mod tests {
         assert_eq!(cache.get(&1), None);
         assert_eq!(cache.get(&2).map(|v| *v), Some(2));
     }
+
+    #[test]
+    fn test_fifo_cache_counts_evictions() {
+        let cache = FIFOCache::new(2);
+        for i in 0..5 {
+            cache.set(i, i);
+        }
+        assert_eq!(cache.stats().evictions, 3);
+    }
+
+    #[test]
+    fn test_fifo_cache_zero_capacity_stores_nothing() {
+        let cache = FIFOCache::new(0);
+        assert!(!cache.try_set(1, 1));
+        assert_eq!(cache.set(1, 1), None);
+        assert_eq!(cache.set(1, 2), None);
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.is_empty());
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.evictions, 0);
+    }
+
+    #[test]
+    fn test_fifo_cache_get_does_not_reorder() {
+        let cache = FIFOCache::new(3);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        for _ in 0..3 {
+            assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        }
+        cache.set(4, 4);
+        // 1 is still the oldest insertion however often it was read
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+    }
+
+    #[test]
+    fn test_fifo_cache_replace_keeps_position() {
+        let cache = FIFOCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.set(1, 10).map(|v| *v), Some(1));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().evictions, 0);
+        cache.set(3, 3);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        cache.set(4, 4);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+    }
+
+    #[test]
+    fn test_fifo_cache_oldest_and_newest_key() {
+        let cache = FIFOCache::new(2);
+        assert_eq!(cache.oldest_key(), None);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&1);
+        assert_eq!(cache.oldest_key(), Some(1));
+        assert_eq!(cache.newest_key(), Some(2));
+        cache.set(3, 3);
+        assert_eq!(cache.oldest_key(), Some(2));
+        assert_eq!(cache.newest_key(), Some(3));
+    }
+
+    #[test]
+    fn test_fifo_cache_remove() {
+        let cache = FIFOCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.remove(&1).map(|v| *v), Some(1));
+        cache.set(3, 3);
+        assert_eq!(cache.stats().evictions, 0);
+        cache.set(4, 4);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_fifo_cache_compare_and_swap() {
+        let cache = FIFOCache::new(2);
+        cache.set(1, 1);
+        assert_eq!(cache.compare_and_swap(&1, &1, 2), Ok(()));
+        assert_eq!(cache.compare_and_swap(&1, &1, 3), Err(Some(Arc::new(2))));
+        assert_eq!(cache.compare_and_swap(&2, &1, 3), Err(None));
+    }
 }
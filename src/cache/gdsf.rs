@@ -0,0 +1,407 @@
+use crate::cache::sync::Mutex;
+use crate::cache::{update_in_place, Cache, CacheStats, StatsCounters};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// A function which computes the cost of regenerating an item, or its size.
+type Measure<K, V, T> = Box<dyn Fn(&K, &V) -> T + Send + Sync>;
+
+/// The priority of an item, ordered with `f64::total_cmp` so it can be used as a key in a BTreeMap.
+#[derive(Debug, Clone, Copy)]
+struct Priority(f64);
+
+impl PartialEq for Priority {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Priority {}
+
+impl PartialOrd for Priority {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Priority {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// A value stored in the GDSFCache along with what is needed to compute its priority.
+struct GDSFEntry<V> {
+    value: Arc<V>,
+    frequency: u64,
+    cost: f64,
+    size: f64,
+    /// The position of the item in the priority queue, the sequence number breaks ties in favour of evicting the older item.
+    position: (Priority, u64),
+}
+
+/// The inner data structure for the GDSFCache.
+struct GDSFCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    capacity: u64,
+    cost: Measure<K, V, f64>,
+    size: Measure<K, V, u64>,
+    key_value_map: HashMap<K, GDSFEntry<V>>,
+    queue: BTreeMap<(Priority, u64), K>,
+    /// The aging clock `L`, the priority of the last evicted item.
+    clock: f64,
+    sequence: u64,
+    evictions: u64,
+    cost_saved: f64,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> GDSFCacheInner<K, V> {
+    /// Create a new GDSFCacheInner with the given capacity, cost function and size function.
+    fn new(capacity: u64, cost: Measure<K, V, f64>, size: Measure<K, V, u64>) -> Self {
+        GDSFCacheInner {
+            capacity,
+            cost,
+            size,
+            key_value_map: HashMap::with_capacity(capacity as usize),
+            queue: BTreeMap::new(),
+            clock: 0.0,
+            sequence: 0,
+            evictions: 0,
+            cost_saved: 0.0,
+        }
+    }
+
+    /// Compute the priority `H = L + frequency * cost / size` of an item and move it to that position in the queue.
+    fn reprioritise(&mut self, key: &K) {
+        let Some(entry) = self.key_value_map.get_mut(key) else {
+            return;
+        };
+        self.queue.remove(&entry.position);
+        let priority = self.clock + entry.frequency as f64 * entry.cost / entry.size;
+        self.sequence += 1;
+        entry.position = (Priority(priority), self.sequence);
+        self.queue.insert(entry.position, key.clone());
+    }
+
+    /// Measure the cost and size of an item, a size of zero is treated as one.
+    fn measure(&self, key: &K, value: &V) -> (f64, f64) {
+        let size = (self.size)(key, value).max(1) as f64;
+        ((self.cost)(key, value), size)
+    }
+
+    /// Look up a key, increasing its frequency and priority, and add its cost to the cost saved.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        let entry = self.key_value_map.get_mut(key)?;
+        entry.frequency += 1;
+        let value = entry.value.clone();
+        self.cost_saved += entry.cost;
+        self.reprioritise(key);
+        Some(value)
+    }
+
+    /// Insert a value, evicting the lowest priority item if the cache is full, and return the previous value.
+    ///
+    /// A replaced item keeps its frequency and has its cost and size measured again.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let (cost, size) = self.measure(&key, &value);
+        if let Some(entry) = self.key_value_map.get_mut(&key) {
+            entry.cost = cost;
+            entry.size = size;
+            let previous = std::mem::replace(&mut entry.value, value);
+            self.reprioritise(&key);
+            return Some(previous);
+        }
+        if self.key_value_map.len() as u64 >= self.capacity {
+            self.evict();
+        }
+        self.key_value_map.insert(
+            key.clone(),
+            GDSFEntry {
+                value,
+                frequency: 1,
+                cost,
+                size,
+                position: (Priority(0.0), 0),
+            },
+        );
+        self.reprioritise(&key);
+        None
+    }
+
+    /// Remove an item from the map and the queue.
+    fn remove(&mut self, key: &K) -> Option<Arc<V>> {
+        let entry = self.key_value_map.remove(key)?;
+        self.queue.remove(&entry.position);
+        Some(entry.value)
+    }
+
+    /// Evict the lowest priority item and advance the aging clock to its priority.
+    fn evict(&mut self) {
+        if let Some(((priority, _), key)) = self.queue.pop_first() {
+            self.key_value_map.remove(&key);
+            self.clock = priority.0;
+            self.evictions += 1;
+        }
+    }
+
+    /// Evict the lowest priority items until the cache fits within its capacity.
+    fn enforce_capacity(&mut self) {
+        while self.key_value_map.len() as u64 > self.capacity {
+            self.evict();
+        }
+    }
+}
+
+/// GDSFCache is a cache that uses the GreedyDual-Size-Frequency (GDSF) algorithm to evict items, keeping items which are expensive to regenerate for longer than cheap ones.
+///
+/// Every item has a priority `H = L + frequency * cost / size`, where the cost and size come from functions given to the constructor and `L` is an aging clock. When the cache is full the item with the lowest priority is removed and `L` is set to its priority, so items which are no longer accessed eventually fall below newer ones. The priority of an item is recomputed each time it is read.
+///
+/// The capacity is a number of items, the size only lowers the priority of large items. The cost saved by hits, the summed cost of every item which was found, is available from `cost_saved`.
+///
+/// All mutability is handled internally with a Mutex, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, GDSFCache};
+///
+/// let cache = GDSFCache::<&str, String>::new(10, |_, value| value.len() as f64);
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// assert_eq!(cache.cost_saved(), 5.0);
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct GDSFCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    inner: Mutex<GDSFCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> GDSFCache<K, V> {
+    /// Create a new GDSFCache with the given capacity and a function to compute the cost of regenerating each item, every item has a size of one.
+    pub fn new<F>(capacity: u64, cost: F) -> Self
+    where
+        F: Fn(&K, &V) -> f64 + Send + Sync + 'static,
+    {
+        Self::with_size(capacity, cost, |_, _| 1)
+    }
+
+    /// Create a new GDSFCache with the given capacity and functions to compute the cost of regenerating each item and its size, for example the size of the value in bytes. A size of zero is treated as one.
+    pub fn with_size<F, G>(capacity: u64, cost: F, size: G) -> Self
+    where
+        F: Fn(&K, &V) -> f64 + Send + Sync + 'static,
+        G: Fn(&K, &V) -> u64 + Send + Sync + 'static,
+    {
+        GDSFCache {
+            inner: Mutex::new(GDSFCacheInner::new(
+                capacity,
+                Box::new(cost),
+                Box::new(size),
+            )),
+            counters: StatsCounters::new(),
+        }
+    }
+
+    /// Get the summed cost of every item found by a `get`, an estimate of the work the cache has saved.
+    pub fn cost_saved(&self) -> f64 {
+        let inner = self.inner.lock();
+        inner.cost_saved
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for GDSFCache<K, V> {
+    /// Get a value from the cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its priority or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner
+            .key_value_map
+            .get(key)
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Set a value in the cache.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.remove(key)
+    }
+
+    /// Clear the cache, removing all items. The aging clock is kept.
+    fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.key_value_map.clear();
+        inner.queue.clear();
+    }
+
+    /// Get the cache statistics.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.key_value_map.len() as u64,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts and the cost saved.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        inner.cost_saved = 0.0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.key_value_map.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, if the new capacity is less than the current size the lowest priority items are evicted.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity;
+        inner.enforce_capacity();
+    }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        let GDSFCacheInner {
+            key_value_map,
+            queue,
+            ..
+        } = &mut *inner;
+        key_value_map.retain(|key, entry| {
+            let keep = f(key, &entry.value);
+            if !keep {
+                queue.remove(&entry.position);
+            }
+            keep
+        });
+    }
+
+    /// Update a value in place without updating the cache statistics, the cost and size of the item are measured again afterwards.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        if !update_in_place(
+            inner
+                .key_value_map
+                .get_mut(key)
+                .map(|entry| &mut entry.value),
+            f,
+        ) {
+            return false;
+        }
+        let value = inner.key_value_map[key].value.clone();
+        let (cost, size) = inner.measure(key, &value);
+        if let Some(entry) = inner.key_value_map.get_mut(key) {
+            entry.cost = cost;
+            entry.size = size;
+        }
+        inner.reprioritise(key);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gdsf_cache_keeps_expensive_items() {
+        let cache = GDSFCache::new(2, |_, value: &u64| *value as f64);
+        cache.set(1, 100);
+        cache.set(2, 1);
+        cache.get(&1);
+        cache.get(&2);
+        // both were read once but 2 is cheap to regenerate, so it is evicted
+        cache.set(3, 50);
+        assert_eq!(cache.peek(&2), None);
+        assert!(cache.contains_key(&1));
+        assert!(cache.contains_key(&3));
+        assert_eq!(cache.cost_saved(), 101.0);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_gdsf_cache_aging_clock() {
+        let cache = GDSFCache::new(2, |_, value: &u64| *value as f64);
+        cache.set(1, 10);
+        cache.set(2, 1);
+        // each eviction raises the clock, so new cheap items eventually outrank the idle expensive one
+        for i in 3..20 {
+            cache.set(i, 1);
+        }
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_gdsf_cache_size_lowers_priority() {
+        let cache = GDSFCache::with_size(2, |_, _: &u64| 10.0, |_, value: &u64| *value);
+        cache.set(1, 1);
+        cache.set(2, 100);
+        cache.set(3, 1);
+        assert_eq!(cache.peek(&2), None);
+        assert!(cache.compute(&1, |value| *value = 1000));
+        cache.set(4, 1);
+        assert_eq!(cache.peek(&1), None);
+        cache.retain(|key, _| *key != 3);
+        assert_eq!(cache.len(), 1);
+        cache.change_capacity(0);
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,259 @@
+use crate::cache::eviction::EvictionReason;
+use crate::cache::lru::LRUCache;
+use crate::cache::sync::Mutex;
+use crate::cache::{Cache, CacheStats};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// The groups of a GroupedCache, indexed both ways so a key can be untagged without searching every group.
+struct Groups<K, G> {
+    members: HashMap<G, HashSet<K>>,
+    key_groups: HashMap<K, G>,
+}
+
+impl<K: Eq + Hash + Clone, G: Eq + Hash + Clone> Groups<K, G> {
+    /// Tag a key with a group, moving it out of any group it was in before.
+    fn tag(&mut self, key: K, group: G) {
+        self.untag(&key);
+        self.members
+            .entry(group.clone())
+            .or_default()
+            .insert(key.clone());
+        self.key_groups.insert(key, group);
+    }
+
+    /// Remove the group tag of a key, dropping the group once it has no keys left.
+    fn untag(&mut self, key: &K) {
+        let Some(group) = self.key_groups.remove(key) else {
+            return;
+        };
+        if let Some(members) = self.members.get_mut(&group) {
+            members.remove(key);
+            if members.is_empty() {
+                self.members.remove(&group);
+            }
+        }
+    }
+}
+
+/// GroupedCache is an LRUCache whose items can be tagged with a group, so every item in a group can be dropped at once, for example all the items of one tenant.
+///
+/// A secondary map from each group to its keys is kept alongside the cache, so `invalidate_group` only touches the keys in the group rather than every item. The map is kept consistent through the eviction listener of the inner LRUCache, a key leaves its group whenever it leaves the cache. Setting a key with `set` keeps any group it already has, use `set_in_group` to move it to another group.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, GroupedCache};
+///
+/// let cache = GroupedCache::<&str, u64, &str>::new(10);
+/// cache.set_in_group("tenant-a", "a:1", 1);
+/// cache.set_in_group("tenant-a", "a:2", 2);
+/// cache.set_in_group("tenant-b", "b:1", 3);
+///
+/// assert_eq!(cache.invalidate_group(&"tenant-a"), 2);
+/// assert_eq!(cache.len(), 1);
+/// assert!(cache.contains_key(&"b:1"));
+/// ```
+pub struct GroupedCache<
+    K: Eq + Hash + Clone + Sync + Send + 'static,
+    V: Send + Sync + 'static,
+    G: Eq + Hash + Clone + Send + 'static,
+> {
+    cache: LRUCache<K, V>,
+    groups: Arc<Mutex<Groups<K, G>>>,
+}
+
+impl<
+        K: Eq + Hash + Clone + Sync + Send + 'static,
+        V: Send + Sync + 'static,
+        G: Eq + Hash + Clone + Send + 'static,
+    > GroupedCache<K, V, G>
+{
+    /// Create a new GroupedCache with the given capacity.
+    pub fn new(capacity: u64) -> Self {
+        let groups = Arc::new(Mutex::new(Groups {
+            members: HashMap::new(),
+            key_groups: HashMap::new(),
+        }));
+        let cache = LRUCache::new(capacity);
+        let listener_groups = groups.clone();
+        cache.on_evict(move |key, _, reason| {
+            // a replaced value keeps its key, the group is changed by set_in_group itself
+            if reason != EvictionReason::Replaced {
+                listener_groups.lock().untag(key);
+            }
+        });
+        GroupedCache { cache, groups }
+    }
+
+    /// Set a value in the cache tagged with `group`, returning the previous value if the key was already present.
+    pub fn set_in_group(&self, group: G, key: K, value: V) -> Option<Arc<V>> {
+        let previous = self.cache.set(key.clone(), value);
+        let mut groups = self.groups.lock();
+        // the item may have been evicted before it was tagged, in which case the listener has already run
+        if self.cache.contains_key(&key) {
+            groups.tag(key, group);
+        } else {
+            groups.untag(&key);
+        }
+        previous
+    }
+
+    /// Remove every item tagged with `group` from the cache, returning how many items were removed.
+    pub fn invalidate_group(&self, group: &G) -> usize {
+        let keys = match self.groups.lock().members.get(group) {
+            Some(keys) => keys.iter().cloned().collect::<Vec<_>>(),
+            None => return 0,
+        };
+        keys.iter()
+            .filter(|key| self.cache.remove(key).is_some())
+            .count()
+    }
+
+    /// Get the group a key is tagged with, if any.
+    pub fn group_of(&self, key: &K) -> Option<G> {
+        self.groups.lock().key_groups.get(key).cloned()
+    }
+}
+
+impl<
+        K: Eq + Hash + Clone + Sync + Send + 'static,
+        V: Send + Sync + 'static,
+        G: Eq + Hash + Clone + Send + 'static,
+    > Cache<K, V> for GroupedCache<K, V, G>
+{
+    /// Get a value from the cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        self.cache.get(key)
+    }
+
+    /// Get several values from the cache.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        self.cache.get_many(keys)
+    }
+
+    /// Peek at a value in the cache.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        self.cache.peek(key)
+    }
+
+    /// Check whether a key is in the cache.
+    fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    /// Set a value in the cache, keeping the group of the key if it has one.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        self.cache.set_arc(key, value)
+    }
+
+    /// Set a value in the cache and return whether it was stored.
+    fn try_set(&self, key: K, value: V) -> bool {
+        self.cache.try_set(key, value)
+    }
+
+    /// Set a value in the cache if the key is missing.
+    fn put_if_absent(&self, key: K, value: V) -> Arc<V> {
+        self.cache.put_if_absent(key, value)
+    }
+
+    /// Replace a value in the cache if the key is present.
+    fn replace(&self, key: &K, value: V) -> Option<Arc<V>> {
+        self.cache.replace(key, value)
+    }
+
+    /// Set several values in the cache.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        self.cache.set_many(items)
+    }
+
+    /// Remove a value from the cache and from its group.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        self.cache.remove(key)
+    }
+
+    /// Clear the cache and every group.
+    fn clear(&self) {
+        self.cache.clear();
+    }
+
+    /// Get the statistics of the cache.
+    fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Reset the statistics of the cache.
+    fn reset_stats(&self) {
+        self.cache.reset_stats();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        self.cache.len()
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        self.cache.capacity()
+    }
+
+    /// Change the capacity of the cache, evicted items leave their groups.
+    fn change_capacity(&self, capacity: u64) {
+        self.cache.change_capacity(capacity);
+    }
+
+    /// Remove every item for which the predicate returns false, removed items leave their groups.
+    fn retain<F>(&self, f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        self.cache.retain(f);
+    }
+
+    /// Update a value in place.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        self.cache.compute(key, f)
+    }
+
+    /// Compare and swap a value in the cache.
+    fn compare_and_swap(&self, key: &K, expected: &V, new: V) -> Result<(), Option<Arc<V>>>
+    where
+        V: Clone + PartialEq,
+    {
+        self.cache.compare_and_swap(key, expected, new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grouped_cache_invalidate_group() {
+        let cache = GroupedCache::new(3);
+        cache.set_in_group("a", 1, 1);
+        cache.set_in_group("a", 2, 2);
+        cache.set_in_group("b", 3, 3);
+        // 1 is evicted so it leaves group a
+        cache.set(4, 4);
+        assert_eq!(cache.group_of(&1), None);
+        // a plain set keeps the group
+        cache.set(2, 20);
+        assert_eq!(cache.group_of(&2), Some("a"));
+        cache.set_in_group("b", 4, 40);
+
+        assert_eq!(cache.invalidate_group(&"a"), 1);
+        assert_eq!(cache.invalidate_group(&"a"), 0);
+        assert!(!cache.contains_key(&2));
+
+        cache.remove(&3);
+        assert_eq!(cache.group_of(&3), None);
+        assert_eq!(cache.invalidate_group(&"b"), 1);
+        assert!(cache.is_empty());
+        assert!(cache.groups.lock().members.is_empty());
+    }
+}
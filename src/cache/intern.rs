@@ -0,0 +1,152 @@
+use crate::cache::sync::Mutex;
+use crate::cache::{CacheStats, StatsCounters};
+use linked_hash_map::LinkedHashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// The inner data structure for the InternCache.
+struct InternCacheInner<V: Eq + Hash> {
+    capacity: u64,
+    values: LinkedHashMap<Arc<V>, ()>,
+    evictions: u64,
+}
+
+impl<V: Eq + Hash> InternCacheInner<V> {
+    /// Remove the least recently interned values until the cache is within its capacity.
+    fn enforce_capacity(&mut self) {
+        while self.values.len() as u64 > self.capacity {
+            self.values.pop_front();
+            self.evictions += 1;
+        }
+    }
+}
+
+/// InternCache deduplicates equal values so every copy shares one Arc, saving memory when equal values are built over and over.
+///
+/// The cache is keyed by the value itself. `intern` returns the Arc which is already held for an equal value, or stores and returns a new one, and the least recently interned values are evicted when the cache is full. An evicted value stays alive for as long as callers hold its Arc, but a later equal value gets a new Arc.
+///
+/// Example:
+/// ```
+/// use arcache::InternCache;
+/// use std::sync::Arc;
+///
+/// let cache = InternCache::<String>::new(10);
+///
+/// let first = cache.intern("value".to_string());
+/// let second = cache.intern("value".to_string());
+///
+/// assert!(Arc::ptr_eq(&first, &second));
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct InternCache<V: Eq + Hash + Send + Sync> {
+    inner: Mutex<InternCacheInner<V>>,
+    counters: StatsCounters,
+}
+
+impl<V: Eq + Hash + Send + Sync> InternCache<V> {
+    /// Create a new InternCache which holds at most `capacity` distinct values.
+    pub fn new(capacity: u64) -> Self {
+        InternCache {
+            inner: Mutex::new(InternCacheInner {
+                capacity,
+                values: LinkedHashMap::with_capacity(capacity as usize),
+                evictions: 0,
+            }),
+            counters: StatsCounters::new(),
+        }
+    }
+
+    /// Get the shared Arc for a value, making it the most recently interned value, or store the value and return a new Arc if no equal value is held.
+    ///
+    /// Finding an equal value is counted as a hit and storing a new one as a miss. With a capacity of zero nothing is stored and a new Arc is always returned.
+    pub fn intern(&self, value: V) -> Arc<V> {
+        let mut inner = self.inner.lock();
+        // a refreshed value is moved to the back of the map, where its Arc can be read
+        if inner.values.get_refresh(&value).is_some() {
+            self.counters.record(Some(()));
+            return inner.values.back().unwrap().0.clone();
+        }
+        self.counters.record(None::<()>);
+        let interned = Arc::new(value);
+        if inner.capacity > 0 {
+            inner.values.insert(interned.clone(), ());
+            inner.enforce_capacity();
+        }
+        interned
+    }
+
+    /// Check whether a value equal to `value` is held, without affecting the eviction order or the cache statistics.
+    pub fn contains(&self, value: &V) -> bool {
+        self.inner.lock().values.contains_key(value)
+    }
+
+    /// Remove every value from the cache.
+    pub fn clear(&self) {
+        self.inner.lock().values.clear();
+    }
+
+    /// Get the number of values in the cache.
+    pub fn len(&self) -> u64 {
+        self.inner.lock().values.len() as u64
+    }
+
+    /// Check whether the cache holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the maximum number of values in the cache.
+    pub fn capacity(&self) -> u64 {
+        self.inner.lock().capacity
+    }
+
+    /// Change the capacity of the cache, if the new capacity is less than the current size the least recently interned values are evicted.
+    pub fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity;
+        inner.enforce_capacity();
+    }
+
+    /// Get the cache statistics, a hit is a call to `intern` which found an equal value.
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.values.len() as u64,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    pub fn reset_stats(&self) {
+        self.inner.lock().evictions = 0;
+        self.counters.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_cache_shares_arcs_and_evicts_least_recently_interned() {
+        let cache = InternCache::new(2);
+        let a = cache.intern("a".to_string());
+        let b = cache.intern("b".to_string());
+        assert!(Arc::ptr_eq(&a, &cache.intern("a".to_string())));
+        // b is the least recently interned value so it is evicted
+        cache.intern("c".to_string());
+        assert!(!cache.contains(&"b".to_string()));
+        assert!(!Arc::ptr_eq(&b, &cache.intern("b".to_string())));
+        assert_eq!(cache.len(), 2);
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 4);
+        assert_eq!(stats.evictions, 2);
+        cache.change_capacity(0);
+        assert!(cache.is_empty());
+        assert!(!Arc::ptr_eq(&a, &cache.intern("a".to_string())));
+    }
+}
@@ -1,8 +1,9 @@
-use crate::cache::{Cache, CacheStats};
+use crate::cache::sync::Mutex;
+use crate::cache::{update_in_place, Cache, CacheStats, StatsCounters};
 use linked_hash_set::LinkedHashSet;
 use std::collections::HashMap;
 use std::hash::Hash;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 /// The inner data structure for the LFUCache.
 struct LFUCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
@@ -10,9 +11,8 @@ struct LFUCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
     key_value_map: HashMap<K, Arc<V>>,
     counter: HashMap<K, u64>,
     freq_map: HashMap<u64, LinkedHashSet<K>>,
-    hits: u64,
-    misses: u64,
     min_freq: u64,
+    evictions: u64,
 }
 
 impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> LFUCacheInner<K, V> {
@@ -23,9 +23,8 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> LFUCacheInner<K, V> {
             key_value_map: HashMap::with_capacity(capacity as usize),
             counter: HashMap::with_capacity(capacity as usize),
             freq_map: HashMap::new(),
-            hits: 0,
-            misses: 0,
             min_freq: 0,
+            evictions: 0,
         }
     }
 
@@ -33,13 +32,15 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> LFUCacheInner<K, V> {
     fn increase_freq(&mut self, key: &K) {
         let freq = *self.counter.get(key).unwrap_or(&0);
         *self.counter.entry(key.clone()).or_default() += 1;
-        self.freq_map.entry(freq).or_default().remove(key);
 
-        if !self.freq_map.contains_key(&freq) {
-            if freq == self.min_freq {
-                self.min_freq += 1;
+        if let Some(bucket) = self.freq_map.get_mut(&freq) {
+            bucket.remove(key);
+            if bucket.is_empty() {
+                self.freq_map.remove(&freq);
+                if freq == self.min_freq {
+                    self.min_freq += 1;
+                }
             }
-            self.freq_map.remove(&freq);
         }
         self.freq_map
             .entry(freq + 1)
@@ -47,17 +48,74 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> LFUCacheInner<K, V> {
             .insert(key.clone());
     }
 
-    /// Remove the least frequent item from the cache.
-    fn remove_least_freq(&mut self) {
-        if let Some(bucket) = self.freq_map.get_mut(&self.min_freq) {
-            if let Some(key) = bucket.pop_front() {
-                self.key_value_map.remove(&key);
-                self.counter.remove(&key);
+    /// Remove the given key from the frequency tracking structures.
+    fn remove_freq(&mut self, key: &K) {
+        if let Some(freq) = self.counter.remove(key) {
+            if let Some(bucket) = self.freq_map.get_mut(&freq) {
+                bucket.remove(key);
+                if bucket.is_empty() {
+                    self.freq_map.remove(&freq);
+                    if freq == self.min_freq {
+                        self.update_min_freq();
+                    }
+                }
             }
-            if bucket.is_empty() {
-                self.freq_map.remove(&self.min_freq);
+        }
+    }
+
+    /// Recompute the minimum frequency from the frequency buckets that are currently populated.
+    fn update_min_freq(&mut self) {
+        self.min_freq = self.freq_map.keys().min().copied().unwrap_or(0);
+    }
+
+    /// Remove the least frequent item from the cache, ties are broken by evicting the oldest item.
+    fn remove_least_freq(&mut self) -> Option<K> {
+        if !self.freq_map.contains_key(&self.min_freq) {
+            self.update_min_freq();
+        }
+        let bucket = self.freq_map.get_mut(&self.min_freq)?;
+        let key = bucket.pop_front();
+        if bucket.is_empty() {
+            self.freq_map.remove(&self.min_freq);
+            self.update_min_freq();
+        }
+        if let Some(key) = &key {
+            self.key_value_map.remove(key);
+            self.counter.remove(key);
+            self.evictions += 1;
+        }
+        key
+    }
+
+    /// Look up a key, updating its frequency.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        let result = self.key_value_map.get(key).cloned();
+        if result.is_some() {
+            self.increase_freq(key);
+        }
+        result
+    }
+
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let existing_value = self.key_value_map.get(&key).cloned();
+
+        if existing_value.is_some() {
+            self.key_value_map.insert(key.clone(), value);
+            self.increase_freq(&key);
+        } else {
+            if self.key_value_map.len() as u64 >= self.capacity {
+                self.remove_least_freq();
             }
+            self.key_value_map.insert(key.clone(), value);
+            *self.counter.entry(key.clone()).or_default() += 1;
+            self.freq_map.entry(1).or_default().insert(key);
+            self.min_freq = 1;
         }
+        existing_value
     }
 }
 
@@ -85,6 +143,7 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> LFUCacheInner<K, V> {
 /// ```
 pub struct LFUCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
     inner: Mutex<LFUCacheInner<K, V>>,
+    counters: StatsCounters,
 }
 
 impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> LFUCache<K, V> {
@@ -92,96 +151,125 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> LFUCache<K, V> {
     pub fn new(capacity: u64) -> Self {
         LFUCache {
             inner: Mutex::new(LFUCacheInner::new(capacity)),
+            counters: StatsCounters::new(),
         }
     }
+
+    /// Get up to `n` of the most frequently accessed keys along with their access counts, most frequent first. Setting a key counts as an access, ties are ordered from the least to the most recently promoted at that count.
+    ///
+    /// This helps find hot keys worth pinning or whether the cache is large enough, it does not affect the eviction order or the cache statistics.
+    pub fn top_n(&self, n: usize) -> Vec<(K, u64)> {
+        let inner = self.inner.lock();
+        let mut freqs: Vec<u64> = inner.freq_map.keys().copied().collect();
+        freqs.sort_unstable_by(|a, b| b.cmp(a));
+        freqs
+            .into_iter()
+            .flat_map(|freq| {
+                inner.freq_map[&freq]
+                    .iter()
+                    .map(move |key| (key.clone(), freq))
+            })
+            .take(n)
+            .collect()
+    }
 }
 
 impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for LFUCache<K, V> {
     /// Get a value from the cache.
     fn get(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        let result = inner.key_value_map.get(key).cloned();
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
 
-        if result.is_some() {
-            inner.hits += 1;
-            inner.increase_freq(key);
-        } else {
-            inner.misses += 1;
-        }
-        result
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner.key_value_map.get(key).cloned()
     }
 
     /// Set a value in the cache.
-    fn set(&self, key: K, value: V) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        let arc_value = Arc::new(value);
-        let existing_value = inner.key_value_map.get(&key).cloned();
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
 
-        if existing_value.is_some() {
-            inner.key_value_map.insert(key.clone(), arc_value);
-            inner.increase_freq(&key);
-        } else {
-            if inner.key_value_map.len() as u64 >= inner.capacity {
-                inner.remove_least_freq();
-            }
-            inner.key_value_map.insert(key.clone(), arc_value);
-            *inner.counter.entry(key.clone()).or_default() += 1;
-            inner.freq_map.entry(1).or_default().insert(key);
-            inner.min_freq = 1;
-        }
-        existing_value
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
     }
 
     /// Remove a value from the cache.
     fn remove(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
 
         let result = inner.key_value_map.remove(key);
-        // if let Some(_) = result {
-        //     inner.counter.remove(key);
-        //     inner.freq_map.get_mut(&1).map(|bucket| bucket.remove(key));
-        // }
-
         if result.is_some() {
-            inner.counter.remove(key);
-            let freq = *inner.counter.get(key).unwrap_or(&0);
-            if let Some(bucket) = inner.freq_map.get_mut(&freq) {
-                bucket.remove(key);
-                if bucket.is_empty() {
-                    inner.freq_map.remove(&1);
-                    inner.min_freq = 0;
-                }
-            }
+            inner.remove_freq(key);
         }
         result
     }
 
     /// Clear the cache.
     fn clear(&self) {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         inner.key_value_map.clear();
         inner.freq_map.clear();
         inner.counter.clear();
+        inner.min_freq = 0;
     }
 
     /// Get cache statistics.
     fn stats(&self) -> CacheStats {
-        let inner = self.inner.lock().unwrap();
+        let inner = self.inner.lock();
         CacheStats {
-            hits: inner.hits,
-            misses: inner.misses,
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
             size: inner.key_value_map.len() as u64,
             capacity: inner.capacity,
         }
     }
 
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.key_value_map.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
     /// Change the capacity of the cache, if the new capacity is smaller than the current size, the least frequently used items are removed.
     fn change_capacity(&self, capacity: u64) {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         let old_capacity = inner.capacity;
         inner.capacity = capacity;
         while inner.key_value_map.len() as u64 > inner.capacity {
-            inner.remove_least_freq();
+            if inner.remove_least_freq().is_none() {
+                break;
+            }
         }
 
         if old_capacity < inner.capacity {
@@ -190,6 +278,34 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for LFUCach
             inner.counter.reserve(additional);
         }
     }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        let rejected: Vec<K> = inner
+            .key_value_map
+            .iter()
+            .filter(|(key, value)| !f(key, value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in rejected {
+            inner.key_value_map.remove(&key);
+            inner.remove_freq(&key);
+        }
+    }
+
+    /// Update a value in place without counting an access or updating the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        update_in_place(inner.key_value_map.get_mut(key), f)
+    }
 }
 
 #[cfg(test)]
@@ -244,4 +360,87 @@ mod tests {
         assert_eq!(stats.size, 2);
         assert_eq!(stats.capacity, 2);
     }
+
+    #[test]
+    fn test_lfu_cache_tie_break_evicts_oldest() {
+        let cache = LFUCache::new(3);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        cache.get(&1);
+        cache.get(&2);
+        cache.get(&3);
+        cache.get(&3);
+        // 1 and 2 share the lowest frequency, 1 was inserted first so it is evicted
+        cache.set(4, 4);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        // 4 is now the only key with the lowest frequency
+        cache.set(5, 5);
+        assert_eq!(cache.get(&4), None);
+        assert_eq!(cache.get(&5).map(|v| *v), Some(5));
+    }
+
+    #[test]
+    fn test_lfu_cache_remove() {
+        let cache = LFUCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&1);
+        assert_eq!(cache.remove(&1).map(|v| *v), Some(1));
+        cache.set(3, 3);
+        cache.set(4, 4);
+        assert_eq!(cache.stats().size, 2);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+        assert_eq!(cache.get(&4).map(|v| *v), Some(4));
+    }
+
+    #[test]
+    fn test_lfu_cache_change_capacity_trims_by_frequency() {
+        let cache = LFUCache::new(4);
+        for i in 0..4 {
+            cache.set(i, i);
+        }
+        cache.get(&0);
+        cache.get(&0);
+        cache.get(&2);
+        cache.change_capacity(2);
+        assert_eq!(cache.stats().size, 2);
+        assert_eq!(cache.get(&0).map(|v| *v), Some(0));
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        cache.change_capacity(0);
+        assert_eq!(cache.stats().size, 0);
+    }
+
+    #[test]
+    fn test_lfu_cache_retain() {
+        let cache = LFUCache::new(4);
+        for i in 0..4 {
+            cache.set(i, i);
+        }
+        cache.retain(|key, _| key % 2 != 0);
+        assert_eq!(cache.stats().size, 2);
+        cache.set(4, 4);
+        cache.set(5, 5);
+        cache.set(6, 6);
+        assert_eq!(cache.stats().size, 4);
+    }
+
+    #[test]
+    fn test_lfu_cache_top_n() {
+        let cache = LFUCache::new(10);
+        for key in 0..4 {
+            cache.set(key, key);
+        }
+        for _ in 0..3 {
+            cache.get(&2);
+        }
+        cache.get(&0);
+        cache.get(&3);
+        assert_eq!(cache.top_n(3), vec![(2, 4), (0, 2), (3, 2)]);
+        assert_eq!(cache.top_n(10).len(), 4);
+        assert!(cache.top_n(0).is_empty());
+        assert_eq!(cache.stats().hits, 5);
+    }
 }
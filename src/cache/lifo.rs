@@ -1,16 +1,16 @@
-use std::collections::HashMap;
-use std::hash::Hash;
-use std::sync::{Arc, Mutex};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hash::Hash;
 
-use crate::cache::{Cache, CacheStats};
+use crate::cache::sync::Mutex;
+use crate::cache::{update_in_place, Cache, CacheStats, HashMap, StatsCounters};
 
 /// LIFOCacheInner contains the inner data structure for the LIFOCache.
 struct LIFOCacheInner<K: Eq + Hash + Send, V: Send + Sync> {
     capacity: u64,
     key_value_map: HashMap<K, Arc<V>>,
     lifo: Vec<K>,
-    hits: u64,
-    misses: u64,
+    evictions: u64,
 }
 
 impl<K: Eq + Hash + Send, V: Send + Sync> LIFOCacheInner<K, V> {
@@ -20,10 +20,33 @@ impl<K: Eq + Hash + Send, V: Send + Sync> LIFOCacheInner<K, V> {
             capacity,
             key_value_map: HashMap::with_capacity(capacity as usize),
             lifo: Vec::with_capacity(capacity as usize),
-            hits: 0,
-            misses: 0,
+            evictions: 0,
         }
     }
+
+    /// Look up a key.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        self.key_value_map.get(key).cloned()
+    }
+}
+
+impl<K: Eq + Hash + Clone + Send, V: Send + Sync> LIFOCacheInner<K, V> {
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        if self.key_value_map.len() as u64 >= self.capacity {
+            if let Some(oldest_key) = self.lifo.pop() {
+                if self.key_value_map.remove(&oldest_key).is_some() {
+                    self.evictions += 1;
+                }
+            }
+        }
+        let result = self.key_value_map.insert(key.clone(), value);
+        self.lifo.push(key);
+        result
+    }
 }
 
 /// LIFOCache is a last-in-first-out cache implementation.
@@ -50,6 +73,7 @@ impl<K: Eq + Hash + Send, V: Send + Sync> LIFOCacheInner<K, V> {
 /// ```
 pub struct LIFOCache<K: Eq + Hash + Send, V: Send + Sync> {
     inner: Mutex<LIFOCacheInner<K, V>>,
+    counters: StatsCounters,
 }
 
 impl<K: Eq + Hash + Sync + Send, V: Send + Sync> LIFOCache<K, V> {
@@ -57,6 +81,7 @@ impl<K: Eq + Hash + Sync + Send, V: Send + Sync> LIFOCache<K, V> {
     pub fn new(capacity: u64) -> Self {
         LIFOCache {
             inner: Mutex::new(LIFOCacheInner::new(capacity)),
+            counters: StatsCounters::new(),
         }
     }
 }
@@ -64,33 +89,42 @@ impl<K: Eq + Hash + Sync + Send, V: Send + Sync> LIFOCache<K, V> {
 impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for LIFOCache<K, V> {
     /// Get a value from the cache.
     fn get(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        let result = inner.key_value_map.get(key).cloned();
-        if result.is_some() {
-            inner.hits += 1;
-        } else {
-            inner.misses += 1;
-        }
-        result
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner.key_value_map.get(key).cloned()
     }
 
     /// Set a value in the cache.
-    fn set(&self, key: K, value: V) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        if inner.key_value_map.len() as u64 >= inner.capacity {
-            if let Some(oldest_key) = inner.lifo.pop() {
-                inner.key_value_map.remove(&oldest_key);
-            }
-        }
-        let arc_value = Arc::new(value);
-        let result = inner.key_value_map.insert(key.clone(), arc_value);
-        inner.lifo.push(key);
-        result
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
     }
 
     /// Remove a value from the cache.
     fn remove(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         let result = inner.key_value_map.remove(key);
         if let Some(pos) = inner.lifo.iter().position(|k| k == key) {
             inner.lifo.remove(pos);
@@ -100,31 +134,53 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for LIFOCac
 
     /// Clear the cache.
     fn clear(&self) {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         inner.key_value_map.clear();
         inner.lifo.clear();
     }
 
     /// Get cache statistics.
     fn stats(&self) -> CacheStats {
-        let inner = self.inner.lock().unwrap();
+        let inner = self.inner.lock();
         CacheStats {
-            hits: inner.hits,
-            misses: inner.misses,
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
             size: inner.key_value_map.len() as u64,
             capacity: inner.capacity,
         }
     }
 
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.key_value_map.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
     /// Change the capacity of the cache, if the new capacity is smaller than the current size, the oldest items are removed.
     fn change_capacity(&self, capacity: u64) {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
 
         let old_capacity = inner.capacity;
         inner.capacity = capacity;
         while inner.key_value_map.len() as u64 > inner.capacity {
             if let Some(oldest_key) = inner.lifo.pop() {
-                inner.key_value_map.remove(&oldest_key);
+                if inner.key_value_map.remove(&oldest_key).is_some() {
+                    inner.evictions += 1;
+                }
             }
         }
 
@@ -134,6 +190,31 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for LIFOCac
             inner.lifo.reserve(additional);
         }
     }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        inner.key_value_map.retain(|key, value| f(key, value));
+        let LIFOCacheInner {
+            key_value_map,
+            lifo,
+            ..
+        } = &mut *inner;
+        lifo.retain(|key| key_value_map.contains_key(key));
+    }
+
+    /// Update a value in place without affecting the insertion order or the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        update_in_place(inner.key_value_map.get_mut(key), f)
+    }
 }
 
 #[cfg(test)]
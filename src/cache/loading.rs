@@ -0,0 +1,243 @@
+use crate::cache::single_flight::SingleFlight;
+use crate::cache::{Cache, CacheStats};
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// A source of values which a LoadingCache calls on a miss, for example a database query.
+///
+/// Any `Fn(&K) -> Option<V>` closure is a Loader.
+pub trait Loader<K, V>: Send + Sync {
+    /// Load the value for a key, or `None` if the key has no value. A `None` is not cached.
+    fn load(&self, key: &K) -> Option<V>;
+}
+
+impl<K, V, F> Loader<K, V> for F
+where
+    F: Fn(&K) -> Option<V> + Send + Sync,
+{
+    fn load(&self, key: &K) -> Option<V> {
+        self(key)
+    }
+}
+
+/// LoadingCache wraps any cache with a Loader so that a `get` which misses loads the value, caches it and returns it.
+///
+/// Concurrent misses for the same key share a single call to the loader, the other callers wait for it and receive the same value. No cache lock is held while the loader runs, so loads for different keys run in parallel. A key for which the loader returns `None` is not cached, so it is loaded again on the next `get`, use a `NegativeCache` if absent keys should be remembered.
+///
+/// Every other operation is passed straight to the wrapped cache, `peek` and `contains_key` never load.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, LRUCache, LoadingCache};
+///
+/// let cache = LoadingCache::new(LRUCache::<u64, String>::new(100), |key: &u64| {
+///     // look the key up in the backend
+///     (*key < 10).then(|| format!("value {}", key))
+/// });
+///
+/// assert_eq!(*cache.get(&1).unwrap(), "value 1".to_string());
+/// assert!(cache.get(&100).is_none());
+/// assert_eq!(cache.len(), 1);
+/// ```
+pub struct LoadingCache<K: Eq + Hash + Clone, V, C, L> {
+    cache: C,
+    loader: L,
+    in_flight: SingleFlight<K, Option<Arc<V>>>,
+}
+
+impl<K, V, C, L> LoadingCache<K, V, C, L>
+where
+    K: Eq + Hash + Clone + Send + Sync,
+    V: Send + Sync,
+    C: Cache<K, V>,
+    L: Loader<K, V>,
+{
+    /// Create a new LoadingCache which fills `cache` with values from `loader` on a miss.
+    pub fn new(cache: C, loader: L) -> Self {
+        LoadingCache {
+            cache,
+            loader,
+            in_flight: SingleFlight::new(),
+        }
+    }
+
+    /// Get the wrapped cache.
+    pub fn cache(&self) -> &C {
+        &self.cache
+    }
+
+    /// Load the value for a key and cache it, sharing the load with any concurrent callers for the same key.
+    fn load(&self, key: &K) -> Option<Arc<V>> {
+        let loaded = self.in_flight.run(key, || {
+            // another caller may have finished loading the key since this caller missed
+            if let Some(value) = self.cache.peek(key) {
+                return Arc::new(Some(value));
+            }
+            let value = self.loader.load(key).map(Arc::new);
+            if let Some(value) = &value {
+                self.cache.set_arc(key.clone(), value.clone());
+            }
+            Arc::new(value)
+        });
+        (*loaded).clone()
+    }
+}
+
+impl<K, V, C, L> Cache<K, V> for LoadingCache<K, V, C, L>
+where
+    K: Eq + Hash + Clone + Send + Sync,
+    V: Send + Sync,
+    C: Cache<K, V>,
+    L: Loader<K, V>,
+{
+    /// Get a value from the cache, loading and caching it if the key is missing. The miss is counted in the statistics of the wrapped cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        self.cache.get(key).or_else(|| self.load(key))
+    }
+
+    /// Peek at a value in the wrapped cache without loading it.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        self.cache.peek(key)
+    }
+
+    /// Check whether a key is in the wrapped cache without loading it.
+    fn contains_key(&self, key: &K) -> bool {
+        self.cache.contains_key(key)
+    }
+
+    /// Set a value in the wrapped cache.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        self.cache.set_arc(key, value)
+    }
+
+    /// Set a value in the wrapped cache and return whether it was stored.
+    fn try_set(&self, key: K, value: V) -> bool {
+        self.cache.try_set(key, value)
+    }
+
+    /// Set a value in the wrapped cache if the key is missing, without loading it.
+    fn put_if_absent(&self, key: K, value: V) -> Arc<V> {
+        self.cache.put_if_absent(key, value)
+    }
+
+    /// Replace a value in the wrapped cache if the key is present, a missing key is not loaded.
+    fn replace(&self, key: &K, value: V) -> Option<Arc<V>> {
+        self.cache.replace(key, value)
+    }
+
+    /// Set several values in the wrapped cache.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        self.cache.set_many(items)
+    }
+
+    /// Remove a value from the wrapped cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        self.cache.remove(key)
+    }
+
+    /// Clear the wrapped cache.
+    fn clear(&self) {
+        self.cache.clear();
+    }
+
+    /// Get the statistics of the wrapped cache.
+    fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Reset the statistics of the wrapped cache.
+    fn reset_stats(&self) {
+        self.cache.reset_stats();
+    }
+
+    /// Get the number of items in the wrapped cache.
+    fn len(&self) -> u64 {
+        self.cache.len()
+    }
+
+    /// Get the capacity of the wrapped cache.
+    fn capacity(&self) -> u64 {
+        self.cache.capacity()
+    }
+
+    /// Change the capacity of the wrapped cache.
+    fn change_capacity(&self, capacity: u64) {
+        self.cache.change_capacity(capacity);
+    }
+
+    /// Remove every item for which the predicate returns false from the wrapped cache.
+    fn retain<F>(&self, f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        self.cache.retain(f);
+    }
+
+    /// Update a value in place in the wrapped cache, a missing key is not loaded.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        self.cache.compute(key, f)
+    }
+
+    /// Compare and swap a value in the wrapped cache, a missing key is not loaded.
+    fn compare_and_swap(&self, key: &K, expected: &V, new: V) -> Result<(), Option<Arc<V>>>
+    where
+        V: Clone + PartialEq,
+    {
+        self.cache.compare_and_swap(key, expected, new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LRUCache;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_loading_cache() {
+        let loads = Arc::new(AtomicU64::new(0));
+        let counter = loads.clone();
+        let cache = LoadingCache::new(LRUCache::new(10), move |key: &u64| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            key.is_multiple_of(2).then_some(*key * 10)
+        });
+        assert_eq!(cache.get(&2).map(|v| *v), Some(20));
+        assert_eq!(cache.get(&2).map(|v| *v), Some(20));
+        assert_eq!(loads.load(Ordering::Relaxed), 1);
+
+        // a key without a value is not cached
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(loads.load(Ordering::Relaxed), 3);
+        assert!(cache.peek(&4).is_none());
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_loading_cache_deduplicates_concurrent_loads() {
+        let loads = Arc::new(AtomicU64::new(0));
+        let counter = loads.clone();
+        let cache = Arc::new(LoadingCache::new(LRUCache::new(10), move |key: &u64| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            thread::sleep(Duration::from_millis(50));
+            Some(*key)
+        }));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = cache.clone();
+                thread::spawn(move || cache.get(&1).map(|v| *v))
+            })
+            .collect();
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), Some(1));
+        }
+        assert_eq!(loads.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::cache::{retain_linked_hash_map, CacheStats};
+use linked_hash_map::LinkedHashMap;
+use std::cell::{Cell, RefCell};
+use std::hash::Hash;
+use std::rc::Rc;
+
+/// LocalCache has the same method shapes as `Cache` for caches which are used from a single thread, values are returned as Rcs and no locks or atomics are involved.
+///
+/// A LocalCache is neither `Send` nor `Sync`, use a cache implementing `Cache` to share a cache between threads.
+pub trait LocalCache<K: Eq + Hash + Clone, V> {
+    fn get(&self, key: &K) -> Option<Rc<V>>;
+
+    /// Get a value from the cache without affecting the eviction order or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Rc<V>>;
+
+    /// Check whether a key is in the cache without affecting the eviction order or the cache statistics.
+    fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    /// Set a value in the cache, returning the previous value if the key was already present.
+    fn set(&self, key: K, value: V) -> Option<Rc<V>> {
+        self.set_rc(key, Rc::new(value))
+    }
+
+    /// Set a value which is already wrapped in an Rc, this allows the same Rc to be shared between caches without reallocating the value.
+    fn set_rc(&self, key: K, value: Rc<V>) -> Option<Rc<V>>;
+
+    fn remove(&self, key: &K) -> Option<Rc<V>>;
+    fn clear(&self);
+    fn stats(&self) -> CacheStats;
+
+    /// Reset the hit, miss and eviction counts to zero without changing the items in the cache.
+    fn reset_stats(&self);
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64;
+
+    /// Get the maximum number of items in the cache, including any change made with `change_capacity`.
+    fn capacity(&self) -> u64;
+
+    /// Check whether the cache holds no items.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    fn change_capacity(&self, capacity: u64);
+
+    /// Remove every item for which the predicate returns false.
+    ///
+    /// The predicate is called while the cache is borrowed, so calling back into the cache panics.
+    fn retain<F>(&self, f: F)
+    where
+        F: FnMut(&K, &Rc<V>) -> bool;
+
+    /// Update the value of a key in place with `f` without affecting the eviction order or the cache statistics, returning whether the key was present.
+    ///
+    /// The value is updated with `Rc::make_mut`, so it is cloned first if a caller still holds an Rc from an earlier `get`. `f` is called while the cache is borrowed, so calling back into the cache panics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone;
+
+    /// Get a value from the cache, or compute it with `f`, insert it and return it if the key is missing.
+    ///
+    /// The cache is not borrowed while `f` runs, so it is safe for `f` to use the cache (for example in recursive memoisation). A missing key is counted as a miss.
+    fn get_or_insert_with<F>(&self, key: K, f: F) -> Rc<V>
+    where
+        F: FnOnce() -> V,
+    {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        let value = Rc::new(f());
+        self.set_rc(key, value.clone());
+        value
+    }
+}
+
+/// The inner data structure for the LocalLRUCache.
+struct LocalLRUCacheInner<K: Eq + Hash + Clone, V> {
+    capacity: u64,
+    key_value_map: LinkedHashMap<K, Rc<V>>,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> LocalLRUCacheInner<K, V> {
+    /// Remove the least recently used items until the cache is within its capacity.
+    fn enforce_capacity(&mut self) {
+        while self.key_value_map.len() as u64 > self.capacity {
+            self.key_value_map.pop_front();
+            self.evictions += 1;
+        }
+    }
+}
+
+/// LocalLRUCache is a single threaded LRUCache which keeps its items in a RefCell and returns values as Rcs.
+///
+/// For a cache which is only used from one thread this avoids the locking and atomic reference counting of `LRUCache`, at the cost of the cache not being `Send` or `Sync`.
+///
+/// Example:
+/// ```
+/// use arcache::{LocalCache, LocalLRUCache};
+///
+/// let cache = LocalLRUCache::<&str, String>::new(10);
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct LocalLRUCache<K: Eq + Hash + Clone, V> {
+    inner: RefCell<LocalLRUCacheInner<K, V>>,
+    hits: Cell<u64>,
+    misses: Cell<u64>,
+}
+
+impl<K: Eq + Hash + Clone, V> LocalLRUCache<K, V> {
+    /// Create a new LocalLRUCache with the given capacity.
+    pub fn new(capacity: u64) -> Self {
+        LocalLRUCache {
+            inner: RefCell::new(LocalLRUCacheInner {
+                capacity,
+                key_value_map: LinkedHashMap::with_capacity(capacity as usize),
+                evictions: 0,
+            }),
+            hits: Cell::new(0),
+            misses: Cell::new(0),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> LocalCache<K, V> for LocalLRUCache<K, V> {
+    /// Get a value from the cache, making it the most recently used item.
+    fn get(&self, key: &K) -> Option<Rc<V>> {
+        let value = self
+            .inner
+            .borrow_mut()
+            .key_value_map
+            .get_refresh(key)
+            .cloned();
+        if value.is_some() {
+            self.hits.set(self.hits.get() + 1);
+        } else {
+            self.misses.set(self.misses.get() + 1);
+        }
+        value
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Rc<V>> {
+        self.inner.borrow().key_value_map.get(key).cloned()
+    }
+
+    /// Set a value in the cache, evicting the least recently used items if the cache is full.
+    fn set_rc(&self, key: K, value: Rc<V>) -> Option<Rc<V>> {
+        let mut inner = self.inner.borrow_mut();
+        if inner.capacity == 0 {
+            return None;
+        }
+        let previous = inner.key_value_map.insert(key, value);
+        inner.enforce_capacity();
+        previous
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Rc<V>> {
+        self.inner.borrow_mut().key_value_map.remove(key)
+    }
+
+    /// Clear the cache, removing all items.
+    fn clear(&self) {
+        self.inner.borrow_mut().key_value_map.clear();
+    }
+
+    /// Get the cache statistics.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.borrow();
+        CacheStats {
+            hits: self.hits.get(),
+            misses: self.misses.get(),
+            evictions: inner.evictions,
+            size: inner.key_value_map.len() as u64,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        self.inner.borrow_mut().evictions = 0;
+        self.hits.set(0);
+        self.misses.set(0);
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        self.inner.borrow().key_value_map.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        self.inner.borrow().capacity
+    }
+
+    /// Change the capacity of the cache, if the new capacity is less than the current size the least recently used items are evicted.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.borrow_mut();
+        inner.capacity = capacity;
+        inner.enforce_capacity();
+    }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Rc<V>) -> bool,
+    {
+        let mut inner = self.inner.borrow_mut();
+        retain_linked_hash_map(&mut inner.key_value_map, &mut f);
+    }
+
+    /// Update a value in place without updating its recency or the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.borrow_mut();
+        match inner.key_value_map.get_mut(key) {
+            Some(value) => {
+                f(Rc::make_mut(value));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_lru_cache_evicts_least_recently_used() {
+        let cache = LocalLRUCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        cache.set(3, 3);
+        assert_eq!(cache.peek(&2), None);
+        assert!(cache.contains_key(&1));
+        assert!(cache.contains_key(&3));
+        cache.change_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.contains_key(&3));
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.evictions, 2);
+        assert!(cache.compute(&3, |value| *value += 1));
+        assert_eq!(cache.remove(&3).map(|v| *v), Some(4));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_local_lru_cache_recursive_get_or_insert_with() {
+        fn fib(n: u64, cache: &LocalLRUCache<u64, u64>) -> u64 {
+            if n <= 1 {
+                return n;
+            }
+            *cache.get_or_insert_with(n, || fib(n - 1, cache) + fib(n - 2, cache))
+        }
+        let cache = LocalLRUCache::new(100);
+        assert_eq!(fib(50, &cache), 12586269025);
+    }
+}
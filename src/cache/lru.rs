@@ -1,24 +1,201 @@
-use crate::cache::{Cache, CacheStats};
+use crate::cache::eviction::{EvictionEvent, EvictionQueue, EvictionReason, EVICTION_EVENT_BOUND};
+#[cfg(feature = "async")]
+use crate::cache::single_flight::AsyncSingleFlight;
+use crate::cache::single_flight::SingleFlight;
+use crate::cache::sketch::CountMinSketch;
+#[cfg(feature = "persistence")]
+use crate::cache::snapshot::{read_from_path, write_to_path};
+use crate::cache::snapshot::{CacheSnapshot, SnapshotEntry};
+use crate::cache::sync::{RwLock, RwLockWriteGuard};
+use crate::cache::time::ExpiryPolicy;
+use crate::cache::{
+    retain_linked_hash_map, swap_if_equal, update_in_place, Cache, CacheStats, StatsCounters,
+};
 use linked_hash_map::LinkedHashMap;
-use std::hash::Hash;
-use std::sync::{Arc, Mutex};
+#[cfg(feature = "persistence")]
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+#[cfg(feature = "persistence")]
+use std::{io, path::Path};
+
+/// The capacity used by `LRUCache::default`.
+const DEFAULT_CAPACITY: u64 = 1000;
+
+/// The order in which an LRUCache evicts items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {
+    /// Reading or replacing an item makes it the most recently used, so the least recently used item is evicted first.
+    #[default]
+    Access,
+    /// Items keep the position from when they were first inserted, so the oldest item is evicted first like a FIFO cache.
+    Insertion,
+}
+
+/// An internal struct of the LRU cache for storing data along with its expiry time, if the cache has a TTL.
+struct DataWithExpiry<V> {
+    data: Arc<V>,
+    expiry: Option<Instant>,
+    /// Whether the item is protected from being evicted to make room, see `LRUCache::pin`.
+    pinned: bool,
+}
+
+impl<V> DataWithExpiry<V> {
+    /// Check whether the item has passed its expiry time.
+    fn is_expired(&self) -> bool {
+        self.expiry.is_some_and(|expiry| expiry <= Instant::now())
+    }
+}
 
 /// The inner data structure for the LRUCache.
-struct LRUCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+struct LRUCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S> {
     capacity: u64,
-    key_value_map: LinkedHashMap<K, Arc<V>>,
-    hits: u64,
-    misses: u64,
+    ttl: Option<Duration>,
+    order: Order,
+    key_value_map: LinkedHashMap<K, DataWithExpiry<V>, S>,
+    eviction_queue: EvictionQueue<K, V>,
+    evictions: u64,
+    admission: Option<CountMinSketch>,
+    /// The number of pinned items, including pinned items which have expired but not been removed yet.
+    pinned: u64,
 }
 
-impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> LRUCacheInner<K, V> {
-    /// Create a new LRUCacheInner with the given capacity, internally capacity is reserved for the necessary data structures.
-    fn new(capacity: u64) -> Self {
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S: BuildHasher + Send + Sync>
+    LRUCacheInner<K, V, S>
+{
+    /// Create a new LRUCacheInner with the given capacity, optional TTL and hasher, internally capacity is reserved for the necessary data structures.
+    fn new(capacity: u64, ttl: Option<Duration>, hasher: S) -> Self {
         LRUCacheInner {
             capacity,
-            key_value_map: LinkedHashMap::with_capacity(capacity as usize),
-            hits: 0,
-            misses: 0,
+            ttl,
+            order: Order::Access,
+            key_value_map: LinkedHashMap::with_capacity_and_hasher(capacity as usize, hasher),
+            eviction_queue: EvictionQueue::new(),
+            evictions: 0,
+            admission: None,
+            pinned: 0,
+        }
+    }
+
+    /// Record an item which left the cache for the eviction listener, an expired item is always reported as expired. Returns the value if the item had not expired.
+    fn evicted(
+        &mut self,
+        key: &K,
+        entry: DataWithExpiry<V>,
+        reason: EvictionReason,
+    ) -> Option<Arc<V>> {
+        if entry.pinned {
+            self.pinned -= 1;
+        }
+        if entry.is_expired() {
+            self.eviction_queue
+                .push(key, entry.data, EvictionReason::Expired);
+            None
+        } else {
+            self.eviction_queue.push(key, entry.data.clone(), reason);
+            Some(entry.data)
+        }
+    }
+
+    /// Evict the least recently used items until the cache fits within its capacity.
+    fn enforce_capacity(&mut self) {
+        self.shrink_to(self.capacity);
+    }
+
+    /// Evict the least recently used items until there are at most `target_size`, returning how many were evicted.
+    ///
+    /// Pinned items which have not expired are walked past in place, so they keep their position in the eviction order. If every remaining item is pinned the cache is left above the target.
+    fn shrink_to(&mut self, target_size: u64) -> u64 {
+        let excess = (self.key_value_map.len() as u64).saturating_sub(target_size) as usize;
+        let victims: Vec<K> = self
+            .key_value_map
+            .iter()
+            .filter(|(_, entry)| !entry.pinned || entry.is_expired())
+            .take(excess)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &victims {
+            if let Some(entry) = self.key_value_map.remove(key) {
+                self.evictions += 1;
+                self.evicted(key, entry, EvictionReason::Capacity);
+            }
+        }
+        victims.len() as u64
+    }
+
+    /// Remove an item, returning its value if it had not expired.
+    fn remove(&mut self, key: &K, reason: EvictionReason) -> Option<Arc<V>> {
+        let entry = self.key_value_map.remove(key)?;
+        self.evicted(key, entry, reason)
+    }
+
+    /// Wrap a value with the expiry time for an item inserted now.
+    fn wrap(&self, data: Arc<V>) -> DataWithExpiry<V> {
+        DataWithExpiry {
+            data,
+            expiry: self.ttl.map(|ttl| Instant::now() + ttl),
+            pinned: false,
+        }
+    }
+
+    /// Look up a key for an update, moving it to the most recently used position unless the cache is in insertion order.
+    fn get_for_update(&mut self, key: &K) -> Option<&mut DataWithExpiry<V>> {
+        match self.order {
+            Order::Access => self.key_value_map.get_refresh(key),
+            Order::Insertion => self.key_value_map.get_mut(key),
+        }
+    }
+
+    /// Look up a key, updating the eviction order, an expired item is removed and treated as missing.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        let (result, expired) = match self.get_for_update(key) {
+            Some(entry) if entry.is_expired() => (None, true),
+            Some(entry) => (Some(entry.data.clone()), false),
+            None => (None, false),
+        };
+        if expired {
+            self.evictions += 1;
+            self.remove(key, EvictionReason::Expired);
+        }
+        result
+    }
+
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut entry = self.wrap(value);
+        if let Some(existing) = self.get_for_update(&key) {
+            entry.pinned = std::mem::take(&mut existing.pinned);
+            let previous = std::mem::replace(existing, entry);
+            return self.evicted(&key, previous, EvictionReason::Replaced);
+        }
+        if let Some(filter) = &mut self.admission {
+            let seen = filter.frequency(&key) > 0;
+            filter.increment(&key);
+            if !seen {
+                return None;
+            }
+        }
+        self.key_value_map.insert(key, entry);
+        self.enforce_capacity();
+        None
+    }
+
+    /// Remove every item, reporting each to the eviction listener.
+    fn clear(&mut self) {
+        if !self.eviction_queue.is_listening() {
+            self.key_value_map.clear();
+            self.pinned = 0;
+            return;
+        }
+        while let Some((key, entry)) = self.key_value_map.pop_front() {
+            self.evicted(&key, entry, EvictionReason::Manual);
         }
     }
 }
@@ -27,7 +204,15 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> LRUCacheInner<K, V> {
 ///
 /// When the cache is full, the item which was least recently accessed is removed to make space for the new item.
 ///
-/// All mutability is handled internally with a Mutex, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
+/// A cache created with `with_ttl` also gives every item a fixed lifetime from when it was set. Expired items are removed lazily when they are accessed and are otherwise treated as missing, reading an item does not extend its lifetime.
+///
+/// A cache created with `with_order(capacity, Order::Insertion)` keeps items in the order they were first inserted instead, so it evicts like a FIFO cache.
+///
+/// Items can be protected from eviction with `pin`, eviction then removes the least recently used item which is not pinned.
+///
+/// A listener set with `on_evict` is called with every item which leaves the cache and the reason it left.
+///
+/// All mutability is handled internally with a RwLock, so the cache can be shared between threads. Cloning an LRUCache is cheap and gives another handle to the same cache, so a clone can be moved into a spawned thread instead of wrapping the cache in an `Arc`. Operations which do not change the recency of items, such as `peek`, `contains_key`, `len` and `stats`, only take a shared read lock so they can run concurrently. A `get` moves the item to the most recently used position so it still takes the exclusive write lock. Values are returned as Arcs to allow for shared ownership.
 ///
 /// Example:
 /// ```
@@ -45,79 +230,829 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> LRUCacheInner<K, V> {
 /// assert_eq!(*value.unwrap(), "value".to_string());
 /// println!("{:?}", cache.stats());
 /// ```
-pub struct LRUCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
-    inner: Mutex<LRUCacheInner<K, V>>,
+pub struct LRUCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S = RandomState> {
+    inner: Arc<RwLock<LRUCacheInner<K, V, S>>>,
+    counters: Arc<StatsCounters>,
+    in_flight: Arc<SingleFlight<K, V>>,
+    #[cfg(feature = "async")]
+    in_flight_async: Arc<AsyncSingleFlight<K, V>>,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S> Clone for LRUCache<K, V, S> {
+    /// Create another handle to the same cache, the items, statistics and eviction listener are shared so a clone can be moved into another thread.
+    fn clone(&self) -> Self {
+        LRUCache {
+            inner: self.inner.clone(),
+            counters: self.counters.clone(),
+            in_flight: self.in_flight.clone(),
+            #[cfg(feature = "async")]
+            in_flight_async: self.in_flight_async.clone(),
+        }
+    }
 }
 
 impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> LRUCache<K, V> {
     /// Create a new LRUCache with the given capacity.
     pub fn new(capacity: u64) -> Self {
+        Self::with_hasher(capacity, RandomState::new())
+    }
+
+    /// Create a new LRUCache with the given capacity where every item expires the given time-to-live (TTL) after it was set.
+    pub fn with_ttl(capacity: u64, ttl: Duration) -> Self {
+        Self::with_ttl_and_hasher(capacity, ttl, RandomState::new())
+    }
+
+    /// Create a new LRUCache with the given capacity and eviction order, `Order::Insertion` stops `get` and `set` from reordering items so the cache evicts like a FIFO cache, for example for reproducible eviction in tests.
+    pub fn with_order(capacity: u64, order: Order) -> Self {
+        let mut inner = LRUCacheInner::new(capacity, None, RandomState::new());
+        inner.order = order;
+        Self::from_inner(inner)
+    }
+
+    /// Create a new LRUCache with the given capacity holding the items, if there are more items than the capacity only the last items are kept.
+    pub fn from_iter_with_capacity<I: IntoIterator<Item = (K, V)>>(capacity: u64, iter: I) -> Self {
+        let cache = Self::new(capacity);
+        cache.set_many(iter.into_iter().collect());
+        cache
+    }
+
+    /// Create a new LRUCache with the given capacity which only admits a new key the second time it is set, so keys which are used once, such as those from a scan, do not evict frequently used items.
+    ///
+    /// The first set of a key is recorded in a count-min sketch instead of the cache. The sketch forgets keys after about twice `capacity` other new keys have been set, since a key which is not reused within that distance would most likely have been evicted anyway. The sketch can overestimate so occasionally a key is admitted on its first set. Values inserted through `entry` are always admitted.
+    pub fn with_admission_filter(capacity: u64) -> Self {
+        let mut inner = LRUCacheInner::new(capacity, None, RandomState::new());
+        inner.admission = Some(CountMinSketch::new(
+            capacity.saturating_mul(8),
+            capacity.saturating_mul(2),
+        ));
+        Self::from_inner(inner)
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S: BuildHasher + Send + Sync>
+    LRUCache<K, V, S>
+{
+    /// Create a new LRUCache with the given capacity which hashes keys with the given hasher, for example a faster hasher for small keys.
+    pub fn with_hasher(capacity: u64, hasher: S) -> Self {
+        Self::from_inner(LRUCacheInner::new(capacity, None, hasher))
+    }
+
+    /// Create a new LRUCache with the given capacity, time-to-live (TTL) and hasher.
+    pub fn with_ttl_and_hasher(capacity: u64, ttl: Duration, hasher: S) -> Self {
+        Self::from_inner(LRUCacheInner::new(capacity, Some(ttl), hasher))
+    }
+
+    /// Wrap an LRUCacheInner in a new cache.
+    fn from_inner(inner: LRUCacheInner<K, V, S>) -> Self {
         LRUCache {
-            inner: Mutex::new(LRUCacheInner::new(capacity)),
+            inner: Arc::new(RwLock::new(inner)),
+            counters: Arc::new(StatsCounters::new()),
+            in_flight: Arc::new(SingleFlight::new()),
+            #[cfg(feature = "async")]
+            in_flight_async: Arc::new(AsyncSingleFlight::new()),
         }
     }
-}
 
-impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for LRUCache<K, V> {
-    /// Get a value from the cache.
-    fn get(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        let result = inner.key_value_map.get_refresh(key).cloned();
-        if result.is_some() {
-            inner.hits += 1;
+    /// Set a listener which is called with every item that leaves the cache and the reason it left, replacing any previous listener.
+    ///
+    /// The listener is called after the cache lock has been released, so it is free to use the cache. Items removed through an `Entry` are reported when the entry is consumed, an expired item found by `entry` and then left vacant is reported by the next operation on the cache.
+    ///
+    /// Example:
+    /// ```
+    /// use arcache::cache::eviction::EvictionReason;
+    /// use arcache::{Cache, LRUCache};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let cache = LRUCache::<&str, u64>::new(1);
+    /// let evicted = Arc::new(Mutex::new(Vec::new()));
+    /// let log = evicted.clone();
+    /// cache.on_evict(move |key, _, reason| log.lock().unwrap().push((*key, reason)));
+    ///
+    /// cache.set("a", 1);
+    /// cache.set("b", 2);
+    /// assert_eq!(*evicted.lock().unwrap(), vec![("a", EvictionReason::Capacity)]);
+    /// ```
+    pub fn on_evict<F>(&self, f: F)
+    where
+        F: Fn(&K, &Arc<V>, EvictionReason) + Send + Sync + 'static,
+    {
+        let mut inner = self.inner.write();
+        inner.eviction_queue.set_listener(Arc::new(f));
+    }
+
+    /// Subscribe to the items which leave the cache from now on through a channel, as an alternative to `on_evict` for consumers which process evictions on another thread or task.
+    ///
+    /// Each event is the key, the value and the reason it left. Events are sent without blocking while the cache is locked, so a slow consumer cannot stall the cache: the channel holds up to 1024 events which have not been received and further events are dropped until there is room. Dropping the receiver unsubscribes it, and several receivers can be subscribed at once.
+    pub fn eviction_events(&self) -> Receiver<EvictionEvent<K, V>> {
+        let mut inner = self.inner.write();
+        inner.eviction_queue.subscribe(EVICTION_EVENT_BOUND)
+    }
+
+    /// Run `f` with the cache locked, then pass any items which left the cache to the eviction listener once the lock has been released.
+    fn update<R>(&self, f: impl FnOnce(&mut LRUCacheInner<K, V, S>) -> R) -> R {
+        let mut inner = self.inner.write();
+        let result = f(&mut inner);
+        let evicted = inner.eviction_queue.take();
+        drop(inner);
+        evicted.notify();
+        result
+    }
+
+    /// Get a value from the cache, or compute it with `f`, insert it and return it if the key is missing.
+    ///
+    /// Unlike `get_or_insert_with`, concurrent callers which miss on the same key are deduplicated: `f` runs once and every caller receives the same Arc. The cache lock is not held while `f` runs so other keys can be read and written in the meantime.
+    pub fn get_or_compute<F: FnOnce() -> V>(&self, key: K, f: F) -> Arc<V> {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        self.in_flight.run(&key, || {
+            // another caller may have finished computing the value since the miss above
+            if let Some(value) = self.peek(&key) {
+                return value;
+            }
+            let value = Arc::new(f());
+            self.set_arc(key.clone(), value.clone());
+            value
+        })
+    }
+
+    /// Get a value from the cache, or await the future returned by `f`, insert its output and return it if the key is missing.
+    ///
+    /// Concurrent tasks which miss on the same key share a single computation: one task awaits its future and the others wait for its result without blocking their threads. The cache lock is never held across an await.
+    #[cfg(feature = "async")]
+    pub async fn get_or_insert_async<F, Fut>(&self, key: K, f: F) -> Arc<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = V>,
+    {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        self.in_flight_async
+            .run(&key, || async {
+                // another task may have finished computing the value since the miss above
+                if let Some(value) = self.peek(&key) {
+                    return value;
+                }
+                let value = Arc::new(f().await);
+                self.set_arc(key.clone(), value.clone());
+                value
+            })
+            .await
+    }
+
+    /// Get the entry for the given key for in-place manipulation.
+    ///
+    /// The cache stays locked for as long as the entry is alive, so a read-modify-write through the entry cannot race with other threads. An occupied entry counts as a hit and refreshes the item, a vacant entry counts as a miss. An expired item is removed and gives a vacant entry.
+    ///
+    /// Example:
+    /// ```
+    /// use arcache::cache::lru::Entry;
+    /// use arcache::{Cache, LRUCache};
+    ///
+    /// let cache = LRUCache::<&str, u64>::new(10);
+    /// for _ in 0..3 {
+    ///     cache.entry("counter").and_modify(|count| *count += 1).or_insert(1);
+    /// }
+    /// assert_eq!(*cache.get(&"counter").unwrap(), 3);
+    ///
+    /// if let Entry::Occupied(entry) = cache.entry("counter") {
+    ///     entry.or_replace(0);
+    /// }
+    /// assert_eq!(*cache.get(&"counter").unwrap(), 0);
+    /// ```
+    pub fn entry(&self, key: K) -> Entry<'_, K, V, S> {
+        let mut inner = self.inner.write();
+        if self.counters.record(inner.get(&key)).is_some() {
+            Entry::Occupied(OccupiedEntry { inner, key })
         } else {
-            inner.misses += 1;
+            Entry::Vacant(VacantEntry { inner, key })
         }
-        result
     }
 
-    /// Set a value in the cache.
-    fn set(&self, key: K, value: V) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
+    /// Reserve room for at least `additional` more items, for example before a bulk insert so the map is not rehashed while the cache warms up. The capacity of the cache is unchanged.
+    pub fn reserve(&self, additional: usize) {
+        let mut inner = self.inner.write();
+        inner.key_value_map.reserve(additional);
+    }
+
+    /// Release any memory the cache has allocated beyond what its current items need, for example after a large cache has been shrunk. The capacity of the cache is unchanged.
+    pub fn shrink_to_fit(&self) {
+        let mut inner = self.inner.write();
+        inner.key_value_map.shrink_to_fit();
+    }
+
+    /// Protect an item from being evicted to make room, regardless of its recency or how full the cache is, returning whether it is now pinned.
+    ///
+    /// Only items in the cache can be pinned and at most `capacity - 1` items can be pinned at once, so there is always an unpinned slot and a new item can always be admitted. A pinned item is still removed by `remove`, `clear` and `retain` and still expires, and replacing its value keeps it pinned. Lowering the capacity to the number of pinned items or below leaves the cache above its capacity until they are unpinned.
+    pub fn pin(&self, key: &K) -> bool {
+        let mut inner = self.inner.write();
+        let pinned = inner.pinned;
+        let limit = inner.capacity.saturating_sub(1);
+        match inner.key_value_map.get_mut(key) {
+            Some(entry) if entry.is_expired() => false,
+            Some(entry) if entry.pinned => true,
+            Some(_) if pinned >= limit => false,
+            Some(entry) => {
+                entry.pinned = true;
+                inner.pinned += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Allow a pinned item to be evicted again, it keeps its current position in the eviction order.
+    pub fn unpin(&self, key: &K) {
+        self.update(|inner| {
+            if let Some(entry) = inner.key_value_map.get_mut(key) {
+                if std::mem::take(&mut entry.pinned) {
+                    inner.pinned -= 1;
+                }
+            }
+            inner.enforce_capacity();
+        });
+    }
+
+    /// Get an exponentially weighted moving average of the hit ratio, which reflects recent lookups rather than the whole lifetime of the cache like `stats().hit_ratio()`, for example to alert on a sudden rise in misses. This is 0.0 if there have been no lookups.
+    pub fn recent_hit_ratio(&self) -> f64 {
+        self.counters.recent_hit_ratio()
+    }
+
+    /// Set the weight given to each new lookup in `recent_hit_ratio`, between 0.0 and 1.0. A larger decay reacts faster but is noisier, the default of 0.01 mostly reflects the last hundred or so lookups.
+    pub fn set_hit_ratio_decay(&self, decay: f64) {
+        self.counters.set_decay(decay);
+    }
+
+    /// Evict the least recently used items until there are at most `target_size`, returning how many were evicted, for example to release memory when the system is under pressure.
+    ///
+    /// Unlike `change_capacity` the capacity is left unchanged, so the cache can grow back afterwards. Evicted items count as evictions and are reported to the eviction listener.
+    pub fn shrink_to(&self, target_size: u64) -> u64 {
+        self.update(|inner| inner.shrink_to(target_size))
+    }
+
+    /// Remove every item from the cache under a single lock and return them, ordered from least to most recently used. Expired items are removed but not returned.
+    ///
+    /// Unlike `iter` followed by `clear`, no item set by another thread in between can be lost. The eviction listener is told about each item as if it had been removed.
+    pub fn drain(&self) -> Vec<(K, Arc<V>)> {
+        self.update(|inner| {
+            let mut items = Vec::with_capacity(inner.key_value_map.len());
+            while let Some((key, entry)) = inner.key_value_map.pop_front() {
+                if let Some(value) = inner.evicted(&key, entry, EvictionReason::Manual) {
+                    items.push((key, value));
+                }
+            }
+            items
+        })
+    }
+
+    /// Take an owned snapshot of the items in the cache in recency order, along with the time each has left before it expires, which can be restored into another cache with `restore`. Expired items are excluded.
+    ///
+    /// The values are shared as Arcs rather than cloned. Taking the snapshot does not affect the recency of the items or the cache statistics.
+    pub fn snapshot(&self) -> CacheSnapshot<K, V> {
+        let now = Instant::now();
+        let inner = self.inner.read();
+        CacheSnapshot {
+            capacity: inner.capacity,
+            ttl: inner.ttl,
+            expiry_policy: ExpiryPolicy::Fixed,
+            entries: inner
+                .key_value_map
+                .iter()
+                .filter(|(_, entry)| !entry.is_expired())
+                .map(|(key, entry)| SnapshotEntry {
+                    key: key.clone(),
+                    value: entry.data.clone(),
+                    remaining: entry
+                        .expiry
+                        .map(|expiry| expiry.saturating_duration_since(now)),
+                    ttl: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Replace the items in the cache with those from a snapshot, preserving their recency order, for example to move warm data into a cache with a different capacity.
+    ///
+    /// The capacity and TTL of this cache are kept. The expiry of each item is recomputed as now plus the time it had left when the snapshot was taken. An item without one gets the TTL of this cache, unless the snapshot has a TTL of its own, in which case the item was set without expiry and keeps none. The current items are reported to the eviction listener as removed, and if the snapshot holds more items than the capacity the least recently used are evicted.
+    pub fn restore(&self, snapshot: CacheSnapshot<K, V>) {
+        let now = Instant::now();
+        self.update(|inner| {
+            inner.clear();
+            // an item without expiry in a snapshot from a cache with a TTL was set to never expire
+            let default_ttl = if snapshot.ttl.is_none() {
+                inner.ttl
+            } else {
+                None
+            };
+            for entry in snapshot.entries {
+                inner.key_value_map.insert(
+                    entry.key,
+                    DataWithExpiry {
+                        data: entry.value,
+                        expiry: entry
+                            .remaining
+                            .or(default_ttl)
+                            .map(|remaining| now + remaining),
+                        pinned: false,
+                    },
+                );
+            }
+            inner.enforce_capacity();
+        });
+    }
+
+    /// Get a snapshot of the items in the cache, ordered from least to most recently used. Expired items are excluded.
+    ///
+    /// The items are collected under a single lock so the lock is not held while the snapshot is iterated. Taking the snapshot does not affect the recency of the items or the cache statistics.
+    pub fn iter(&self) -> Vec<(K, Arc<V>)> {
+        let inner = self.inner.read();
+        inner
+            .key_value_map
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired())
+            .map(|(key, entry)| (key.clone(), entry.data.clone()))
+            .collect()
+    }
+
+    /// Get a snapshot of the keys in the cache, ordered from least to most recently used. Expired items are excluded.
+    pub fn keys(&self) -> Vec<K> {
+        let inner = self.inner.read();
+        inner
+            .key_value_map
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired())
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Get a snapshot of the values in the cache, ordered from least to most recently used. Expired items are excluded.
+    pub fn values(&self) -> Vec<Arc<V>> {
+        let inner = self.inner.read();
+        inner
+            .key_value_map
+            .values()
+            .filter(|entry| !entry.is_expired())
+            .map(|entry| entry.data.clone())
+            .collect()
+    }
+
+    /// Get the key of the least recently used live item, which is the next to be evicted unless it is pinned, for example to log what is about to go under memory pressure. This does not affect the eviction order or the cache statistics.
+    pub fn oldest_key(&self) -> Option<K> {
+        let inner = self.inner.read();
+        inner
+            .key_value_map
+            .iter()
+            .find(|(_, entry)| !entry.is_expired())
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Get the key of the most recently used live item. This does not affect the eviction order or the cache statistics.
+    pub fn newest_key(&self) -> Option<K> {
+        let inner = self.inner.read();
+        inner
+            .key_value_map
+            .iter()
+            .rev()
+            .find(|(_, entry)| !entry.is_expired())
+            .map(|(key, _)| key.clone())
+    }
+}
+
+/// A view into a single entry of an LRUCache, which may either be occupied or vacant.
+///
+/// This is returned by `LRUCache::entry` and holds the cache lock until it is dropped.
+pub enum Entry<'a, K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S = RandomState> {
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+/// An entry for a key which is present in the cache.
+pub struct OccupiedEntry<'a, K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S = RandomState> {
+    inner: RwLockWriteGuard<'a, LRUCacheInner<K, V, S>>,
+    key: K,
+}
+
+/// An entry for a key which is not present in the cache.
+pub struct VacantEntry<'a, K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S = RandomState> {
+    inner: RwLockWriteGuard<'a, LRUCacheInner<K, V, S>>,
+    key: K,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S: BuildHasher + Send + Sync>
+    Entry<'_, K, V, S>
+{
+    /// Get the key of the entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Get the value of an occupied entry, or insert `default` into a vacant entry.
+    pub fn or_insert(self, default: V) -> Arc<V> {
+        match self {
+            Entry::Occupied(entry) => entry.get(),
+            Entry::Vacant(entry) => entry.insert(default),
+        }
+    }
+
+    /// Get the value of an occupied entry, or insert the result of `f` into a vacant entry.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> Arc<V> {
+        match self {
+            Entry::Occupied(entry) => entry.get(),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// Modify the value of an occupied entry in place, vacant entries are left untouched.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self
+    where
+        V: Clone,
+    {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S: BuildHasher + Send + Sync>
+    OccupiedEntry<'_, K, V, S>
+{
+    /// Get the key of the entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Get the value of the entry.
+    pub fn get(&self) -> Arc<V> {
+        self.inner.key_value_map[&self.key].data.clone()
+    }
+
+    /// Get a mutable reference to the value of the entry.
+    ///
+    /// This uses `Arc::make_mut`, so if the value is currently shared with a caller who holds an Arc from an earlier `get` the value is cloned first and that caller keeps seeing the old value.
+    pub fn get_mut(&mut self) -> &mut V
+    where
+        V: Clone,
+    {
+        let entry = self.inner.key_value_map.get_mut(&self.key).unwrap();
+        Arc::make_mut(&mut entry.data)
+    }
+
+    /// Replace the value of the entry, returning the previous value. Like `set` this restarts the lifetime of the item if the cache has a TTL.
+    pub fn or_replace(self, value: V) -> Arc<V> {
+        let OccupiedEntry { mut inner, key } = self;
+        let mut entry = inner.wrap(Arc::new(value));
+        // like set this only moves the item under the access order
+        let existing = inner.get_for_update(&key).unwrap();
+        entry.pinned = std::mem::take(&mut existing.pinned);
+        let previous = std::mem::replace(existing, entry);
+        inner
+            .eviction_queue
+            .push(&key, previous.data.clone(), EvictionReason::Replaced);
+        let evicted = inner.eviction_queue.take();
+        drop(inner);
+        evicted.notify();
+        previous.data
+    }
+
+    /// Remove the entry from the cache, returning its value.
+    pub fn remove(self) -> Arc<V> {
+        let OccupiedEntry { mut inner, key } = self;
+        let entry = inner.key_value_map.remove(&key).unwrap();
+        if entry.pinned {
+            inner.pinned -= 1;
+        }
+        inner
+            .eviction_queue
+            .push(&key, entry.data.clone(), EvictionReason::Manual);
+        let evicted = inner.eviction_queue.take();
+        drop(inner);
+        evicted.notify();
+        entry.data
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S: BuildHasher + Send + Sync>
+    VacantEntry<'_, K, V, S>
+{
+    /// Get the key of the entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Insert a value into the entry, evicting the least recently used item if the cache is full.
+    pub fn insert(self, value: V) -> Arc<V> {
+        let VacantEntry { mut inner, key } = self;
         let arc_value = Arc::new(value);
-        let result = inner.key_value_map.insert(key, arc_value);
-        if inner.key_value_map.len() as u64 > inner.capacity {
-            inner.key_value_map.pop_front();
+        if inner.capacity > 0 {
+            let entry = inner.wrap(arc_value.clone());
+            inner.key_value_map.insert(key, entry);
+            inner.enforce_capacity();
         }
-        result
+        let evicted = inner.eviction_queue.take();
+        drop(inner);
+        evicted.notify();
+        arc_value
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<
+        K: Eq + Hash + Clone + Sync + Send,
+        V: Send + Sync,
+        S: BuildHasher + Default + Send + Sync,
+    > LRUCache<K, V, S>
+{
+    /// Take a snapshot of the items in the cache in recency order, which can be serialized and later restored with `from_snapshot`. This is the same as `snapshot`.
+    pub fn to_snapshot(&self) -> CacheSnapshot<K, V> {
+        self.snapshot()
+    }
+
+    /// Create a new LRUCache from a snapshot, preserving the recency order of the items. The expiry of each item is recomputed as now plus the time it had left when the snapshot was taken.
+    pub fn from_snapshot(snapshot: CacheSnapshot<K, V>) -> Self {
+        let cache = Self::from_inner(LRUCacheInner::new(
+            snapshot.capacity,
+            snapshot.ttl,
+            S::default(),
+        ));
+        cache.restore(snapshot);
+        cache
+    }
+}
+
+#[cfg(feature = "persistence")]
+impl<
+        K: Eq + Hash + Clone + Sync + Send,
+        V: Send + Sync,
+        S: BuildHasher + Default + Send + Sync,
+    > LRUCache<K, V, S>
+{
+    /// Save a snapshot of the cache to a file which can be loaded with `load_from_path`, for example to keep a cache between runs of a program.
+    ///
+    /// The file starts with a magic header and a format version so files from an incompatible version of this crate are rejected on load.
+    pub fn save_to_path(&self, path: &Path) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        write_to_path(path, self.to_snapshot())
+    }
+
+    /// Load a cache from a file written by `save_to_path`, preserving the recency order of the items. Items which expired since the file was written are skipped.
+    pub fn load_from_path(path: &Path) -> io::Result<Self>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        Ok(Self::from_snapshot(read_from_path(path)?))
+    }
+}
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S: BuildHasher + Send + Sync> Cache<K, V>
+    for LRUCache<K, V, S>
+{
+    /// Get a value from the cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        self.counters.record(self.update(|inner| inner.get(key)))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        self.update(|inner| {
+            keys.iter()
+                .map(|key| self.counters.record(inner.get(key)))
+                .collect()
+        })
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics, expired items are not returned.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.read();
+        inner
+            .key_value_map
+            .get(key)
+            .filter(|entry| !entry.is_expired())
+            .map(|entry| entry.data.clone())
+    }
+
+    /// Check whether a key is in the cache without updating its recency or the cache statistics, expired items are reported as missing.
+    fn contains_key(&self, key: &K) -> bool {
+        let inner = self.inner.read();
+        inner
+            .key_value_map
+            .get(key)
+            .is_some_and(|entry| !entry.is_expired())
+    }
+
+    /// Set a value in the cache.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        self.update(|inner| inner.set(key, value))
+    }
+
+    /// Set a value in the cache and return whether it was stored, it is not stored if the admission filter rejects it or the capacity is zero.
+    fn try_set(&self, key: K, value: V) -> bool {
+        self.update(|inner| {
+            inner.set(key.clone(), Arc::new(value));
+            inner.key_value_map.contains_key(&key)
+        })
+    }
+
+    /// Set a value under a single lock if the key is missing or expired, returning the value which is in the cache afterwards.
+    fn put_if_absent(&self, key: K, value: V) -> Arc<V> {
+        self.update(|inner| {
+            if let Some(entry) = inner
+                .key_value_map
+                .get(&key)
+                .filter(|entry| !entry.is_expired())
+            {
+                return entry.data.clone();
+            }
+            let value = Arc::new(value);
+            inner.set(key, value.clone());
+            value
+        })
+    }
+
+    /// Replace the value of a key under a single lock if it is present and has not expired, returning the previous value.
+    fn replace(&self, key: &K, value: V) -> Option<Arc<V>> {
+        self.update(|inner| {
+            let present = inner
+                .key_value_map
+                .get(key)
+                .is_some_and(|entry| !entry.is_expired());
+            if !present {
+                return None;
+            }
+            inner.set(key.clone(), Arc::new(value))
+        })
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        self.update(|inner| {
+            items
+                .into_iter()
+                .map(|(key, value)| inner.set(key, Arc::new(value)))
+                .collect()
+        })
     }
 
-    /// Remove a value from the cache.
+    /// Remove a value from the cache, an expired item is removed but not returned.
     fn remove(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        inner.key_value_map.remove(key)
+        self.update(|inner| inner.remove(key, EvictionReason::Manual))
     }
 
     /// Clear the cache, removing all items.
     fn clear(&self) {
-        let mut inner = self.inner.lock().unwrap();
-        inner.key_value_map.clear();
+        self.update(|inner| inner.clear());
     }
 
-    /// Get the cache statistics.
+    /// Get the cache statistics, the size includes expired items which have not been removed yet unlike `len`.
     fn stats(&self) -> CacheStats {
-        let inner = self.inner.lock().unwrap();
+        let inner = self.inner.read();
         CacheStats {
-            hits: inner.hits,
-            misses: inner.misses,
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
             size: inner.key_value_map.len() as u64,
             capacity: inner.capacity,
         }
     }
 
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.write();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache. If the cache has a TTL expired items which have not been removed yet are not counted, which requires scanning every item.
+    fn len(&self) -> u64 {
+        let inner = self.inner.read();
+        if inner.ttl.is_none() {
+            return inner.key_value_map.len() as u64;
+        }
+        inner
+            .key_value_map
+            .values()
+            .filter(|entry| !entry.is_expired())
+            .count() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.read();
+        inner.capacity
+    }
+
     /// Change the capacity of the cache, if the new capacity is smaller than the current size, the least recently accessed items are removed
     fn change_capacity(&self, capacity: u64) {
-        let mut inner = self.inner.lock().unwrap();
-        let old_capacity = inner.capacity;
-        inner.capacity = capacity;
-        while inner.key_value_map.len() as u64 > inner.capacity {
-            inner.key_value_map.pop_front();
-        }
+        self.update(|inner| {
+            let old_capacity = inner.capacity;
+            inner.capacity = capacity;
+            inner.enforce_capacity();
 
-        if inner.capacity > old_capacity {
-            let additional = (inner.capacity - old_capacity) as usize;
-            inner.key_value_map.reserve(additional);
-        }
+            if inner.capacity > old_capacity {
+                let additional = (inner.capacity - old_capacity) as usize;
+                inner.key_value_map.reserve(additional);
+            }
+        });
+    }
+
+    /// Remove every item for which the predicate returns false, expired items are always removed.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        self.update(|inner| {
+            let removed = retain_linked_hash_map(&mut inner.key_value_map, |key, entry| {
+                !entry.is_expired() && f(key, &entry.data)
+            });
+            for (key, entry) in removed {
+                inner.evicted(&key, entry, EvictionReason::Manual);
+            }
+        });
+    }
+
+    /// Update a value in place without updating its recency or the cache statistics, an expired item is not updated.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.write();
+        let value = inner
+            .key_value_map
+            .get_mut(key)
+            .filter(|entry| !entry.is_expired())
+            .map(|entry| &mut entry.data);
+        update_in_place(value, f)
+    }
+
+    /// Swap the value of a live key if it equals `expected`, the current value is returned on failure from under the same lock.
+    fn compare_and_swap(&self, key: &K, expected: &V, new: V) -> Result<(), Option<Arc<V>>>
+    where
+        V: Clone + PartialEq,
+    {
+        let mut inner = self.inner.write();
+        let value = inner
+            .key_value_map
+            .get_mut(key)
+            .filter(|entry| !entry.is_expired())
+            .map(|entry| &mut entry.data);
+        swap_if_equal(value, expected, new)
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S: BuildHasher + Send + Sync> fmt::Debug
+    for LRUCache<K, V, S>
+{
+    /// Format the capacity, size and hit and miss counts of the cache, the items are not listed so neither keys nor values need to implement Debug.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = self.stats();
+        f.debug_struct("LRUCache")
+            .field("capacity", &stats.capacity)
+            .field("size", &stats.size)
+            .field("hits", &stats.hits)
+            .field("misses", &stats.misses)
+            .finish()
+    }
+}
+
+impl<
+        K: Eq + Hash + Clone + Sync + Send,
+        V: Send + Sync,
+        S: BuildHasher + Default + Send + Sync,
+    > Default for LRUCache<K, V, S>
+{
+    /// Create a new LRUCache with a capacity of 1000 items.
+    fn default() -> Self {
+        Self::with_hasher(DEFAULT_CAPACITY, S::default())
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> FromIterator<(K, V)> for LRUCache<K, V> {
+    /// Create a new LRUCache holding the items, with a capacity of the number of items. Use `from_iter_with_capacity` to choose the capacity.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let items: Vec<(K, V)> = iter.into_iter().collect();
+        let cache = Self::new(items.len() as u64);
+        cache.set_many(items);
+        cache
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S: BuildHasher + Send + Sync>
+    Extend<(K, V)> for LRUCache<K, V, S>
+{
+    /// Set every item under a single lock, evicting the least recently used items as needed to stay within the capacity.
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.set_many(iter.into_iter().collect());
     }
 }
 
@@ -181,4 +1116,854 @@ mod tests {
         cache.get(&4);
         assert_eq!(cache.stats().hits, 3);
     }
+
+    #[test]
+    fn test_lru_cache_get_or_insert_with() {
+        let cache = LRUCache::new(2);
+        let mut calls = 0;
+        let value = cache.get_or_insert_with(1, || {
+            calls += 1;
+            10
+        });
+        assert_eq!(*value, 10);
+        let value = cache.get_or_insert_with(1, || {
+            calls += 1;
+            20
+        });
+        assert_eq!(*value, 10);
+        assert_eq!(calls, 1);
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn test_lru_cache_get_or_try_insert_with() {
+        let cache = LRUCache::new(2);
+        assert_eq!(
+            cache.get_or_try_insert_with(1, || Err("offline")),
+            Err("offline")
+        );
+        assert!(cache.is_empty());
+        let value = cache.get_or_try_insert_with(1, || Ok::<_, &str>(10));
+        assert_eq!(value.map(|v| *v), Ok(10));
+        let value = cache.get_or_try_insert_with(1, || Err("offline"));
+        assert_eq!(value.map(|v| *v), Ok(10));
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[test]
+    fn test_lru_cache_get_or_compute_single_flight() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Barrier;
+        use std::thread;
+        use std::time::Duration;
+
+        let cache = Arc::new(LRUCache::new(10));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(16));
+        let handles: Vec<_> = (0..16)
+            .map(|_| {
+                let cache = cache.clone();
+                let calls = calls.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    cache.get_or_compute(1, || {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(100));
+                        42
+                    })
+                })
+            })
+            .collect();
+
+        let results: Vec<Arc<i32>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        for result in &results {
+            assert!(Arc::ptr_eq(result, &results[0]));
+        }
+        assert_eq!(cache.get(&1).map(|v| *v), Some(42));
+    }
+
+    #[test]
+    fn test_lru_cache_entry() {
+        let cache = LRUCache::new(2);
+        match cache.entry(1) {
+            Entry::Occupied(_) => panic!("expected a vacant entry"),
+            Entry::Vacant(entry) => assert_eq!(*entry.insert(10), 10),
+        }
+        match cache.entry(1) {
+            Entry::Occupied(entry) => {
+                assert_eq!(*entry.get(), 10);
+                assert_eq!(*entry.or_replace(20), 10);
+            }
+            Entry::Vacant(_) => panic!("expected an occupied entry"),
+        }
+        assert_eq!(cache.get(&1).map(|v| *v), Some(20));
+        assert_eq!(cache.stats().hits, 2);
+        assert_eq!(cache.stats().misses, 1);
+    }
+
+    #[test]
+    fn test_lru_cache_entry_concurrent_increments() {
+        use std::thread;
+
+        let cache = Arc::new(LRUCache::<&str, u64>::new(2));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = cache.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        cache.entry("count").and_modify(|c| *c += 1).or_insert(1);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(cache.get(&"count").map(|v| *v), Some(800));
+    }
+
+    #[test]
+    fn test_lru_cache_concurrent_readers() {
+        use std::sync::Barrier;
+        use std::thread;
+
+        let cache = Arc::new(LRUCache::new(2));
+        cache.set(1, 1);
+        let barrier = Arc::new(Barrier::new(2));
+        // hold a read lock while another reader runs, this would deadlock if peek took an exclusive lock
+        let guard = cache.inner.read();
+        let reader = {
+            let cache = cache.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                let value = cache.peek(&1).map(|v| *v);
+                barrier.wait();
+                value
+            })
+        };
+        barrier.wait();
+        drop(guard);
+        assert_eq!(reader.join().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_lru_cache_peek() {
+        let cache = LRUCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.peek(&3), None);
+        // peeking does not refresh 1 so it is still the least recently used item
+        cache.set(3, 3);
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.stats().hits, 0);
+        assert_eq!(cache.stats().misses, 0);
+    }
+
+    #[test]
+    fn test_lru_cache_contains_key() {
+        let cache = LRUCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&3));
+        cache.set(3, 3);
+        assert!(!cache.contains_key(&1));
+        assert_eq!(cache.stats().hits, 0);
+        assert_eq!(cache.stats().misses, 0);
+    }
+
+    #[test]
+    fn test_lru_cache_len() {
+        let cache = LRUCache::new(2);
+        assert!(cache.is_empty());
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.is_empty());
+    }
+
+    #[test]
+    fn test_lru_cache_iter() {
+        let cache = LRUCache::new(3);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        cache.get(&1);
+        let items: Vec<(i32, i32)> = cache.iter().into_iter().map(|(k, v)| (k, *v)).collect();
+        assert_eq!(items, vec![(2, 2), (3, 3), (1, 1)]);
+    }
+
+    #[test]
+    fn test_lru_cache_keys_and_values() {
+        let cache = LRUCache::new(3);
+        cache.set(1, 10);
+        cache.set(2, 20);
+        cache.get(&1);
+        assert_eq!(cache.keys(), vec![2, 1]);
+        let values: Vec<i32> = cache.values().into_iter().map(|v| *v).collect();
+        assert_eq!(values, vec![20, 10]);
+    }
+
+    #[test]
+    fn test_lru_cache_retain() {
+        let cache = LRUCache::new(10);
+        for i in 0..10 {
+            cache.set(i, i);
+        }
+        cache.retain(|key, _| key % 2 != 0);
+        assert_eq!(cache.stats().size, 5);
+        assert_eq!(cache.keys(), vec![1, 3, 5, 7, 9]);
+    }
+
+    #[test]
+    fn test_lru_cache_get_many() {
+        let cache = LRUCache::new(3);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        let values = cache.get_many(&[3, 4, 1]);
+        assert_eq!(
+            values
+                .into_iter()
+                .map(|v| v.map(|v| *v))
+                .collect::<Vec<_>>(),
+            vec![Some(3), None, Some(1)]
+        );
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 1);
+        // 2 was the only key not refreshed so it is evicted first
+        cache.set(4, 4);
+        assert_eq!(cache.peek(&2), None);
+    }
+
+    #[test]
+    fn test_lru_cache_set_many() {
+        let cache = LRUCache::new(3);
+        cache.set(1, 10);
+        let previous = cache.set_many(vec![(1, 1), (2, 2), (3, 3), (4, 4)]);
+        assert_eq!(
+            previous
+                .into_iter()
+                .map(|v| v.map(|v| *v))
+                .collect::<Vec<_>>(),
+            vec![Some(10), None, None, None]
+        );
+        assert_eq!(cache.keys(), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_lru_cache_with_ttl() {
+        use std::thread;
+
+        let cache = LRUCache::with_ttl(2, Duration::from_millis(50));
+        cache.set(1, 1);
+        thread::sleep(Duration::from_millis(30));
+        cache.set(2, 2);
+        // reading an item does not extend its lifetime
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        assert_eq!(cache.stats().misses, 1);
+        assert_eq!(cache.stats().size, 1);
+    }
+
+    #[test]
+    fn test_lru_cache_with_ttl_evicts_least_recently_used() {
+        let cache = LRUCache::with_ttl(2, Duration::from_secs(1));
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&1);
+        cache.set(3, 3);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+    }
+
+    #[test]
+    fn test_lru_cache_on_evict() {
+        use std::sync::Mutex;
+
+        let cache = Arc::new(LRUCache::new(2));
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        let handle = Arc::downgrade(&cache);
+        cache.on_evict(move |key, value, reason| {
+            // the listener is called without the lock held so it can use the cache
+            let len = handle.upgrade().unwrap().len();
+            log.lock().unwrap().push((*key, **value, reason, len));
+        });
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(1, 10);
+        cache.set(3, 3);
+        cache.remove(&1);
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            vec![
+                (1, 1, EvictionReason::Replaced, 2),
+                (2, 2, EvictionReason::Capacity, 2),
+                (1, 10, EvictionReason::Manual, 1),
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_lru_cache_snapshot() {
+        let cache = LRUCache::with_ttl(3, Duration::from_secs(60));
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        cache.get(&1);
+        let json = serde_json::to_string(&cache.to_snapshot()).unwrap();
+        let restored: LRUCache<i32, i32> =
+            LRUCache::from_snapshot(serde_json::from_str(&json).unwrap());
+        assert_eq!(restored.keys(), vec![2, 3, 1]);
+        assert_eq!(restored.stats().capacity, 3);
+        let inner = restored.inner.read();
+        assert!(inner
+            .key_value_map
+            .values()
+            .all(|entry| entry.expiry.is_some()));
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn test_lru_cache_save_and_load() {
+        let path = std::env::temp_dir().join(format!("arcache-lru-{}", std::process::id()));
+        let cache = LRUCache::new(3);
+        cache.set(1, "one".to_string());
+        cache.set(2, "two".to_string());
+        cache.get(&1);
+        cache.save_to_path(&path).unwrap();
+        let loaded = LRUCache::<i32, String>::load_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.keys(), vec![2, 1]);
+        assert_eq!(
+            loaded.peek(&1).map(|v| v.to_string()),
+            Some("one".to_string())
+        );
+    }
+
+    #[test]
+    fn test_lru_cache_hit_ratio() {
+        let cache = LRUCache::new(2);
+        assert_eq!(cache.stats().hit_ratio(), 0.0);
+        assert_eq!(cache.stats().miss_ratio(), 0.0);
+        cache.set(1, 1);
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&2);
+        assert_eq!(cache.stats().hit_ratio(), 0.75);
+        assert_eq!(cache.stats().miss_ratio(), 0.25);
+    }
+
+    #[test]
+    fn test_lru_cache_stats_display() {
+        let cache = LRUCache::new(4);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&3);
+        assert_eq!(
+            cache.stats().to_string(),
+            "hits=3 misses=1 size=2/4 hit_ratio=75.0%"
+        );
+    }
+
+    #[test]
+    fn test_lru_cache_counts_evictions() {
+        let cache = LRUCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(1, 10);
+        assert_eq!(cache.stats().evictions, 0);
+        cache.set(3, 3);
+        cache.remove(&1);
+        assert_eq!(cache.stats().evictions, 1);
+        cache.change_capacity(0);
+        assert_eq!(cache.stats().evictions, 2);
+    }
+
+    #[test]
+    fn test_lru_cache_reset_stats() {
+        let cache = LRUCache::new(1);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&1);
+        cache.get(&2);
+        cache.reset_stats();
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 0);
+        assert_eq!(stats.misses, 0);
+        assert_eq!(stats.evictions, 0);
+        assert_eq!(stats.size, 1);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_lru_cache_with_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        let cache = LRUCache::with_hasher(2, BuildHasherDefault::<DefaultHasher>::default());
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&1);
+        cache.set(3, 3);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.keys(), vec![3, 1]);
+    }
+
+    #[test]
+    fn test_lru_cache_admission_filter() {
+        let cache = LRUCache::with_admission_filter(2);
+        cache.set(1, 1);
+        // the first set is only recorded
+        assert_eq!(cache.get(&1), None);
+        cache.set(1, 1);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert!(!cache.try_set(2, 2));
+        assert!(cache.try_set(2, 2));
+    }
+
+    #[test]
+    fn test_lru_cache_admission_filter_scan_keeps_hot_key() {
+        let cache = LRUCache::with_admission_filter(100);
+        cache.set(0, 0);
+        cache.set(0, 0);
+        for key in 1..1000 {
+            assert_eq!(cache.get(&0).map(|v| *v), Some(0));
+            if cache.get(&key).is_none() {
+                cache.set(key, key);
+            }
+        }
+        assert_eq!(cache.get(&0).map(|v| *v), Some(0));
+        // keys seen once are not admitted, apart from rare false positives of the sketch
+        assert!(cache.len() < 10);
+    }
+
+    #[test]
+    fn test_lru_cache_compute() {
+        let cache = LRUCache::new(2);
+        cache.set(1, vec![1]);
+        let shared = cache.get(&1).unwrap();
+        assert!(cache.compute(&1, |value| value.push(2)));
+        assert!(!cache.compute(&2, |value| value.push(2)));
+        assert_eq!(*cache.get(&1).unwrap(), vec![1, 2]);
+        // a caller holding the old Arc keeps the old value
+        assert_eq!(*shared, vec![1]);
+    }
+
+    #[test]
+    fn test_lru_cache_zero_capacity_stores_nothing() {
+        let cache = LRUCache::new(0);
+        assert_eq!(cache.set(1, 1), None);
+        assert_eq!(cache.set(1, 2), None);
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.is_empty());
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.evictions, 0);
+    }
+
+    #[test]
+    fn test_lru_cache_drain() {
+        let cache = LRUCache::new(3);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        cache.get(&1);
+        let drained: Vec<_> = cache.drain().into_iter().map(|(k, v)| (k, *v)).collect();
+        assert_eq!(drained, vec![(2, 2), (3, 3), (1, 1)]);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_lru_cache_from_iter_and_extend() {
+        let mut cache: LRUCache<u64, u64> = (0..3).map(|key| (key, key * 10)).collect();
+        assert_eq!(cache.stats().capacity, 3);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(20));
+
+        cache.extend(vec![(3, 30), (4, 40)]);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(&0), None);
+        assert_eq!(cache.get(&4).map(|v| *v), Some(40));
+
+        let cache = LRUCache::from_iter_with_capacity(2, (0..5).map(|key| (key, key)));
+        assert_eq!(cache.keys(), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_lru_cache_capacity() {
+        let cache = LRUCache::new(4);
+        cache.set(1, 1);
+        assert_eq!(cache.capacity() - cache.len(), 3);
+        cache.change_capacity(2);
+        assert_eq!(cache.capacity(), 2);
+    }
+
+    #[test]
+    fn test_lru_cache_clone_shares_cache() {
+        let cache = LRUCache::new(10);
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    cache.set(i, i);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(cache.len(), 4);
+        let clone = cache.clone();
+        clone.get(&0);
+        clone.remove(&1);
+        assert_eq!(cache.stats().hits, 1);
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_lru_cache_put_if_absent() {
+        let cache = LRUCache::new(2);
+        assert_eq!(*cache.put_if_absent(1, 1), 1);
+        assert_eq!(*cache.put_if_absent(1, 10), 1);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.stats().hits, 1);
+
+        // a rejected value is still returned but not stored
+        let cache = LRUCache::new(0);
+        assert_eq!(*cache.put_if_absent(1, 1), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_lru_cache_replace() {
+        let cache = LRUCache::new(2);
+        assert_eq!(cache.replace(&1, 1), None);
+        assert!(cache.is_empty());
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.replace(&1, 10).map(|v| *v), Some(1));
+        // the replaced key became the most recently used
+        cache.set(3, 3);
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(10));
+        assert_eq!(cache.peek(&2), None);
+    }
+
+    #[test]
+    fn test_lru_cache_compare_and_swap() {
+        let cache = Arc::new(LRUCache::new(2));
+        cache.set(1, 0);
+        assert_eq!(cache.compare_and_swap(&1, &1, 2), Err(Some(Arc::new(0))));
+        assert_eq!(cache.compare_and_swap(&1, &0, 1), Ok(()));
+        assert_eq!(cache.compare_and_swap(&2, &0, 1), Err(None));
+
+        // concurrent writers retry until their increment is applied
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..100 {
+                        let mut current = *cache.peek(&1).unwrap();
+                        while let Err(Some(value)) =
+                            cache.compare_and_swap(&1, &current, current + 1)
+                        {
+                            current = *value;
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(401));
+    }
+
+    #[test]
+    fn test_lru_cache_default() {
+        let cache: LRUCache<u64, u64> = LRUCache::default();
+        assert_eq!(cache.capacity(), 1000);
+        cache.set(1, 1);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+    }
+
+    #[test]
+    fn test_lru_cache_debug() {
+        struct NotDebug;
+
+        let cache = LRUCache::new(10);
+        cache.set(1, NotDebug);
+        cache.get(&1);
+        cache.get(&2);
+        assert_eq!(
+            format!("{:?}", cache),
+            "LRUCache { capacity: 10, size: 1, hits: 1, misses: 1 }"
+        );
+    }
+
+    #[test]
+    fn test_lru_cache_shrink_to() {
+        let cache = LRUCache::new(4);
+        for i in 0..4 {
+            cache.set(i, i);
+        }
+        cache.get(&0);
+        assert_eq!(cache.shrink_to(2), 2);
+        assert_eq!(cache.keys(), vec![3, 0]);
+        assert_eq!(cache.shrink_to(2), 0);
+        assert_eq!(cache.capacity(), 4);
+        assert_eq!(cache.stats().evictions, 2);
+        // the capacity is unchanged so the cache grows back
+        cache.set(4, 4);
+        cache.set(5, 5);
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn test_lru_cache_reserve_and_shrink_to_fit() {
+        let cache = LRUCache::new(10);
+        cache.reserve(5_000);
+        assert!(cache.inner.read().key_value_map.capacity() >= 5_000);
+        cache.set_many((0..100).map(|i| (i, i)).collect());
+        cache.shrink_to_fit();
+        assert!(cache.inner.read().key_value_map.capacity() < 5_000);
+        assert_eq!(cache.len(), 10);
+        assert_eq!(cache.capacity(), 10);
+    }
+
+    #[test]
+    fn test_lru_cache_eviction_events() {
+        let cache = LRUCache::new(1);
+        let events = cache.eviction_events();
+        cache.set(1, 1);
+        cache.set(2, 2);
+        let (key, value, reason) = events.try_recv().unwrap();
+        assert_eq!((key, *value, reason), (1, 1, EvictionReason::Capacity));
+        assert!(events.try_recv().is_err());
+
+        // a consumer which falls behind misses events instead of blocking the cache
+        for i in 0..2000 {
+            cache.set(i, i);
+        }
+        assert_eq!(events.try_iter().count(), EVICTION_EVENT_BOUND);
+
+        drop(events);
+        cache.set(3, 3);
+        assert!(!cache.inner.read().eviction_queue.is_listening());
+    }
+
+    #[test]
+    fn test_lru_cache_survives_panicking_closure() {
+        let cache = LRUCache::new(2);
+        cache.set(1, 1);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cache.compute(&1, |_| panic!("compute failed"));
+        }));
+        assert!(result.is_err());
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        cache.set(2, 2);
+        cache.set(3, 3);
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_lru_cache_insertion_order() {
+        let cache = LRUCache::with_order(2, Order::Insertion);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        cache.set(1, 10);
+        cache.set(3, 3);
+        // 1 was inserted first so it is evicted despite being read and replaced
+        assert_eq!(cache.keys(), vec![2, 3]);
+
+        let cache = LRUCache::with_order(2, Order::Access);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&1);
+        cache.set(3, 3);
+        assert_eq!(cache.keys(), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_lru_cache_restore_into_smaller_cache() {
+        let cache = LRUCache::with_ttl(10, Duration::from_secs(60));
+        for i in 0..5 {
+            cache.set(i, i);
+        }
+        cache.get(&0);
+        let snapshot = cache.snapshot();
+        assert!(snapshot.entries[0].remaining.is_some());
+
+        let smaller = LRUCache::new(3);
+        smaller.set(100, 100);
+        smaller.restore(snapshot);
+        assert_eq!(smaller.capacity(), 3);
+        // the recency order is kept so the least recently used items are dropped
+        assert_eq!(smaller.keys(), vec![3, 4, 0]);
+        assert_eq!(smaller.peek(&100), None);
+        assert!(smaller.snapshot().entries[0].remaining.is_some());
+    }
+
+    #[test]
+    fn test_lru_cache_recent_hit_ratio() {
+        let cache = LRUCache::new(10);
+        assert_eq!(cache.recent_hit_ratio(), 0.0);
+        cache.set(1, 1);
+        for _ in 0..1000 {
+            cache.get(&1);
+        }
+        assert_eq!(cache.recent_hit_ratio(), 1.0);
+        cache.set_hit_ratio_decay(0.1);
+        for i in 0..50 {
+            cache.get(&(i + 100));
+        }
+        // the lifetime ratio barely moves but the recent ratio follows the misses
+        assert!(cache.stats().hit_ratio() > 0.9);
+        assert!(cache.recent_hit_ratio() < 0.01);
+        cache.reset_stats();
+        assert_eq!(cache.recent_hit_ratio(), 0.0);
+    }
+
+    #[test]
+    fn test_lru_cache_pinned_items_are_not_evicted() {
+        let cache = LRUCache::new(3);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        assert!(cache.pin(&1));
+        assert!(!cache.pin(&10));
+        for i in 4..10 {
+            cache.set(i, i);
+        }
+        // 1 is the least recently used item but it is pinned
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.len(), 3);
+        cache.set(1, 10);
+        assert!(cache.pin(&8));
+        // at most capacity - 1 items can be pinned so a new item can still be admitted
+        assert!(!cache.pin(&9));
+        cache.set(20, 20);
+        // the pinned items keep their positions, 9 is the oldest unpinned item
+        assert_eq!(cache.keys(), vec![8, 1, 20]);
+        cache.unpin(&1);
+        cache.set(21, 21);
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.peek(&21).map(|v| *v), Some(21));
+        // removing a pinned item frees its pin
+        cache.remove(&8);
+        assert!(cache.pin(&20));
+        assert!(cache.pin(&21));
+        cache.clear();
+        cache.set(1, 1);
+        assert!(cache.pin(&1));
+    }
+
+    #[test]
+    fn test_lru_cache_oldest_and_newest_key() {
+        let cache = LRUCache::new(3);
+        assert_eq!(cache.oldest_key(), None);
+        assert_eq!(cache.newest_key(), None);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        cache.get(&1);
+        assert_eq!(cache.oldest_key(), Some(2));
+        assert_eq!(cache.newest_key(), Some(1));
+        cache.set(4, 4);
+        assert_eq!(cache.oldest_key(), Some(3));
+        assert_eq!(cache.newest_key(), Some(4));
+    }
+
+    #[test]
+    fn test_lru_cache_insertion_order_entry_replace_keeps_position() {
+        let cache = LRUCache::with_order(2, Order::Insertion);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        match cache.entry(1) {
+            Entry::Occupied(entry) => assert_eq!(*entry.or_replace(10), 1),
+            Entry::Vacant(_) => panic!("1 should be in the cache"),
+        }
+        assert_eq!(cache.oldest_key(), Some(1));
+        cache.set(3, 3);
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.keys(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_lru_cache_insertion_order_pinned_items_keep_position() {
+        let cache = LRUCache::with_order(3, Order::Insertion);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert!(cache.pin(&1));
+        assert!(cache.pin(&2));
+        cache.set(3, 3);
+        cache.set(4, 4);
+        cache.set(5, 5);
+        assert_eq!(cache.keys(), vec![1, 2, 5]);
+        assert_eq!(cache.oldest_key(), Some(1));
+        assert_eq!(cache.newest_key(), Some(5));
+        cache.unpin(&1);
+        cache.set(6, 6);
+        assert_eq!(cache.keys(), vec![2, 5, 6]);
+    }
+
+    #[test]
+    fn test_lru_cache_restore_applies_ttl() {
+        use std::thread;
+
+        let cache = LRUCache::new(10);
+        cache.set(1, 1);
+        let restored = LRUCache::with_ttl(10, Duration::from_millis(50));
+        restored.restore(cache.snapshot());
+        assert_eq!(restored.peek(&1).map(|v| *v), Some(1));
+        thread::sleep(Duration::from_millis(80));
+        assert_eq!(restored.get(&1), None);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_lru_cache_get_or_insert_async() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let cache = Arc::new(LRUCache::<u64, u64>::new(10));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = cache.clone();
+                let calls = calls.clone();
+                tokio::spawn(async move {
+                    cache
+                        .get_or_insert_async(1, || async {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            42
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(*task.await.unwrap(), 42);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(42));
+    }
 }
@@ -0,0 +1,342 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::time::Duration;
+
+use crate::cache::ordered_map::OrderedMap;
+use crate::cache::sync::Mutex;
+use crate::cache::time::{Clock, Timestamp};
+use crate::cache::{update_in_place, Cache, CacheStats, StatsCounters};
+
+/// An internal struct of the LRU cache for storing data along with when it expires, `None` if the cache has no TTL.
+struct DataWithExpiry<V> {
+    data: Arc<V>,
+    expiry: Option<Timestamp>,
+}
+
+impl<V> DataWithExpiry<V> {
+    /// Check whether the item has expired at the given time.
+    fn is_expired(&self, now: Timestamp) -> bool {
+        self.expiry.is_some_and(|expiry| expiry <= now)
+    }
+}
+
+/// LRUCacheInner contains the inner data structure for the LRUCache, the items are ordered from least to most recently used.
+struct LRUCacheInner<K, V> {
+    capacity: u64,
+    ttl: Option<Duration>,
+    clock: Option<Arc<dyn Clock>>,
+    key_value_map: OrderedMap<K, DataWithExpiry<V>>,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> LRUCacheInner<K, V> {
+    /// Get the current time, only read for a cache with a TTL, which always has a clock.
+    fn now(&self) -> Timestamp {
+        match &self.clock {
+            Some(clock) => clock.now(),
+            None => Timestamp::ZERO,
+        }
+    }
+
+    /// Look up a key and make it the most recently used, an expired item is removed and treated as missing.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        let now = self.now();
+        let entry = self.key_value_map.get_refresh(key)?;
+        if !entry.is_expired(now) {
+            return Some(entry.data.clone());
+        }
+        self.key_value_map.remove(key);
+        self.evictions += 1;
+        None
+    }
+
+    /// Get the value of a live key without changing the order.
+    fn peek(&self, key: &K) -> Option<&DataWithExpiry<V>> {
+        let now = self.now();
+        self.key_value_map
+            .get(key)
+            .filter(|entry| !entry.is_expired(now))
+    }
+
+    /// Insert a value as the most recently used, evicting the least recently used items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let now = self.now();
+        let entry = DataWithExpiry {
+            data: value,
+            expiry: self.ttl.map(|ttl| now + ttl),
+        };
+        if !self.key_value_map.contains_key(&key) {
+            self.shrink_to(self.capacity - 1);
+        }
+        self.key_value_map
+            .insert(key, entry)
+            .filter(|previous| !previous.is_expired(now))
+            .map(|previous| previous.data)
+    }
+
+    /// Remove the least recently used items until at most `size` are left, counting each as an eviction.
+    fn shrink_to(&mut self, size: u64) {
+        while self.key_value_map.len() as u64 > size {
+            if self.key_value_map.pop_front().is_some() {
+                self.evictions += 1;
+            }
+        }
+    }
+}
+
+/// LRUCache is a least-recently-used cache implementation for builds without the standard library.
+///
+/// When the cache is full, the least recently used item is removed to make space for the new item. The items are kept in a hash map which remembers their order, so every operation other than `retain` and `len` takes constant time.
+///
+/// There is no system clock without the standard library, so a cache whose items expire is created with `with_ttl_and_clock` and a `Clock` reading a timer of the target. Expiry is lazy as there is no background thread: an expired item is treated as missing and removed when it is accessed, or evicted like any other item once it becomes the least recently used.
+///
+/// All mutability is handled internally with a spin lock, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, LRUCache};
+///
+/// let cache = LRUCache::<&str, u64>::new(2);
+///
+/// cache.set("a", 1);
+/// cache.set("b", 2);
+/// cache.get(&"a");
+/// cache.set("c", 3);
+///
+/// assert!(cache.get(&"b").is_none());
+/// assert_eq!(cache.keys(), vec!["a", "c"]);
+/// ```
+pub struct LRUCache<K: Eq + Hash + Clone + Send + Sync, V: Send + Sync> {
+    inner: Mutex<LRUCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Clone + Send + Sync, V: Send + Sync> LRUCache<K, V> {
+    /// Create a new LRUCache with the given capacity whose items never expire.
+    pub fn new(capacity: u64) -> Self {
+        Self::with_inner(capacity, None, None)
+    }
+
+    /// Create a new LRUCache with the given capacity where every item expires the given time-to-live (TTL) after it was set, as read from the clock.
+    pub fn with_ttl_and_clock(capacity: u64, ttl: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self::with_inner(capacity, Some(ttl), Some(clock))
+    }
+
+    /// Create a new LRUCache, internally capacity is reserved for the items.
+    fn with_inner(capacity: u64, ttl: Option<Duration>, clock: Option<Arc<dyn Clock>>) -> Self {
+        LRUCache {
+            inner: Mutex::new(LRUCacheInner {
+                capacity,
+                ttl,
+                clock,
+                key_value_map: OrderedMap::with_capacity(capacity as usize),
+                evictions: 0,
+            }),
+            counters: StatsCounters::new(),
+        }
+    }
+
+    /// Get the keys of the live items, ordered from least to most recently used.
+    pub fn keys(&self) -> Vec<K> {
+        let inner = self.inner.lock();
+        let now = inner.now();
+        inner
+            .key_value_map
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+impl<K: Eq + Hash + Clone + Send + Sync, V: Send + Sync> Cache<K, V> for LRUCache<K, V> {
+    /// Get a value from the cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics, expired items are not returned.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner.peek(key).map(|entry| entry.data.clone())
+    }
+
+    /// Check whether a key is in the cache without updating its recency or the cache statistics, expired items are reported as missing.
+    fn contains_key(&self, key: &K) -> bool {
+        let inner = self.inner.lock();
+        inner.peek(key).is_some()
+    }
+
+    /// Set a value in the cache.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Remove a value from the cache, an expired item is removed but not returned.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        let now = inner.now();
+        inner
+            .key_value_map
+            .remove(key)
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| entry.data)
+    }
+
+    /// Clear the cache, removing all items.
+    fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.key_value_map.clear();
+    }
+
+    /// Get the cache statistics, the size includes expired items which have not been removed yet unlike `len`.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.key_value_map.len() as u64,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache. If the cache has a TTL expired items which have not been removed yet are not counted, which requires scanning every item.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        if inner.ttl.is_none() {
+            return inner.key_value_map.len() as u64;
+        }
+        let now = inner.now();
+        inner
+            .key_value_map
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .count() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, if the new capacity is smaller than the current size, the least recently used items are removed.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity;
+        inner.shrink_to(capacity);
+    }
+
+    /// Remove every item for which the predicate returns false, expired items are always removed.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        let now = inner.now();
+        inner
+            .key_value_map
+            .retain(|key, entry| !entry.is_expired(now) && f(key, &entry.data));
+    }
+
+    /// Update a value in place without updating its recency or the cache statistics, an expired item is not updated.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        let now = inner.now();
+        let value = inner
+            .key_value_map
+            .get_mut(key)
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| &mut entry.data);
+        update_in_place(value, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::time::ManualClock;
+
+    #[test]
+    fn test_lru_cache_evicts_least_recently_used() {
+        let cache = LRUCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        cache.set(3, 3);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.keys(), vec![1, 3]);
+        assert_eq!(cache.set(1, 10).map(|v| *v), Some(1));
+        assert_eq!(cache.keys(), vec![3, 1]);
+        cache.change_capacity(1);
+        assert_eq!(cache.keys(), vec![1]);
+        assert_eq!(cache.stats().evictions, 2);
+    }
+
+    #[test]
+    fn test_lru_cache_zero_capacity_stores_nothing() {
+        let cache = LRUCache::new(0);
+        assert!(!cache.try_set(1, 1));
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_lru_cache_items_expire_lazily_with_the_clock() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = LRUCache::with_ttl_and_clock(3, Duration::from_secs(10), clock.clone());
+        cache.set(1, 1);
+        clock.advance(Duration::from_secs(5));
+        cache.set(2, 2);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.len(), 1);
+        // the expired item is only removed once it is accessed
+        assert_eq!(cache.stats().size, 2);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.stats().size, 1);
+        assert_eq!(cache.stats().evictions, 1);
+        assert_eq!(cache.keys(), vec![2]);
+        assert!(!cache.compute(&1, |v| *v += 1));
+        assert!(cache.compute(&2, |v| *v += 1));
+        assert_eq!(cache.get(&2).map(|v| *v), Some(3));
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.remove(&2), None);
+        assert!(cache.is_empty());
+    }
+}
@@ -1,14 +1,14 @@
-use crate::cache::{Cache, CacheStats};
+use crate::cache::sync::Mutex;
+use crate::cache::{retain_linked_hash_map, update_in_place, Cache, CacheStats, StatsCounters};
 use linked_hash_map::LinkedHashMap;
 use std::hash::Hash;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
 /// The inner data structure for the MRUCache.
 struct MRUCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
     capacity: u64,
     key_value_map: LinkedHashMap<K, Arc<V>>,
-    hits: u64,
-    misses: u64,
+    evictions: u64,
 }
 
 impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> MRUCacheInner<K, V> {
@@ -17,10 +17,27 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> MRUCacheInner<K, V> {
         MRUCacheInner {
             capacity,
             key_value_map: LinkedHashMap::with_capacity(capacity as usize),
-            hits: 0,
-            misses: 0,
+            evictions: 0,
         }
     }
+
+    /// Look up a key, updating the eviction order.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        self.key_value_map.get_refresh(key).cloned()
+    }
+
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        if self.key_value_map.len() as u64 + 1 > self.capacity
+            && self.key_value_map.pop_back().is_some()
+        {
+            self.evictions += 1;
+        }
+        self.key_value_map.insert(key, value)
+    }
 }
 
 /// MRUCache is a cache that uses the Most Recently Used (MRU) algorithm to evict items.
@@ -47,6 +64,7 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> MRUCacheInner<K, V> {
 /// ```
 pub struct MRUCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
     inner: Mutex<MRUCacheInner<K, V>>,
+    counters: StatsCounters,
 }
 
 impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> MRUCache<K, V> {
@@ -54,6 +72,7 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> MRUCache<K, V> {
     pub fn new(capacity: u64) -> Self {
         MRUCache {
             inner: Mutex::new(MRUCacheInner::new(capacity)),
+            counters: StatsCounters::new(),
         }
     }
 }
@@ -61,58 +80,90 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> MRUCache<K, V> {
 impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for MRUCache<K, V> {
     /// Get a value from the cache.
     fn get(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        let result = inner.key_value_map.get_refresh(key).cloned();
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
 
-        if result.is_some() {
-            inner.hits += 1;
-        } else {
-            inner.misses += 1;
-        }
-        result
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner.key_value_map.get(key).cloned()
     }
 
     /// Set a value in the cache.
-    fn set(&self, key: K, value: V) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        let arc_value = Arc::new(value);
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
 
-        if inner.key_value_map.len() as u64 + 1 > inner.capacity {
-            inner.key_value_map.pop_back();
-        }
-        inner.key_value_map.insert(key, arc_value)
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
     }
 
     /// Remove a value from the cache.
     fn remove(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         inner.key_value_map.remove(key)
     }
 
     /// Clear the cache, removing all items.
     fn clear(&self) {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         inner.key_value_map.clear();
     }
 
     /// Get the cache statistics.
     fn stats(&self) -> CacheStats {
-        let inner = self.inner.lock().unwrap();
+        let inner = self.inner.lock();
         CacheStats {
-            hits: inner.hits,
-            misses: inner.misses,
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
             size: inner.key_value_map.len() as u64,
             capacity: inner.capacity,
         }
     }
 
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.key_value_map.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
     /// Change the capacity of the cache, if the new capacity is less than the current capacity, the cache will evict the most recently used items until the size equals the new capacity.
     fn change_capacity(&self, capacity: u64) {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         let old_capacity = inner.capacity;
         inner.capacity = capacity;
         while inner.key_value_map.len() as u64 > inner.capacity {
             inner.key_value_map.pop_back();
+            inner.evictions += 1;
         }
 
         if inner.capacity > old_capacity {
@@ -120,6 +171,25 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for MRUCach
             inner.key_value_map.reserve(additional);
         }
     }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        retain_linked_hash_map(&mut inner.key_value_map, &mut f);
+    }
+
+    /// Update a value in place without updating its recency or the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        update_in_place(inner.key_value_map.get_mut(key), f)
+    }
 }
 
 #[cfg(test)]
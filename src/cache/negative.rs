@@ -0,0 +1,150 @@
+use crate::cache::ttl::TTLCache;
+use crate::cache::{Cache, CacheStats};
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// NegativeCache is a TTL cache which can also remember that a key has no value, so repeated lookups for missing keys do not reach the backend.
+///
+/// A key is either uncached, cached with a value, or cached as negative. Negative entries expire after their own `negative_ttl`, which is usually much shorter than the TTL of values so a key which starts to exist is picked up quickly.
+///
+/// `get` returns `None` for an uncached key, `Some(None)` for a negative entry and `Some(Some(value))` for a value.
+///
+/// Example:
+/// ```
+/// use arcache::NegativeCache;
+/// use std::time::Duration;
+///
+/// let cache = NegativeCache::<&str, String>::new(Duration::from_secs(60), Duration::from_secs(5), 100);
+///
+/// // the loader finds nothing, the absence is cached
+/// assert!(cache.get_or_insert_with("missing", || None).is_none());
+/// assert_eq!(cache.get(&"missing"), Some(None));
+///
+/// // the loader is not called again while the negative entry is live
+/// assert!(cache.get_or_insert_with("missing", || Some("found".to_string())).is_none());
+///
+/// cache.set("key", "value".to_string());
+/// assert_eq!(*cache.get(&"key").unwrap().unwrap(), "value".to_string());
+/// assert_eq!(cache.get(&"other"), None);
+/// ```
+pub struct NegativeCache<K: Eq + Hash + Clone + Send + Sync + 'static, V: Send + Sync + 'static> {
+    cache: TTLCache<K, Option<Arc<V>>>,
+    negative_ttl: Duration,
+}
+
+impl<K: Eq + Hash + Clone + Send + Sync + 'static, V: Send + Sync + 'static> NegativeCache<K, V> {
+    /// Create a new NegativeCache where values expire after `ttl` and negative entries expire after `negative_ttl`.
+    pub fn new(ttl: Duration, negative_ttl: Duration, capacity: u64) -> Self {
+        NegativeCache {
+            cache: TTLCache::new(ttl, capacity),
+            negative_ttl,
+        }
+    }
+
+    /// Look up a key, returning `None` if it is not cached, `Some(None)` if it is cached as negative and `Some(Some(value))` if it has a value.
+    pub fn get(&self, key: &K) -> Option<Option<Arc<V>>> {
+        self.cache.get(key).map(|entry| (*entry).clone())
+    }
+
+    /// Get the cached value or negative entry for a key, or call `f` and cache its result if the key is not cached. A `None` returned by `f` is cached as negative.
+    pub fn get_or_insert_with<F>(&self, key: K, f: F) -> Option<Arc<V>>
+    where
+        F: FnOnce() -> Option<V>,
+    {
+        if let Some(entry) = self.get(&key) {
+            return entry;
+        }
+        let value = f().map(Arc::new);
+        self.insert(key, value.clone());
+        value
+    }
+
+    /// Cache a value for a key.
+    pub fn set(&self, key: K, value: V) {
+        self.insert(key, Some(Arc::new(value)));
+    }
+
+    /// Cache that a key has no value, the negative entry expires after the negative TTL.
+    pub fn set_negative(&self, key: K) {
+        self.insert(key, None);
+    }
+
+    /// Cache a value or, for `None`, a negative entry.
+    fn insert(&self, key: K, value: Option<Arc<V>>) {
+        match value {
+            Some(value) => {
+                self.cache.set(key, Some(value));
+            }
+            None => {
+                self.cache.set_with_ttl(key, None, self.negative_ttl);
+            }
+        }
+    }
+
+    /// Remove a value or negative entry, returning what was cached.
+    pub fn remove(&self, key: &K) -> Option<Option<Arc<V>>> {
+        self.cache.remove(key).map(|entry| (*entry).clone())
+    }
+
+    /// Clear the cache, removing all values and negative entries.
+    pub fn clear(&self) {
+        self.cache.clear();
+    }
+
+    /// Get the cache statistics, a negative entry counts as a hit.
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Get the number of live values and negative entries.
+    pub fn len(&self) -> u64 {
+        self.cache.len()
+    }
+
+    /// Check whether the cache holds no values or negative entries.
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_negative_cache() {
+        let cache = NegativeCache::new(Duration::from_secs(10), Duration::from_secs(10), 10);
+        assert_eq!(cache.get(&1), None);
+        cache.set(1, 1);
+        cache.set_negative(2);
+        assert_eq!(cache.get(&1).map(|v| v.map(|v| *v)), Some(Some(1)));
+        assert_eq!(cache.get(&2), Some(None));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.remove(&2), Some(None));
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[test]
+    fn test_negative_cache_negative_ttl() {
+        let cache = NegativeCache::new(Duration::from_secs(10), Duration::from_millis(50), 10);
+        let mut calls = 0;
+        let mut load = |key: u64| {
+            cache.get_or_insert_with(key, || {
+                calls += 1;
+                None::<u64>
+            })
+        };
+        assert_eq!(load(1), None);
+        assert_eq!(load(1), None);
+        thread::sleep(Duration::from_millis(100));
+        // the negative entry has expired so the loader runs again
+        assert_eq!(load(1), None);
+        assert_eq!(calls, 2);
+
+        cache.set(2, 2);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(cache.get(&2).map(|v| v.map(|v| *v)), Some(Some(2)));
+    }
+}
@@ -0,0 +1,241 @@
+use crate::cache::HashMap;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::mem;
+
+/// The index used for a missing link.
+const NIL: usize = usize::MAX;
+
+/// An entry of an OrderedMap, linked to the entries before and after it.
+struct Node<K, V> {
+    key: K,
+    value: V,
+    prev: usize,
+    next: usize,
+}
+
+/// A hash map which remembers the order of its entries, used by the caches in place of `LinkedHashMap` when the crate is built without the standard library.
+///
+/// The entries are kept in a vector and linked into a list from the front, the oldest entry, to the back, the newest entry. The map holds the index of each key in the vector, and the slots of removed entries are reused by later insertions.
+pub(crate) struct OrderedMap<K, V> {
+    index: HashMap<K, usize>,
+    nodes: Vec<Option<Node<K, V>>>,
+    free: Vec<usize>,
+    front: usize,
+    back: usize,
+}
+
+impl<K: Eq + Hash + Clone, V> OrderedMap<K, V> {
+    /// Create a new empty OrderedMap with room for `capacity` entries.
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        OrderedMap {
+            index: HashMap::with_capacity(capacity),
+            nodes: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            front: NIL,
+            back: NIL,
+        }
+    }
+
+    /// Get the number of entries.
+    pub(crate) fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Check whether the map holds an entry for a key.
+    pub(crate) fn contains_key(&self, key: &K) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Get the node at an index which is known to be in use.
+    fn node(&self, index: usize) -> &Node<K, V> {
+        self.nodes[index].as_ref().unwrap()
+    }
+
+    /// Get the node at an index which is known to be in use for an update.
+    fn node_mut(&mut self, index: usize) -> &mut Node<K, V> {
+        self.nodes[index].as_mut().unwrap()
+    }
+
+    /// Get the value of a key without changing the order.
+    pub(crate) fn get(&self, key: &K) -> Option<&V> {
+        let index = *self.index.get(key)?;
+        Some(&self.node(index).value)
+    }
+
+    /// Get the value of a key for an update without changing the order.
+    pub(crate) fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let index = *self.index.get(key)?;
+        Some(&mut self.node_mut(index).value)
+    }
+
+    /// Get the value of a key for an update and move the entry to the back.
+    pub(crate) fn get_refresh(&mut self, key: &K) -> Option<&mut V> {
+        let index = *self.index.get(key)?;
+        self.unlink(index);
+        self.link_back(index);
+        Some(&mut self.node_mut(index).value)
+    }
+
+    /// Insert a value at the back, returning the previous value if the key was present. A replaced entry is moved to the back as well.
+    pub(crate) fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(&index) = self.index.get(&key) {
+            self.unlink(index);
+            self.link_back(index);
+            return Some(mem::replace(&mut self.node_mut(index).value, value));
+        }
+        let node = Node {
+            key: key.clone(),
+            value,
+            prev: NIL,
+            next: NIL,
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = Some(node);
+                index
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        self.index.insert(key, index);
+        self.link_back(index);
+        None
+    }
+
+    /// Remove the entry of a key, returning its value.
+    pub(crate) fn remove(&mut self, key: &K) -> Option<V> {
+        let index = self.index.remove(key)?;
+        Some(self.take(index).1)
+    }
+
+    /// Remove the entry at the front, returning its key and value.
+    pub(crate) fn pop_front(&mut self) -> Option<(K, V)> {
+        if self.front == NIL {
+            return None;
+        }
+        let (key, value) = self.take(self.front);
+        self.index.remove(&key);
+        Some((key, value))
+    }
+
+    /// Get the entry at the front.
+    pub(crate) fn front(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    /// Iterate over the entries from the front to the back.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let mut next = self.front;
+        core::iter::from_fn(move || {
+            if next == NIL {
+                return None;
+            }
+            let node = self.node(next);
+            next = node.next;
+            Some((&node.key, &node.value))
+        })
+    }
+
+    /// Remove every entry for which the predicate returns false, preserving the order of the remaining entries and returning the removed ones.
+    pub(crate) fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut f: F) -> Vec<(K, V)> {
+        let rejected: Vec<K> = self
+            .iter()
+            .filter(|(key, value)| !f(key, value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        rejected
+            .into_iter()
+            .filter_map(|key| self.remove(&key).map(|value| (key, value)))
+            .collect()
+    }
+
+    /// Remove every entry.
+    pub(crate) fn clear(&mut self) {
+        self.index.clear();
+        self.nodes.clear();
+        self.free.clear();
+        self.front = NIL;
+        self.back = NIL;
+    }
+
+    /// Take the node out of a slot which is in use, unlinking it and freeing the slot. The key is left in the index.
+    fn take(&mut self, index: usize) -> (K, V) {
+        self.unlink(index);
+        let node = self.nodes[index].take().unwrap();
+        self.free.push(index);
+        (node.key, node.value)
+    }
+
+    /// Unlink a node from the list, leaving it in its slot.
+    fn unlink(&mut self, index: usize) {
+        let (prev, next) = {
+            let node = self.node(index);
+            (node.prev, node.next)
+        };
+        if prev == NIL {
+            self.front = next;
+        } else {
+            self.node_mut(prev).next = next;
+        }
+        if next == NIL {
+            self.back = prev;
+        } else {
+            self.node_mut(next).prev = prev;
+        }
+    }
+
+    /// Link a node which is not in the list at the back.
+    fn link_back(&mut self, index: usize) {
+        let back = self.back;
+        {
+            let node = self.node_mut(index);
+            node.prev = back;
+            node.next = NIL;
+        }
+        if back == NIL {
+            self.front = index;
+        } else {
+            self.node_mut(back).next = index;
+        }
+        self.back = index;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(map: &OrderedMap<u64, u64>) -> Vec<u64> {
+        map.iter().map(|(key, _)| *key).collect()
+    }
+
+    #[test]
+    fn test_ordered_map_keeps_insertion_order_and_reuses_slots() {
+        let mut map = OrderedMap::with_capacity(4);
+        for i in 0..4 {
+            assert_eq!(map.insert(i, i), None);
+        }
+        assert_eq!(map.get_refresh(&1), Some(&mut 1));
+        assert_eq!(keys(&map), vec![0, 2, 3, 1]);
+        assert_eq!(map.insert(2, 20), Some(2));
+        assert_eq!(keys(&map), vec![0, 3, 1, 2]);
+        assert_eq!(map.pop_front(), Some((0, 0)));
+        assert_eq!(map.remove(&1), Some(1));
+        assert_eq!(map.remove(&1), None);
+        map.insert(4, 4);
+        map.insert(5, 5);
+        // the two freed slots are reused rather than growing the vector
+        assert_eq!(map.nodes.len(), 4);
+        assert_eq!(keys(&map), vec![3, 2, 4, 5]);
+        assert_eq!(map.front(), Some((&3, &3)));
+        assert_eq!(map.retain(|key, _| key % 2 == 0), vec![(3, 3), (5, 5)]);
+        assert_eq!(keys(&map), vec![2, 4]);
+        assert_eq!(map.get(&2), Some(&20));
+        map.clear();
+        assert_eq!(map.len(), 0);
+        assert_eq!(map.pop_front(), None);
+    }
+}
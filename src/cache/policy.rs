@@ -0,0 +1,397 @@
+use linked_hash_set::LinkedHashSet;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crate::cache::sync::Mutex;
+use crate::cache::{update_in_place, Cache, CacheStats, StatsCounters};
+
+/// An eviction strategy which decides which key a `PolicyCache` removes when it is full.
+///
+/// The policy only tracks keys, the cache owns the values and tells the policy about every key which is read, inserted or removed.
+pub trait EvictionPolicy<K>: Send {
+    /// Record a read of a key which is in the cache.
+    fn on_access(&mut self, key: &K);
+    /// Record a key which has just been added to the cache.
+    fn on_insert(&mut self, key: &K);
+    /// Record a key which has left the cache, including one returned by `victim`.
+    fn on_remove(&mut self, key: &K);
+    /// Forget every key.
+    fn clear(&mut self);
+    /// Choose the key to evict when the cache is full.
+    fn victim(&self) -> Option<K>;
+}
+
+/// LRUPolicy evicts the least recently used key.
+pub struct LRUPolicy<K: Eq + Hash> {
+    order: LinkedHashSet<K>,
+}
+
+impl<K: Eq + Hash> LRUPolicy<K> {
+    /// Create a new LRUPolicy.
+    pub fn new() -> Self {
+        LRUPolicy {
+            order: LinkedHashSet::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash> Default for LRUPolicy<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash + Clone + Send> EvictionPolicy<K> for LRUPolicy<K> {
+    fn on_access(&mut self, key: &K) {
+        self.order.refresh(key);
+    }
+
+    fn on_insert(&mut self, key: &K) {
+        self.order.insert(key.clone());
+    }
+
+    fn on_remove(&mut self, key: &K) {
+        self.order.remove(key);
+    }
+
+    fn clear(&mut self) {
+        self.order.clear();
+    }
+
+    fn victim(&self) -> Option<K> {
+        self.order.front().cloned()
+    }
+}
+
+/// FIFOPolicy evicts the key which was inserted first, reads do not change the order.
+pub struct FIFOPolicy<K: Eq + Hash> {
+    order: LinkedHashSet<K>,
+}
+
+impl<K: Eq + Hash> FIFOPolicy<K> {
+    /// Create a new FIFOPolicy.
+    pub fn new() -> Self {
+        FIFOPolicy {
+            order: LinkedHashSet::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash> Default for FIFOPolicy<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash + Clone + Send> EvictionPolicy<K> for FIFOPolicy<K> {
+    fn on_access(&mut self, _key: &K) {}
+
+    fn on_insert(&mut self, key: &K) {
+        self.order.insert(key.clone());
+    }
+
+    fn on_remove(&mut self, key: &K) {
+        self.order.remove(key);
+    }
+
+    fn clear(&mut self) {
+        self.order.clear();
+    }
+
+    fn victim(&self) -> Option<K> {
+        self.order.front().cloned()
+    }
+}
+
+/// PolicyCacheInner contains the inner data structure for the PolicyCache.
+struct PolicyCacheInner<K, V, P> {
+    capacity: u64,
+    key_value_map: HashMap<K, Arc<V>>,
+    policy: P,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone, V, P: EvictionPolicy<K>> PolicyCacheInner<K, V, P> {
+    /// Look up a key, telling the policy it was read.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        let value = self.key_value_map.get(key).cloned();
+        if value.is_some() {
+            self.policy.on_access(key);
+        }
+        value
+    }
+
+    /// Remove the victim chosen by the policy.
+    fn evict(&mut self) {
+        if let Some(victim) = self.policy.victim() {
+            self.policy.on_remove(&victim);
+            if self.key_value_map.remove(&victim).is_some() {
+                self.evictions += 1;
+            }
+        }
+    }
+
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value. Replacing a value counts as an access for the policy.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        if let Some(entry) = self.key_value_map.get_mut(&key) {
+            let previous = std::mem::replace(entry, value);
+            self.policy.on_access(&key);
+            return Some(previous);
+        }
+        while self.key_value_map.len() as u64 >= self.capacity {
+            let len = self.key_value_map.len();
+            self.evict();
+            // a policy which offers no victim, or one which is not cached, cannot make room
+            if self.key_value_map.len() == len {
+                break;
+            }
+        }
+        self.policy.on_insert(&key);
+        self.key_value_map.insert(key, value)
+    }
+}
+
+/// PolicyCache is a cache which stores the values itself and delegates the choice of what to evict to an `EvictionPolicy`.
+///
+/// New eviction strategies can be added by implementing `EvictionPolicy`, the cache provides the storage, statistics and the `Cache` trait. `LRUPolicy` and `FIFOPolicy` are provided, the dedicated `LRUCache` and `FIFOCache` are faster for those strategies.
+///
+/// A policy which offers no victim lets the cache grow past its capacity, so `victim` should return a key whenever the policy tracks one.
+///
+/// All mutability is handled internally with a Mutex, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, LRUPolicy, PolicyCache};
+///
+/// let cache = PolicyCache::<&str, String, _>::new(2, LRUPolicy::new());
+///
+/// cache.set("a", "1".to_string());
+/// cache.set("b", "2".to_string());
+/// cache.get(&"a");
+/// cache.set("c", "3".to_string());
+///
+/// assert!(cache.get(&"b").is_none());
+/// assert_eq!(*cache.get(&"a").unwrap(), "1".to_string());
+/// ```
+pub struct PolicyCache<K, V, P> {
+    inner: Mutex<PolicyCacheInner<K, V, P>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Clone + Send + Sync, V: Send + Sync, P: EvictionPolicy<K>>
+    PolicyCache<K, V, P>
+{
+    /// Create a new PolicyCache with the given capacity which evicts the keys chosen by `policy`.
+    pub fn new(capacity: u64, policy: P) -> Self {
+        PolicyCache {
+            inner: Mutex::new(PolicyCacheInner {
+                capacity,
+                key_value_map: HashMap::with_capacity(capacity as usize),
+                policy,
+                evictions: 0,
+            }),
+            counters: StatsCounters::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone + Send + Sync, V: Send + Sync, P: EvictionPolicy<K>> Cache<K, V>
+    for PolicyCache<K, V, P>
+{
+    /// Get a value from the cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without telling the policy or updating the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner.key_value_map.get(key).cloned()
+    }
+
+    /// Set a value in the cache.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        let result = inner.key_value_map.remove(key);
+        if result.is_some() {
+            inner.policy.on_remove(key);
+        }
+        result
+    }
+
+    /// Clear the cache.
+    fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.key_value_map.clear();
+        inner.policy.clear();
+    }
+
+    /// Get cache statistics.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.key_value_map.len() as u64,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.key_value_map.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, if the new capacity is smaller than the current size, the victims chosen by the policy are removed.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity;
+        while inner.key_value_map.len() as u64 > inner.capacity {
+            let len = inner.key_value_map.len();
+            inner.evict();
+            if inner.key_value_map.len() == len {
+                break;
+            }
+        }
+    }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        let PolicyCacheInner {
+            key_value_map,
+            policy,
+            ..
+        } = &mut *inner;
+        key_value_map.retain(|key, value| {
+            let keep = f(key, value);
+            if !keep {
+                policy.on_remove(key);
+            }
+            keep
+        });
+    }
+
+    /// Update a value in place without telling the policy or updating the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        update_in_place(inner.key_value_map.get_mut(key), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_cache_lru() {
+        let cache = PolicyCache::new(2, LRUPolicy::new());
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&1);
+        cache.set(3, 3);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_policy_cache_fifo() {
+        let cache = PolicyCache::new(2, FIFOPolicy::new());
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&1);
+        cache.set(1, 10);
+        cache.set(3, 3);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+    }
+
+    #[test]
+    fn test_policy_cache_remove_and_change_capacity() {
+        let cache = PolicyCache::new(3, LRUPolicy::new());
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        cache.remove(&1);
+        cache.retain(|key, _| *key != 2);
+        cache.set(4, 4);
+        cache.set(5, 5);
+        cache.change_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&5).map(|v| *v), Some(5));
+    }
+
+    /// A policy which never offers a victim.
+    struct KeepAll;
+
+    impl EvictionPolicy<u64> for KeepAll {
+        fn on_access(&mut self, _key: &u64) {}
+        fn on_insert(&mut self, _key: &u64) {}
+        fn on_remove(&mut self, _key: &u64) {}
+        fn clear(&mut self) {}
+        fn victim(&self) -> Option<u64> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_policy_cache_custom_policy() {
+        let cache = PolicyCache::new(1, KeepAll);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.stats().evictions, 0);
+    }
+}
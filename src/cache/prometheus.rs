@@ -0,0 +1,181 @@
+use crate::cache::{Cache, CacheStats};
+use ::prometheus::core::{Collector, Desc, Describer};
+use ::prometheus::proto::MetricFamily;
+use ::prometheus::{IntCounter, IntGauge, Opts, Registry};
+use std::hash::Hash;
+use std::sync::{Arc, Weak};
+
+/// The metrics published for each cache, as the metric name, its help text and whether it is a counter.
+const METRICS: [(&str, &str, bool); 5] = [
+    (
+        "arcache_hits_total",
+        "Number of cache lookups which found a value.",
+        true,
+    ),
+    (
+        "arcache_misses_total",
+        "Number of cache lookups which found no value.",
+        true,
+    ),
+    (
+        "arcache_evictions_total",
+        "Number of items removed by the cache to respect its capacity or expiry.",
+        true,
+    ),
+    ("arcache_size", "Number of items in the cache.", false),
+    (
+        "arcache_capacity",
+        "Maximum number of items in the cache.",
+        false,
+    ),
+];
+
+/// Reads the statistics of a cache which may have been dropped.
+type StatsSource = Box<dyn Fn() -> Option<CacheStats> + Send + Sync>;
+
+/// A Prometheus collector which reads the statistics of a cache on each scrape.
+struct CacheCollector {
+    name: String,
+    stats: StatsSource,
+    descs: Vec<Desc>,
+}
+
+impl CacheCollector {
+    /// Create a collector for a cache, the name is published as the `cache` label of every metric.
+    fn new(name: &str, stats: StatsSource) -> ::prometheus::Result<Self> {
+        let mut descs = Vec::with_capacity(METRICS.len());
+        for (metric, help, _) in METRICS {
+            descs.push(Self::opts(name, metric, help).describe()?);
+        }
+        Ok(CacheCollector {
+            name: name.to_string(),
+            stats,
+            descs,
+        })
+    }
+
+    /// Build the options of a metric for the cache with the given name.
+    fn opts(name: &str, metric: &str, help: &str) -> Opts {
+        Opts::new(metric, help).const_label("cache", name)
+    }
+}
+
+impl Collector for CacheCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    /// Snapshot the cache statistics, a cache which has been dropped publishes no metrics.
+    fn collect(&self) -> Vec<MetricFamily> {
+        let Some(stats) = (self.stats)() else {
+            return Vec::new();
+        };
+        let values = [
+            stats.hits,
+            stats.misses,
+            stats.evictions,
+            stats.size,
+            stats.capacity,
+        ];
+        // the options were validated when the collector was created
+        METRICS
+            .iter()
+            .zip(values)
+            .flat_map(|((metric, help, counter), value)| {
+                let opts = Self::opts(&self.name, metric, help);
+                if *counter {
+                    let metric = IntCounter::with_opts(opts).unwrap();
+                    metric.inc_by(value);
+                    metric.collect()
+                } else {
+                    let metric = IntGauge::with_opts(opts).unwrap();
+                    metric.set(value as i64);
+                    metric.collect()
+                }
+            })
+            .collect()
+    }
+}
+
+/// Publish the statistics of a cache to the default Prometheus registry under the given name.
+///
+/// Hits, misses and evictions are published as counters and the size and capacity as gauges, all labelled with `cache="<name>"` so several caches can be registered. The statistics are read from the cache on every scrape. The registry only holds a weak reference, so registering does not keep the cache alive and a dropped cache stops publishing metrics.
+///
+/// Example:
+/// ```
+/// use arcache::cache::prometheus::register_cache;
+/// use arcache::{Cache, LRUCache};
+/// use std::sync::Arc;
+///
+/// let cache = Arc::new(LRUCache::<&str, String>::new(10));
+/// register_cache("sessions", &cache).unwrap();
+///
+/// cache.get(&"key");
+/// let families = prometheus::gather();
+/// assert!(families.iter().any(|family| family.name() == "arcache_misses_total"));
+/// ```
+pub fn register_cache<K, V, C>(name: &str, cache: &Arc<C>) -> ::prometheus::Result<()>
+where
+    K: Eq + Hash + Clone + Send + Sync,
+    V: Send + Sync,
+    C: Cache<K, V> + 'static,
+{
+    register_cache_with_registry(::prometheus::default_registry(), name, cache)
+}
+
+/// Publish the statistics of a cache to the given Prometheus registry under the given name, see `register_cache`.
+pub fn register_cache_with_registry<K, V, C>(
+    registry: &Registry,
+    name: &str,
+    cache: &Arc<C>,
+) -> ::prometheus::Result<()>
+where
+    K: Eq + Hash + Clone + Send + Sync,
+    V: Send + Sync,
+    C: Cache<K, V> + 'static,
+{
+    let cache: Weak<C> = Arc::downgrade(cache);
+    let stats = Box::new(move || cache.upgrade().map(|cache| cache.stats()));
+    registry.register(Box::new(CacheCollector::new(name, stats)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::lru::LRUCache;
+    use ::prometheus::{Encoder, TextEncoder};
+
+    /// Render the metrics of a registry in the Prometheus text format.
+    fn scrape(registry: &Registry) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_register_cache() {
+        let registry = Registry::new();
+        let cache = Arc::new(LRUCache::new(1));
+        register_cache_with_registry(&registry, "users", &cache).unwrap();
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&2);
+        cache.get(&1);
+
+        let output = scrape(&registry);
+        assert!(output.contains("arcache_hits_total{cache=\"users\"} 1"));
+        assert!(output.contains("arcache_misses_total{cache=\"users\"} 1"));
+        assert!(output.contains("arcache_evictions_total{cache=\"users\"} 1"));
+        assert!(output.contains("arcache_size{cache=\"users\"} 1"));
+        assert!(output.contains("arcache_capacity{cache=\"users\"} 1"));
+
+        // the same name cannot be registered twice, but other caches can be
+        assert!(register_cache_with_registry(&registry, "users", &cache).is_err());
+        register_cache_with_registry(&registry, "orders", &cache).unwrap();
+
+        drop(cache);
+        assert!(scrape(&registry).is_empty());
+    }
+}
@@ -1,35 +1,70 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::collections::HashMap;
 use std::hash::Hash;
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 
-use crate::cache::{Cache, CacheStats};
+use crate::cache::sync::Mutex;
+use crate::cache::{update_in_place, Cache, CacheStats, StatsCounters};
 
 /// RandomReplacementCacheInner contains the inner data structure for the RandomReplacementCache.
 struct RandomReplacementCacheInner<K: Eq + Hash + Send, V: Send + Sync> {
     capacity: u64,
     key_value_map: HashMap<K, Arc<V>>,
     keys: Vec<K>,
-    hits: u64,
-    misses: u64,
+    rng: StdRng,
+    evictions: u64,
 }
 
 impl<K: Eq + Hash + Send, V: Send + Sync> RandomReplacementCacheInner<K, V> {
-    /// Create a new RandomReplacementCacheInner with the given capacity, internally capacity is reserved for the necessary data structures.
-    fn new(capacity: u64) -> Self {
+    /// Create a new RandomReplacementCacheInner with the given capacity and random number generator, internally capacity is reserved for the necessary data structures.
+    fn new(capacity: u64, rng: StdRng) -> Self {
         RandomReplacementCacheInner {
             capacity,
             key_value_map: HashMap::with_capacity(capacity as usize),
             keys: Vec::with_capacity(capacity as usize),
-            hits: 0,
-            misses: 0,
+            rng,
+            evictions: 0,
         }
     }
+
+    /// Evict a uniformly random item from the cache.
+    fn evict_random(&mut self) {
+        if self.keys.is_empty() {
+            return;
+        }
+        let index = self.rng.random_range(0..self.keys.len());
+        let removed_key = self.keys.swap_remove(index);
+        self.key_value_map.remove(&removed_key);
+        self.evictions += 1;
+    }
+
+    /// Look up a key.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        self.key_value_map.get(key).cloned()
+    }
+}
+
+impl<K: Eq + Hash + Clone + Send, V: Send + Sync> RandomReplacementCacheInner<K, V> {
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        if let Some(entry) = self.key_value_map.get_mut(&key) {
+            return Some(std::mem::replace(entry, value));
+        }
+        if self.key_value_map.len() as u64 >= self.capacity {
+            self.evict_random();
+        }
+        self.keys.push(key.clone());
+        self.key_value_map.insert(key, value)
+    }
 }
 
 /// RandomReplacementCache is a cache which evicts items randomly.
 ///
-/// When the cache is full, a random item is removed to make space for the new item.
+/// When the cache is full, a uniformly random item is removed to make space for the new item. Use `with_seed` to make the eviction order reproducible, which is useful for tests and benchmarking baselines.
 ///
 /// All mutability is handled internally with a Mutex, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
 ///
@@ -51,15 +86,37 @@ impl<K: Eq + Hash + Send, V: Send + Sync> RandomReplacementCacheInner<K, V> {
 /// ```
 pub struct RandomReplacementCache<K: Eq + Hash + Send, V: Send + Sync> {
     inner: Mutex<RandomReplacementCacheInner<K, V>>,
+    counters: StatsCounters,
 }
 
 impl<K: Eq + Hash + Sync + Send, V: Send + Sync> RandomReplacementCache<K, V> {
     /// Create a new RandomReplacementCache with the given capacity.
     pub fn new(capacity: u64) -> Self {
         RandomReplacementCache {
-            inner: Mutex::new(RandomReplacementCacheInner::new(capacity)),
+            inner: Mutex::new(RandomReplacementCacheInner::new(
+                capacity,
+                StdRng::from_os_rng(),
+            )),
+            counters: StatsCounters::new(),
         }
     }
+
+    /// Create a new RandomReplacementCache with the given capacity and a seed for the random number generator, caches created with the same seed evict the same items given the same sequence of operations.
+    pub fn with_seed(capacity: u64, seed: u64) -> Self {
+        RandomReplacementCache {
+            inner: Mutex::new(RandomReplacementCacheInner::new(
+                capacity,
+                StdRng::seed_from_u64(seed),
+            )),
+            counters: StatsCounters::new(),
+        }
+    }
+
+    /// Get the number of items which have been evicted to make space for new items, this is the same as the evictions in the cache statistics.
+    pub fn evictions(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.evictions
+    }
 }
 
 impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V>
@@ -67,33 +124,42 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V>
 {
     /// Get a value from the cache.
     fn get(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        let result = inner.key_value_map.get(key).cloned();
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
 
-        if result.is_some() {
-            inner.hits += 1;
-        } else {
-            inner.misses += 1;
-        }
-        result
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner.key_value_map.get(key).cloned()
     }
 
     /// Set a value in the cache.
-    fn set(&self, key: K, value: V) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        if inner.key_value_map.len() as u64 >= inner.capacity {
-            let index = rand::rng().random_range(0..inner.keys.len());
-            let removed_key = inner.keys.swap_remove(index);
-            inner.key_value_map.remove(&removed_key);
-        }
-        let arc_value = Arc::new(value);
-        inner.keys.push(key.clone());
-        inner.key_value_map.insert(key, arc_value)
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
     }
 
     /// Remove a value from the cache.
     fn remove(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         let result = inner.key_value_map.remove(key);
         if let Some(pos) = inner.keys.iter().position(|k| k == key) {
             inner.keys.remove(pos);
@@ -103,31 +169,49 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V>
 
     /// Clear the cache.
     fn clear(&self) {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         inner.key_value_map.clear();
         inner.keys.clear();
     }
 
     /// Get cache statistics.
     fn stats(&self) -> CacheStats {
-        let inner = self.inner.lock().unwrap();
+        let inner = self.inner.lock();
         CacheStats {
-            hits: inner.hits,
-            misses: inner.misses,
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
             size: inner.key_value_map.len() as u64,
             capacity: inner.capacity,
         }
     }
 
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.key_value_map.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
     /// Change the capacity of the cache, if the new capacity is smaller than the current size, the oldest items are removed.
     fn change_capacity(&self, capacity: u64) {
-        let mut inner = self.inner.lock().unwrap();
+        let mut inner = self.inner.lock();
         let old_capacity = inner.capacity;
         inner.capacity = capacity;
         while inner.key_value_map.len() as u64 > inner.capacity {
-            let index = rand::rng().random_range(0..inner.keys.len());
-            let removed_key = inner.keys.swap_remove(index);
-            inner.key_value_map.remove(&removed_key);
+            inner.evict_random();
         }
 
         if inner.capacity > old_capacity {
@@ -135,6 +219,31 @@ impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V>
             inner.key_value_map.reserve(additional);
         }
     }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        inner.key_value_map.retain(|key, value| f(key, value));
+        let RandomReplacementCacheInner {
+            key_value_map,
+            keys,
+            ..
+        } = &mut *inner;
+        keys.retain(|key| key_value_map.contains_key(key));
+    }
+
+    /// Update a value in place without updating the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        update_in_place(inner.key_value_map.get_mut(key), f)
+    }
 }
 
 #[cfg(test)]
@@ -172,4 +281,43 @@ mod tests {
         cache.change_capacity(1);
         assert!(cache.get(&1).is_none() || cache.get(&2).is_none());
     }
+
+    #[test]
+    fn test_random_replacement_cache_with_seed() {
+        let first = RandomReplacementCache::with_seed(5, 42);
+        let second = RandomReplacementCache::with_seed(5, 42);
+        for i in 0..20 {
+            first.set(i, i);
+            second.set(i, i);
+        }
+        for i in 0..20 {
+            assert_eq!(first.get(&i), second.get(&i));
+        }
+        assert_eq!(first.evictions(), 15);
+        assert_eq!(second.evictions(), 15);
+        assert_eq!(first.stats().evictions, 15);
+    }
+
+    #[test]
+    fn test_random_replacement_cache_overwrite_does_not_evict() {
+        let cache = RandomReplacementCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.set(1, 10).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(10));
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        assert_eq!(cache.evictions(), 0);
+    }
+
+    #[test]
+    fn test_random_replacement_cache_zero_capacity_stores_nothing() {
+        let cache = RandomReplacementCache::new(0);
+        assert_eq!(cache.set(1, 1), None);
+        assert_eq!(cache.set(1, 2), None);
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.is_empty());
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.evictions, 0);
+    }
 }
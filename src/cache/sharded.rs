@@ -0,0 +1,301 @@
+use crate::cache::{Cache, CacheStats};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+
+/// ShardedCache splits its items across several independent caches to reduce lock contention.
+///
+/// Each key is routed to one shard by its hash, so operations on keys in different shards take different locks and can run in parallel. Any cache implementing the `Cache` trait can be used as a shard. Eviction happens independently within each shard, so the cache as a whole only approximates the eviction policy of its shards.
+///
+/// The capacity is divided evenly between the shards, rounding up, and the statistics are summed across all shards. Use `shard_stats` to see the statistics of each shard, for example to find a hot shard when keys hash unevenly.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, LRUCache, ShardedCache};
+///
+/// let cache = ShardedCache::new(4, 100, LRUCache::<&str, String>::new);
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct ShardedCache<C> {
+    shards: Vec<C>,
+    hasher: RandomState,
+}
+
+impl<C> ShardedCache<C> {
+    /// Create a new ShardedCache with the given number of shards and total capacity, `make_shard` is called with the capacity of each shard to create it.
+    ///
+    /// Panics if `shard_count` is zero.
+    pub fn new<F: FnMut(u64) -> C>(shard_count: usize, capacity: u64, make_shard: F) -> Self {
+        assert!(shard_count > 0, "a ShardedCache needs at least one shard");
+        let shard_capacity = capacity.div_ceil(shard_count as u64);
+        Self::with_shards(
+            std::iter::repeat_n(shard_capacity, shard_count)
+                .map(make_shard)
+                .collect(),
+        )
+    }
+
+    /// Create a new ShardedCache from already constructed shards.
+    ///
+    /// Panics if `shards` is empty.
+    pub fn with_shards(shards: Vec<C>) -> Self {
+        assert!(
+            !shards.is_empty(),
+            "a ShardedCache needs at least one shard"
+        );
+        ShardedCache {
+            shards,
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Get the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Get the shard which is responsible for the given key.
+    fn shard<K: Hash>(&self, key: &K) -> &C {
+        let index = self.hasher.hash_one(key) % self.shards.len() as u64;
+        &self.shards[index as usize]
+    }
+}
+
+impl<C> ShardedCache<C> {
+    /// Get the statistics of each shard in shard order, the statistics from `stats` are their sum.
+    pub fn shard_stats<K, V>(&self) -> Vec<CacheStats>
+    where
+        K: Eq + Hash + Clone + Send + Sync,
+        V: Send + Sync,
+        C: Cache<K, V>,
+    {
+        self.shards.iter().map(|shard| shard.stats()).collect()
+    }
+}
+
+impl<K, V, C> Cache<K, V> for ShardedCache<C>
+where
+    K: Eq + Hash + Clone + Send + Sync,
+    V: Send + Sync,
+    C: Cache<K, V>,
+{
+    /// Get a value from the shard responsible for the key.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        self.shard(key).get(key)
+    }
+
+    /// Peek at a value in the shard responsible for the key without updating its eviction order or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        self.shard(key).peek(key)
+    }
+
+    /// Check whether a key is in the shard responsible for it.
+    fn contains_key(&self, key: &K) -> bool {
+        self.shard(key).contains_key(key)
+    }
+
+    /// Set a value in the shard responsible for the key.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        self.shard(&key).set_arc(key, value)
+    }
+
+    /// Set a value in the shard responsible for the key and return whether it was stored.
+    fn try_set(&self, key: K, value: V) -> bool {
+        self.shard(&key).try_set(key, value)
+    }
+
+    /// Set a value in the shard responsible for the key if the key is missing.
+    fn put_if_absent(&self, key: K, value: V) -> Arc<V> {
+        self.shard(&key).put_if_absent(key, value)
+    }
+
+    /// Replace a value in the shard responsible for the key if the key is present.
+    fn replace(&self, key: &K, value: V) -> Option<Arc<V>> {
+        self.shard(key).replace(key, value)
+    }
+
+    /// Remove a value from the shard responsible for the key.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        self.shard(key).remove(key)
+    }
+
+    /// Clear every shard.
+    fn clear(&self) {
+        for shard in &self.shards {
+            shard.clear();
+        }
+    }
+
+    /// Get the cache statistics summed across all shards.
+    fn stats(&self) -> CacheStats {
+        self.shards.iter().map(|shard| shard.stats()).fold(
+            CacheStats {
+                hits: 0,
+                misses: 0,
+                evictions: 0,
+                size: 0,
+                capacity: 0,
+            },
+            |total, stats| CacheStats {
+                hits: total.hits + stats.hits,
+                misses: total.misses + stats.misses,
+                evictions: total.evictions + stats.evictions,
+                size: total.size + stats.size,
+                capacity: total.capacity + stats.capacity,
+            },
+        )
+    }
+
+    /// Reset the statistics of every shard.
+    fn reset_stats(&self) {
+        for shard in &self.shards {
+            shard.reset_stats();
+        }
+    }
+
+    /// Get the number of items across all shards.
+    fn len(&self) -> u64 {
+        self.shards.iter().map(|shard| shard.len()).sum()
+    }
+
+    /// Get the total capacity of every shard.
+    fn capacity(&self) -> u64 {
+        self.shards.iter().map(|shard| shard.capacity()).sum()
+    }
+
+    /// Change the total capacity of the cache, the new capacity is divided evenly between the shards, rounding up.
+    fn change_capacity(&self, capacity: u64) {
+        let shard_capacity = capacity.div_ceil(self.shards.len() as u64);
+        for shard in &self.shards {
+            shard.change_capacity(shard_capacity);
+        }
+    }
+
+    /// Remove every item for which the predicate returns false from every shard.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        for shard in &self.shards {
+            shard.retain(&mut f);
+        }
+    }
+
+    /// Update a value in place in the shard responsible for the key.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        self.shard(key).compute(key, f)
+    }
+
+    /// Compare and swap a value in the shard responsible for the key.
+    fn compare_and_swap(&self, key: &K, expected: &V, new: V) -> Result<(), Option<Arc<V>>>
+    where
+        V: Clone + PartialEq,
+    {
+        self.shard(key).compare_and_swap(key, expected, new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LRUCache;
+
+    #[test]
+    fn test_sharded_cache() {
+        let cache = ShardedCache::new(4, 100, LRUCache::new);
+        for i in 0..50 {
+            cache.set(i, i);
+        }
+        for i in 0..50 {
+            assert_eq!(cache.get(&i).map(|v| *v), Some(i));
+        }
+        cache.get(&100);
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 50);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.size, 50);
+        assert_eq!(stats.capacity, 100);
+        assert_eq!(cache.len(), 50);
+    }
+
+    #[test]
+    fn test_sharded_cache_shard_stats() {
+        let cache = ShardedCache::new(4, 100, LRUCache::new);
+        for i in 0..50 {
+            cache.set(i, i);
+            cache.get(&i);
+        }
+        let shard_stats = cache.shard_stats();
+        assert_eq!(shard_stats.len(), 4);
+        assert!(shard_stats.iter().all(|stats| stats.capacity == 25));
+        let stats = cache.stats();
+        assert_eq!(shard_stats.iter().map(|s| s.size).sum::<u64>(), stats.size);
+        assert_eq!(shard_stats.iter().map(|s| s.hits).sum::<u64>(), stats.hits);
+    }
+
+    #[test]
+    fn test_sharded_cache_divides_capacity() {
+        let cache = ShardedCache::new(3, 10, LRUCache::<u64, u64>::new);
+        assert_eq!(cache.shard_count(), 3);
+        assert_eq!(cache.stats().capacity, 12);
+        cache.change_capacity(3);
+        for i in 0..100 {
+            cache.set(i, i);
+        }
+        assert!(cache.len() <= 3);
+    }
+
+    #[test]
+    fn test_sharded_cache_remove_and_retain() {
+        let cache = ShardedCache::new(4, 100, LRUCache::new);
+        for i in 0..20 {
+            cache.set(i, i);
+        }
+        assert_eq!(cache.remove(&0).map(|v| *v), Some(0));
+        cache.retain(|key, _| key % 2 == 1);
+        assert_eq!(cache.len(), 10);
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_sharded_cache_reset_stats() {
+        let cache = ShardedCache::new(4, 100, LRUCache::new);
+        for i in 0..20 {
+            cache.set(i, i);
+            cache.get(&i);
+        }
+        cache.reset_stats();
+        assert_eq!(cache.stats().hits, 0);
+        assert_eq!(cache.len(), 20);
+    }
+
+    #[test]
+    fn test_sharded_cache_capacity() {
+        let cache = ShardedCache::new(4, 100, LRUCache::<u64, u64>::new);
+        assert_eq!(cache.capacity(), cache.stats().capacity);
+        cache.change_capacity(8);
+        assert_eq!(cache.capacity(), 8);
+    }
+
+    #[test]
+    fn test_sharded_cache_put_if_absent() {
+        let cache = ShardedCache::new(4, 100, LRUCache::new);
+        assert_eq!(*cache.put_if_absent(1, 1), 1);
+        assert_eq!(*cache.put_if_absent(1, 2), 1);
+        assert_eq!(cache.len(), 1);
+    }
+}
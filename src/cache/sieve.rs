@@ -0,0 +1,384 @@
+use crate::cache::sync::RwLock;
+use crate::cache::{update_in_place, Cache, CacheStats, HashMap, StatsCounters};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// A node in the queue of the SieveCache, linked from newer items to older items.
+struct SieveNode<K, V> {
+    key: K,
+    value: Arc<V>,
+    visited: AtomicBool,
+    newer: Option<usize>,
+    older: Option<usize>,
+}
+
+/// The inner data structure for the SieveCache.
+struct SieveCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    capacity: u64,
+    nodes: Vec<Option<SieveNode<K, V>>>,
+    index: HashMap<K, usize>,
+    free: Vec<usize>,
+    newest: Option<usize>,
+    oldest: Option<usize>,
+    hand: Option<usize>,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> SieveCacheInner<K, V> {
+    /// Create a new SieveCacheInner with the given capacity, internally capacity is reserved for the necessary data structures.
+    fn new(capacity: u64) -> Self {
+        SieveCacheInner {
+            capacity,
+            nodes: Vec::with_capacity(capacity as usize),
+            index: HashMap::with_capacity(capacity as usize),
+            free: Vec::new(),
+            newest: None,
+            oldest: None,
+            hand: None,
+            evictions: 0,
+        }
+    }
+
+    /// Get the node at a position, which must be in the queue.
+    fn node(&self, position: usize) -> &SieveNode<K, V> {
+        self.nodes[position].as_ref().unwrap()
+    }
+
+    /// Unlink the node at a position from the queue and free its slot, moving the hand on to the next newer item if it pointed at the node.
+    fn unlink(&mut self, position: usize) -> Option<SieveNode<K, V>> {
+        let node = self.nodes[position].take()?;
+        match node.newer {
+            Some(newer) => self.nodes[newer].as_mut().unwrap().older = node.older,
+            None => self.newest = node.older,
+        }
+        match node.older {
+            Some(older) => self.nodes[older].as_mut().unwrap().newer = node.newer,
+            None => self.oldest = node.newer,
+        }
+        if self.hand == Some(position) {
+            self.hand = node.newer;
+        }
+        self.index.remove(&node.key);
+        self.free.push(position);
+        Some(node)
+    }
+
+    /// Move the hand from older to newer items, clearing visited bits, until an unvisited item is found and evicted. The hand wraps around to the oldest item when it passes the newest.
+    fn evict(&mut self) {
+        let mut position = match self.hand.or(self.oldest) {
+            Some(position) => position,
+            None => return,
+        };
+        loop {
+            let node = self.node(position);
+            if !node.visited.swap(false, Ordering::Relaxed) {
+                break;
+            }
+            position = match node.newer.or(self.oldest) {
+                Some(next) => next,
+                None => return,
+            };
+        }
+        self.hand = Some(position);
+        self.unlink(position);
+        self.evictions += 1;
+    }
+
+    /// Remove a key from the cache.
+    fn remove(&mut self, key: &K) -> Option<Arc<V>> {
+        let position = *self.index.get(key)?;
+        self.unlink(position).map(|node| node.value)
+    }
+
+    /// Insert a value as the newest item, evicting items as needed to stay within the capacity, and return the previous value. Replacing the value of a key keeps its place in the queue.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        if let Some(position) = self.index.get(&key).copied() {
+            let node = self.nodes[position].as_mut().unwrap();
+            node.visited.store(true, Ordering::Relaxed);
+            return Some(core::mem::replace(&mut node.value, value));
+        }
+        if self.index.len() as u64 >= self.capacity {
+            self.evict();
+        }
+        let node = SieveNode {
+            key: key.clone(),
+            value,
+            visited: AtomicBool::new(false),
+            newer: None,
+            older: self.newest,
+        };
+        let position = match self.free.pop() {
+            Some(position) => {
+                self.nodes[position] = Some(node);
+                position
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.nodes.len() - 1
+            }
+        };
+        match self.newest {
+            Some(newest) => self.nodes[newest].as_mut().unwrap().newer = Some(position),
+            None => self.oldest = Some(position),
+        }
+        self.newest = Some(position);
+        self.index.insert(key, position);
+        None
+    }
+
+    /// Look up a key and set its visited bit, this only needs a shared reference so it can be done under a read lock.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let node = self.node(*self.index.get(key)?);
+        node.visited.store(true, Ordering::Relaxed);
+        Some(node.value.clone())
+    }
+}
+
+/// SieveCache is a cache that uses the SIEVE algorithm to evict items.
+///
+/// Items are kept in a single queue in insertion order, each with a visited bit which is set whenever the item is read. When the cache is full a hand moves from the oldest item towards the newest, clearing visited bits as it passes, and evicts the first item which has not been visited. Unlike CLOCK the hand stays where it stopped, and new items are always added at the newest end, so items which are only used once are evicted quickly while items which are reused survive scans. Like `ClockCache` it avoids reordering a list on every `get`.
+///
+/// Mutability is handled internally with a RwLock. Because a `get` only needs to set an atomic visited bit it takes a shared read lock, so concurrent readers do not block each other. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, SieveCache};
+///
+/// let cache = SieveCache::<&str, String>::new(10);
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct SieveCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    inner: RwLock<SieveCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> SieveCache<K, V> {
+    /// Create a new SieveCache with the given capacity.
+    pub fn new(capacity: u64) -> Self {
+        SieveCache {
+            inner: RwLock::new(SieveCacheInner::new(capacity)),
+            counters: StatsCounters::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for SieveCache<K, V> {
+    /// Get a value from the cache, setting its visited bit.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.read();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single read lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let inner = self.inner.read();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without setting its visited bit or updating the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.read();
+        let position = *inner.index.get(key)?;
+        Some(inner.node(position).value.clone())
+    }
+
+    /// Set a value in the cache.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.write();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.write();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.write();
+        inner.remove(key)
+    }
+
+    /// Clear the cache, removing all items.
+    fn clear(&self) {
+        let mut inner = self.inner.write();
+        inner.nodes.clear();
+        inner.index.clear();
+        inner.free.clear();
+        inner.newest = None;
+        inner.oldest = None;
+        inner.hand = None;
+    }
+
+    /// Get the cache statistics.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.read();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.index.len() as u64,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.write();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.read();
+        inner.index.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.read();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, if the new capacity is smaller than the current size the hand evicts unvisited items until the cache fits.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.write();
+        let old_capacity = inner.capacity;
+        inner.capacity = capacity;
+        while inner.index.len() as u64 > inner.capacity {
+            inner.evict();
+        }
+
+        if inner.capacity > old_capacity {
+            let additional = (inner.capacity - old_capacity) as usize;
+            inner.index.reserve(additional);
+        }
+    }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.write();
+        for position in 0..inner.nodes.len() {
+            let rejected = match &inner.nodes[position] {
+                Some(node) => !f(&node.key, &node.value),
+                None => false,
+            };
+            if rejected {
+                inner.unlink(position);
+            }
+        }
+    }
+
+    /// Update a value in place without setting its visited bit or updating the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.write();
+        let SieveCacheInner { nodes, index, .. } = &mut *inner;
+        let value = index
+            .get(key)
+            .and_then(|position| nodes[*position].as_mut())
+            .map(|node| &mut node.value);
+        update_in_place(value, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FIFOCache;
+
+    #[test]
+    fn test_sieve_cache() {
+        let cache = SieveCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        // 1 has been visited so the hand clears its bit and evicts 2
+        cache.set(3, 3);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_sieve_cache_retains_reused_key_under_scan() {
+        let sieve = SieveCache::new(4);
+        let fifo = FIFOCache::new(4);
+        for i in 0..4 {
+            sieve.set(i, i);
+            fifo.set(i, i);
+        }
+        // 0 is reused between every item of a scan which is never read again
+        for i in 100..120 {
+            sieve.get(&0);
+            fifo.get(&0);
+            sieve.set(i, i);
+            fifo.set(i, i);
+        }
+        assert_eq!(sieve.get(&0).map(|v| *v), Some(0));
+        assert_eq!(fifo.get(&0), None);
+    }
+
+    #[test]
+    fn test_sieve_cache_remove_and_retain() {
+        let cache = SieveCache::new(4);
+        for i in 0..4 {
+            cache.set(i, i);
+        }
+        assert_eq!(cache.remove(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.remove(&1), None);
+        cache.set(4, 4);
+        cache.retain(|key, _| key % 2 == 0);
+        assert_eq!(cache.len(), 3);
+        assert!(cache.compute(&2, |value| *value += 1));
+        assert_eq!(cache.peek(&2).map(|v| *v), Some(3));
+        cache.set(5, 5);
+        cache.set(6, 6);
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_sieve_cache_change_capacity_and_clear() {
+        let cache = SieveCache::new(3);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        cache.get(&2);
+        cache.change_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        cache.clear();
+        assert!(cache.is_empty());
+        cache.set(4, 4);
+        assert_eq!(cache.get(&4).map(|v| *v), Some(4));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::cache::sync::Mutex;
+use std::collections::HashMap;
+#[cfg(feature = "async")]
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, OnceLock};
+
+/// SingleFlight deduplicates concurrent computations of the value for a key.
+///
+/// When several threads call `run` for the same key at the same time only the first runs its closure, the others block until it finishes and then receive the same `Arc<V>`. In-flight keys are tracked under their own Mutex, so computations for different keys do not serialize each other and no cache lock is held while a closure runs.
+///
+/// If the running closure panics the waiting callers are not poisoned, one of them runs its own closure instead.
+///
+/// Example:
+/// ```
+/// use arcache::SingleFlight;
+/// use std::sync::Arc;
+///
+/// let group = SingleFlight::<&str, String>::new();
+/// let value = group.run(&"key", || Arc::new("value".to_string()));
+/// assert_eq!(*value, "value".to_string());
+/// ```
+pub struct SingleFlight<K: Eq + Hash + Clone, V> {
+    in_flight: Mutex<HashMap<K, Arc<OnceLock<Arc<V>>>>>,
+}
+
+impl<K: Eq + Hash + Clone, V> SingleFlight<K, V> {
+    /// Create a new SingleFlight with no keys in flight.
+    pub fn new() -> Self {
+        SingleFlight {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Run `f` to produce the value for `key`, unless a computation for the key is already in flight in which case wait for it and return its result.
+    pub fn run<F: FnOnce() -> Arc<V>>(&self, key: &K, f: F) -> Arc<V> {
+        let cell = {
+            let mut in_flight = self.in_flight.lock();
+            in_flight
+                .entry(key.clone())
+                .or_insert_with(|| Arc::new(OnceLock::new()))
+                .clone()
+        };
+
+        let value = cell.get_or_init(f).clone();
+
+        let mut in_flight = self.in_flight.lock();
+        if in_flight
+            .get(key)
+            .is_some_and(|current| Arc::ptr_eq(current, &cell))
+        {
+            in_flight.remove(key);
+        }
+        value
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Default for SingleFlight<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// AsyncSingleFlight deduplicates concurrent asynchronous computations of the value for a key.
+///
+/// This is the async counterpart of `SingleFlight`: when several tasks call `run` for the same key at the same time only the first awaits its future, the others wait for it without blocking their thread and then receive the same `Arc<V>`.
+///
+/// If the running future is dropped before it completes, for example because its task was cancelled, one of the waiting tasks runs its own future instead.
+///
+/// Example:
+/// ```
+/// use arcache::AsyncSingleFlight;
+/// use std::sync::Arc;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let group = AsyncSingleFlight::<&str, String>::new();
+/// let value = group.run(&"key", || async { Arc::new("value".to_string()) }).await;
+/// assert_eq!(*value, "value".to_string());
+/// # });
+/// ```
+#[cfg(feature = "async")]
+pub struct AsyncSingleFlight<K: Eq + Hash + Clone, V> {
+    in_flight: Mutex<HashMap<K, Arc<tokio::sync::OnceCell<Arc<V>>>>>,
+}
+
+#[cfg(feature = "async")]
+impl<K: Eq + Hash + Clone, V> AsyncSingleFlight<K, V> {
+    /// Create a new AsyncSingleFlight with no keys in flight.
+    pub fn new() -> Self {
+        AsyncSingleFlight {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Await the future returned by `f` to produce the value for `key`, unless a computation for the key is already in flight in which case wait for it and return its result.
+    pub async fn run<F, Fut>(&self, key: &K, f: F) -> Arc<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Arc<V>>,
+    {
+        let cell = {
+            let mut in_flight = self.in_flight.lock();
+            in_flight
+                .entry(key.clone())
+                .or_insert_with(|| Arc::new(tokio::sync::OnceCell::new()))
+                .clone()
+        };
+
+        let value = cell.get_or_init(f).await.clone();
+
+        let mut in_flight = self.in_flight.lock();
+        if in_flight
+            .get(key)
+            .is_some_and(|current| Arc::ptr_eq(current, &cell))
+        {
+            in_flight.remove(key);
+        }
+        value
+    }
+}
+
+#[cfg(feature = "async")]
+impl<K: Eq + Hash + Clone, V> Default for AsyncSingleFlight<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_single_flight_runs_once() {
+        let group = Arc::new(SingleFlight::<u64, u64>::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(8));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let group = group.clone();
+                let calls = calls.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    group.run(&1, || {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(100));
+                        Arc::new(42)
+                    })
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(*handle.join().unwrap(), 42);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(group.in_flight.lock().is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// Number of rows in the sketch, each row uses an independent hash of the key.
+const DEPTH: usize = 4;
+
+/// Counters saturate at this value, mirroring the 4-bit counters used by TinyLFU.
+const MAX_COUNT: u8 = 15;
+
+/// The most counters in a row, so a cache with a huge capacity does not allocate a huge table.
+const MAX_WIDTH: u64 = 1 << 24;
+
+/// Odd multipliers used to derive an independent index for each row from a single hash.
+const SEEDS: [u64; DEPTH] = [
+    0xc3a5_c85c_97cb_3127,
+    0xb492_b66f_be98_f273,
+    0x9ae1_6a3b_2f90_404f,
+    0xcbf2_9ce4_8422_2325,
+];
+
+/// CountMinSketch is a compact probabilistic frequency counter.
+///
+/// Frequencies are never underestimated but may be overestimated due to hash collisions. The sketch ages itself by halving every counter once `sample_size` increments have been recorded so that historic popularity fades over time.
+pub(crate) struct CountMinSketch {
+    table: Vec<u8>,
+    width: usize,
+    hasher: RandomState,
+    additions: u64,
+    sample_size: u64,
+}
+
+impl CountMinSketch {
+    /// Create a new CountMinSketch with roughly `width` counters per row, at most `MAX_WIDTH`, which is reset after `sample_size` increments.
+    pub(crate) fn new(width: u64, sample_size: u64) -> Self {
+        let width = (width.clamp(1, MAX_WIDTH) as usize)
+            .next_power_of_two()
+            .max(16);
+        CountMinSketch {
+            table: vec![0; width * DEPTH],
+            width,
+            hasher: RandomState::new(),
+            additions: 0,
+            sample_size: sample_size.max(1),
+        }
+    }
+
+    /// Get the table index of the key for each row of the sketch.
+    fn indices<K: Hash>(&self, key: &K) -> [usize; DEPTH] {
+        let hash = self.hasher.hash_one(key);
+        let mut indices = [0; DEPTH];
+        for (row, seed) in SEEDS.iter().enumerate() {
+            let mixed = (hash ^ seed).wrapping_mul(*seed);
+            indices[row] = row * self.width + ((mixed >> 32) as usize & (self.width - 1));
+        }
+        indices
+    }
+
+    /// Record an occurrence of the key, halving all counters once the sample size is reached.
+    pub(crate) fn increment<K: Hash>(&mut self, key: &K) {
+        for index in self.indices(key) {
+            if self.table[index] < MAX_COUNT {
+                self.table[index] += 1;
+            }
+        }
+        self.additions += 1;
+        if self.additions >= self.sample_size {
+            self.reset();
+        }
+    }
+
+    /// Estimate how often the key has been seen.
+    pub(crate) fn frequency<K: Hash>(&self, key: &K) -> u8 {
+        self.indices(key)
+            .iter()
+            .map(|index| self.table[*index])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Age the sketch by halving every counter.
+    fn reset(&mut self) {
+        for counter in self.table.iter_mut() {
+            *counter >>= 1;
+        }
+        self.additions /= 2;
+    }
+
+    /// Forget all recorded frequencies.
+    pub(crate) fn clear(&mut self) {
+        self.table.iter_mut().for_each(|counter| *counter = 0);
+        self.additions = 0;
+    }
+}
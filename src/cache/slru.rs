@@ -0,0 +1,312 @@
+use crate::cache::sync::Mutex;
+use crate::cache::{retain_linked_hash_map, update_in_place, Cache, CacheStats, StatsCounters};
+use linked_hash_map::LinkedHashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// The default fraction of the capacity given to the protected segment.
+const DEFAULT_PROTECTED_RATIO: f64 = 0.8;
+
+/// The inner data structure for the SLRUCache.
+struct SLRUCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    capacity: u64,
+    protected_ratio: f64,
+    protected_capacity: u64,
+    probation: LinkedHashMap<K, Arc<V>>,
+    protected: LinkedHashMap<K, Arc<V>>,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> SLRUCacheInner<K, V> {
+    /// Create a new SLRUCacheInner with the given capacity and protected fraction.
+    fn new(capacity: u64, protected_ratio: f64) -> Self {
+        let protected_ratio = protected_ratio.clamp(0.0, 1.0);
+        SLRUCacheInner {
+            capacity,
+            protected_ratio,
+            protected_capacity: (capacity as f64 * protected_ratio) as u64,
+            probation: LinkedHashMap::new(),
+            protected: LinkedHashMap::new(),
+            evictions: 0,
+        }
+    }
+
+    /// The share of the capacity available to the probationary segment.
+    fn probation_capacity(&self) -> u64 {
+        self.capacity - self.protected_capacity
+    }
+
+    /// The number of items stored in the cache.
+    fn len(&self) -> u64 {
+        (self.probation.len() + self.protected.len()) as u64
+    }
+
+    /// Demote protected items back into probation and evict from probation until both segments fit their share.
+    fn enforce_capacity(&mut self) {
+        while self.protected.len() as u64 > self.protected_capacity {
+            match self.protected.pop_front() {
+                Some((key, value)) => {
+                    self.probation.insert(key, value);
+                }
+                None => break,
+            }
+        }
+        while self.probation.len() as u64 > self.probation_capacity() {
+            if self.probation.pop_front().is_none() {
+                break;
+            }
+            self.evictions += 1;
+        }
+    }
+
+    /// Look up a key, updating the eviction order.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        let mut result = self.protected.get_refresh(key).cloned();
+        if result.is_none() {
+            if let Some(value) = self.probation.remove(key) {
+                self.protected.insert(key.clone(), value.clone());
+                self.enforce_capacity();
+                result = Some(value);
+            }
+        }
+
+        result
+    }
+
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        if let Some(entry) = self.protected.get_refresh(&key) {
+            return Some(std::mem::replace(entry, value));
+        }
+        if let Some(entry) = self.probation.get_refresh(&key) {
+            return Some(std::mem::replace(entry, value));
+        }
+
+        self.probation.insert(key, value);
+        self.enforce_capacity();
+        None
+    }
+}
+
+/// SLRUCache is a cache that uses the Segmented LRU (SLRU) algorithm to evict items.
+///
+/// The capacity is split between a probationary segment and a protected segment, each of which is never allowed to exceed its share. New items are placed in the probationary segment. An item which is accessed again while in probation is promoted to a protected segment, and when the protected segment is full its least recently used item is demoted back into probation. Items are only ever evicted from the probationary segment, so a burst of items which are only seen once cannot flush out items which are used repeatedly.
+///
+/// All mutability is handled internally with a Mutex, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, SLRUCache};
+///
+/// let cache = SLRUCache::<&str, String>::new(10);
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct SLRUCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    inner: Mutex<SLRUCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> SLRUCache<K, V> {
+    /// Create a new SLRUCache with the given capacity, 80% of the capacity is used for the protected segment.
+    pub fn new(capacity: u64) -> Self {
+        Self::with_protected_ratio(capacity, DEFAULT_PROTECTED_RATIO)
+    }
+
+    /// Create a new SLRUCache with the given capacity and the fraction of the capacity to use for the protected segment.
+    pub fn with_protected_ratio(capacity: u64, protected_ratio: f64) -> Self {
+        SLRUCache {
+            inner: Mutex::new(SLRUCacheInner::new(capacity, protected_ratio)),
+            counters: StatsCounters::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for SLRUCache<K, V> {
+    /// Get a value from the cache, a hit in the probationary segment promotes the item to the protected segment.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner
+            .probation
+            .get(key)
+            .or_else(|| inner.protected.get(key))
+            .cloned()
+    }
+
+    /// Set a value in the cache, new items are placed in the probationary segment.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner
+            .probation
+            .remove(key)
+            .or_else(|| inner.protected.remove(key))
+    }
+
+    /// Clear the cache, removing all items.
+    fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.probation.clear();
+        inner.protected.clear();
+    }
+
+    /// Get the cache statistics.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.len(),
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.len()
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, both segments are resized and items are demoted or evicted until neither exceeds its share.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity;
+        inner.protected_capacity = (capacity as f64 * inner.protected_ratio) as u64;
+        inner.enforce_capacity();
+    }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        retain_linked_hash_map(&mut inner.probation, &mut f);
+        retain_linked_hash_map(&mut inner.protected, &mut f);
+    }
+
+    /// Update a value in place without promoting it or updating the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        let SLRUCacheInner {
+            probation,
+            protected,
+            ..
+        } = &mut *inner;
+        update_in_place(probation.get_mut(key).or_else(|| protected.get_mut(key)), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slru_cache() {
+        let cache = SLRUCache::new(5);
+        cache.set(1, 1);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        cache.set(2, 2);
+        // the probationary segment only holds a single item, so 2 is evicted
+        cache.set(3, 3);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+    }
+
+    #[test]
+    fn test_slru_cache_protects_reused_items() {
+        let cache = SLRUCache::new(10);
+        cache.set(0, 0);
+        cache.get(&0);
+        for i in 1..100 {
+            cache.set(i, i);
+            cache.get(&0);
+        }
+        assert_eq!(cache.get(&0).map(|v| *v), Some(0));
+        assert_eq!(cache.get(&99).map(|v| *v), Some(99));
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_slru_cache_change_capacity() {
+        let cache = SLRUCache::new(10);
+        for i in 0..10 {
+            cache.set(i, i);
+            cache.get(&i);
+        }
+        cache.change_capacity(5);
+        let inner = cache.inner.lock();
+        assert!(inner.protected.len() as u64 <= inner.protected_capacity);
+        assert!(inner.probation.len() as u64 <= inner.probation_capacity());
+        assert_eq!(inner.len(), 5);
+        assert!(inner.protected.contains_key(&9));
+    }
+
+    #[test]
+    fn test_slru_cache_clear() {
+        let cache = SLRUCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.clear();
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), None);
+    }
+}
@@ -0,0 +1,186 @@
+#[cfg(feature = "persistence")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "persistence")]
+use std::fs::File;
+#[cfg(feature = "persistence")]
+use std::io::{self, BufReader, BufWriter, Read, Write};
+#[cfg(feature = "persistence")]
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "persistence")]
+use std::time::SystemTime;
+
+use crate::cache::time::ExpiryPolicy;
+
+/// An owned copy of the contents of a cache, used to restore the items into another cache later and, with the `serde` feature, to persist a cache.
+///
+/// Values are shared with the cache as Arcs so taking a snapshot does not clone them. Expiry times are stored as the time remaining when the snapshot was taken rather than as `Instant`s, which are only meaningful within a single process. When the snapshot is restored each expiry is recomputed from the time of the restore.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, LRUCache};
+///
+/// let cache = LRUCache::<String, u64>::new(10);
+/// cache.set("a".to_string(), 1);
+/// cache.set("b".to_string(), 2);
+///
+/// let restored = LRUCache::<String, u64>::new(100);
+/// restored.restore(cache.snapshot());
+///
+/// assert_eq!(restored.keys(), vec!["a".to_string(), "b".to_string()]);
+/// assert_eq!(restored.capacity(), 100);
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CacheSnapshot<K, V> {
+    /// The capacity of the cache.
+    pub capacity: u64,
+    /// The TTL of the cache, if it has one.
+    pub ttl: Option<Duration>,
+    /// Whether reading an item extends its expiry, an LRUCache never extends the expiry of its items so its snapshots always record `ExpiryPolicy::Fixed`.
+    pub expiry_policy: ExpiryPolicy,
+    /// The items in the cache, ordered from least to most recently used.
+    pub entries: Vec<SnapshotEntry<K, V>>,
+}
+
+/// A single item in a CacheSnapshot.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SnapshotEntry<K, V> {
+    pub key: K,
+    pub value: Arc<V>,
+    /// The time left before the item expires, `None` if it never expires. An item without one restored from a snapshot which has no TTL gets the TTL of the restoring cache instead.
+    pub remaining: Option<Duration>,
+    /// The TTL the expiry of the item is extended by under a sliding expiry policy, if it has its own.
+    pub ttl: Option<Duration>,
+}
+
+#[cfg(feature = "persistence")]
+impl<K, V> CacheSnapshot<K, V> {
+    /// Account for time which has passed since the snapshot was taken, dropping items which have expired in the meantime.
+    fn elapse(&mut self, elapsed: Duration) {
+        self.entries.retain_mut(|entry| match entry.remaining {
+            Some(remaining) if remaining <= elapsed => false,
+            Some(remaining) => {
+                entry.remaining = Some(remaining - elapsed);
+                true
+            }
+            None => true,
+        });
+    }
+}
+
+/// The bytes every file written by `save_to_path` starts with.
+#[cfg(feature = "persistence")]
+const MAGIC: &[u8; 8] = b"ARCACHE\0";
+
+/// The version of the file format written by `save_to_path`, this is bumped whenever the format changes so old files are detected rather than misread.
+#[cfg(feature = "persistence")]
+const FORMAT_VERSION: u32 = 1;
+
+/// The body of a file written by `save_to_path`.
+#[cfg(feature = "persistence")]
+#[derive(Serialize, Deserialize)]
+struct SnapshotFile<K, V> {
+    /// The wall clock time the file was written, as the time since the unix epoch.
+    saved_at: Duration,
+    snapshot: CacheSnapshot<K, V>,
+}
+
+/// Write a snapshot to a file, the file starts with a magic header and format version followed by the snapshot as JSON.
+#[cfg(feature = "persistence")]
+pub(crate) fn write_to_path<K: Serialize, V: Serialize>(
+    path: &Path,
+    snapshot: CacheSnapshot<K, V>,
+) -> io::Result<()> {
+    let saved_at = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    serde_json::to_writer(&mut writer, &SnapshotFile { saved_at, snapshot })?;
+    writer.flush()
+}
+
+/// Read a snapshot written by `write_to_path`, dropping items which have expired since the file was written.
+#[cfg(feature = "persistence")]
+pub(crate) fn read_from_path<K: DeserializeOwned, V: DeserializeOwned>(
+    path: &Path,
+) -> io::Result<CacheSnapshot<K, V>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the file is not an arcache snapshot",
+        ));
+    }
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported arcache snapshot version {version}"),
+        ));
+    }
+
+    let file: SnapshotFile<K, V> = serde_json::from_reader(reader)?;
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut snapshot = file.snapshot;
+    snapshot.elapse(now.saturating_sub(file.saved_at));
+    Ok(snapshot)
+}
+
+#[cfg(all(test, feature = "persistence"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_from_path_rejects_bad_header() {
+        let path = std::env::temp_dir().join(format!("arcache-bad-header-{}", std::process::id()));
+        std::fs::write(&path, b"not a snapshot").unwrap();
+        let error = read_from_path::<u64, u64>(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        let error = read_from_path::<u64, u64>(&path).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_elapse_drops_expired_entries() {
+        let mut snapshot = CacheSnapshot {
+            capacity: 2,
+            ttl: None,
+            expiry_policy: ExpiryPolicy::Fixed,
+            entries: vec![
+                SnapshotEntry {
+                    key: 1,
+                    value: Arc::new(1),
+                    remaining: Some(Duration::from_secs(1)),
+                    ttl: None,
+                },
+                SnapshotEntry {
+                    key: 2,
+                    value: Arc::new(2),
+                    remaining: Some(Duration::from_secs(3)),
+                    ttl: None,
+                },
+            ],
+        };
+        snapshot.elapse(Duration::from_secs(2));
+        assert_eq!(snapshot.entries.len(), 1);
+        assert_eq!(snapshot.entries[0].remaining, Some(Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,138 @@
+use core::fmt;
+#[cfg(all(feature = "std", not(feature = "parking_lot")))]
+use std::sync::PoisonError;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+#[cfg(feature = "parking_lot")]
+pub(crate) use parking_lot::{MutexGuard, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(not(feature = "std"))]
+pub(crate) use spin::{MutexGuard, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(all(feature = "std", not(feature = "parking_lot")))]
+pub(crate) use std::sync::{MutexGuard, RwLockReadGuard, RwLockWriteGuard};
+
+#[cfg(feature = "parking_lot")]
+use parking_lot as imp;
+#[cfg(not(feature = "std"))]
+use spin as imp;
+#[cfg(all(feature = "std", not(feature = "parking_lot")))]
+use std::sync as imp;
+
+/// A Mutex which recovers from poisoning instead of panicking, used for the locks inside the caches.
+///
+/// A lock is poisoned when a thread panics while holding it, for example in a closure passed to `compute` or `retain`. The caches keep their inner data structurally valid whenever user code runs under the lock, so the data behind a poisoned lock is still usable and one panicking closure does not make a cache unusable for the rest of the process.
+///
+/// With the `parking_lot` feature this wraps `parking_lot::Mutex` instead, which never poisons, and without the `std` feature it wraps `spin::Mutex`, which spins rather than blocking and has no poisoning either.
+#[derive(Default)]
+pub(crate) struct Mutex<T>(imp::Mutex<T>);
+
+impl<T> Mutex<T> {
+    /// Create a new unlocked Mutex holding the given value.
+    pub(crate) fn new(value: T) -> Self {
+        Mutex(imp::Mutex::new(value))
+    }
+
+    /// Lock the Mutex, blocking until it is available. The guard is returned even if another thread panicked while holding the lock.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+        #[cfg(any(feature = "parking_lot", not(feature = "std")))]
+        return self.0.lock();
+        #[cfg(all(feature = "std", not(feature = "parking_lot")))]
+        return self.0.lock().unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Mutex").field(&*self.lock()).finish()
+    }
+}
+
+/// A RwLock which recovers from poisoning instead of panicking, for the same reason as `Mutex`.
+#[derive(Default)]
+pub(crate) struct RwLock<T>(imp::RwLock<T>);
+
+impl<T> RwLock<T> {
+    /// Create a new unlocked RwLock holding the given value.
+    pub(crate) fn new(value: T) -> Self {
+        RwLock(imp::RwLock::new(value))
+    }
+
+    /// Take a shared read lock, blocking until it is available. The guard is returned even if another thread panicked while holding the lock.
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, T> {
+        #[cfg(any(feature = "parking_lot", not(feature = "std")))]
+        return self.0.read();
+        #[cfg(all(feature = "std", not(feature = "parking_lot")))]
+        return self.0.read().unwrap_or_else(PoisonError::into_inner);
+    }
+
+    /// Take the exclusive write lock, blocking until it is available. The guard is returned even if another thread panicked while holding the lock.
+    pub(crate) fn write(&self) -> RwLockWriteGuard<'_, T> {
+        #[cfg(any(feature = "parking_lot", not(feature = "std")))]
+        return self.0.write();
+        #[cfg(all(feature = "std", not(feature = "parking_lot")))]
+        return self.0.write().unwrap_or_else(PoisonError::into_inner);
+    }
+}
+
+/// A Condvar for waiting on a Mutex from this module, only available with the `std` feature.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub(crate) struct Condvar(imp::Condvar);
+
+#[cfg(feature = "std")]
+impl Condvar {
+    /// Create a new Condvar.
+    pub(crate) fn new() -> Self {
+        Condvar(imp::Condvar::new())
+    }
+
+    /// Release the lock and block until notified or until the timeout has passed, then take the lock again and return its guard.
+    #[cfg(feature = "parking_lot")]
+    pub(crate) fn wait_timeout<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> MutexGuard<'a, T> {
+        self.0.wait_for(&mut guard, timeout);
+        guard
+    }
+
+    /// Release the lock and block until notified or until the timeout has passed, then take the lock again and return its guard.
+    #[cfg(all(feature = "std", not(feature = "parking_lot")))]
+    pub(crate) fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> MutexGuard<'a, T> {
+        self.0
+            .wait_timeout(guard, timeout)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+    }
+
+    /// Wake one thread which is waiting on the Condvar.
+    pub(crate) fn notify_one(&self) {
+        self.0.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn test_locks_recover_from_poisoning() {
+        let mutex = Mutex::new(1);
+        let rw_lock = RwLock::new(1);
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _guard = mutex.lock();
+            let _write = rw_lock.write();
+            panic!("poison the locks");
+        }));
+        *mutex.lock() += 1;
+        *rw_lock.write() += 1;
+        assert_eq!(*mutex.lock(), 2);
+        assert_eq!(*rw_lock.read(), 2);
+    }
+}
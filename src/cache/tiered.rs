@@ -0,0 +1,262 @@
+use crate::cache::{Cache, CacheStats, StatsCounters};
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// TieredStats breaks down the lookups of a TieredCache by the tier which served them.
+#[derive(Debug, Clone)]
+pub struct TieredStats {
+    /// The number of lookups served by the first tier.
+    pub l1_hits: u64,
+    /// The number of lookups which missed the first tier and were served by the second.
+    pub l2_hits: u64,
+    /// The number of lookups which missed both tiers.
+    pub misses: u64,
+}
+
+/// TieredCache puts a small fast cache in front of a larger one, for example an `LRUCache` in front of a `ShardedCache`.
+///
+/// A `get` checks the first tier and then the second, an item found in the second tier is copied into the first so it is served from there next time. A `set` writes through to both tiers. Any caches implementing the `Cache` trait can be used as tiers, eviction happens independently in each one.
+///
+/// The hits and misses in `stats` count the lookups of the TieredCache as a whole, `tiered_stats` breaks the hits down by tier. Because every item is written to the second tier, the size, capacity and evictions are reported from it.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, LRUCache, TieredCache};
+///
+/// let cache = TieredCache::new(LRUCache::<&str, String>::new(1), LRUCache::new(100));
+///
+/// cache.set("a", "1".to_string());
+/// cache.set("b", "2".to_string());
+///
+/// // "a" was evicted from the first tier but is still in the second
+/// assert_eq!(*cache.get(&"a").unwrap(), "1".to_string());
+/// assert_eq!(cache.tiered_stats().l2_hits, 1);
+///
+/// // the hit promoted "a" back into the first tier
+/// assert_eq!(*cache.get(&"a").unwrap(), "1".to_string());
+/// assert_eq!(cache.tiered_stats().l1_hits, 1);
+/// ```
+pub struct TieredCache<L1, L2> {
+    l1: L1,
+    l2: L2,
+    counters: StatsCounters,
+    l1_hits: AtomicU64,
+}
+
+impl<L1, L2> TieredCache<L1, L2> {
+    /// Create a new TieredCache which checks `l1` before `l2`.
+    pub fn new(l1: L1, l2: L2) -> Self {
+        TieredCache {
+            l1,
+            l2,
+            counters: StatsCounters::new(),
+            l1_hits: AtomicU64::new(0),
+        }
+    }
+
+    /// Get the first tier.
+    pub fn l1(&self) -> &L1 {
+        &self.l1
+    }
+
+    /// Get the second tier.
+    pub fn l2(&self) -> &L2 {
+        &self.l2
+    }
+
+    /// Get the number of lookups served by each tier and the number which missed both.
+    pub fn tiered_stats(&self) -> TieredStats {
+        let l1_hits = self.l1_hits.load(Ordering::Relaxed);
+        TieredStats {
+            l1_hits,
+            l2_hits: self.counters.hits().saturating_sub(l1_hits),
+            misses: self.counters.misses(),
+        }
+    }
+}
+
+impl<K, V, L1, L2> Cache<K, V> for TieredCache<L1, L2>
+where
+    K: Eq + Hash + Clone + Send + Sync,
+    V: Send + Sync,
+    L1: Cache<K, V>,
+    L2: Cache<K, V>,
+{
+    /// Get a value from the first tier, or from the second tier copying it into the first.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        if let Some(value) = self.l1.get(key) {
+            let value = self.counters.record(Some(value));
+            self.l1_hits.fetch_add(1, Ordering::Relaxed);
+            return value;
+        }
+        let value = self.l2.get(key);
+        if let Some(value) = &value {
+            self.l1.set_arc(key.clone(), value.clone());
+        }
+        self.counters.record(value)
+    }
+
+    /// Peek at a value in either tier without promoting it or updating the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        self.l1.peek(key).or_else(|| self.l2.peek(key))
+    }
+
+    /// Check whether a key is in either tier.
+    fn contains_key(&self, key: &K) -> bool {
+        self.l1.contains_key(key) || self.l2.contains_key(key)
+    }
+
+    /// Set a value in both tiers, returning the previous value from the second tier or, if it had none, the first.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let previous = self.l2.set_arc(key.clone(), value.clone());
+        let l1_previous = self.l1.set_arc(key, value);
+        previous.or(l1_previous)
+    }
+
+    /// Remove a value from both tiers, returning the value from the first tier or, if it had none, the second.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let l1_value = self.l1.remove(key);
+        let l2_value = self.l2.remove(key);
+        l1_value.or(l2_value)
+    }
+
+    /// Clear both tiers.
+    fn clear(&self) {
+        self.l1.clear();
+        self.l2.clear();
+    }
+
+    /// Get the hits and misses of the cache as a whole, with the size, capacity and evictions of the second tier.
+    fn stats(&self) -> CacheStats {
+        let l2 = self.l2.stats();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: l2.evictions,
+            size: l2.size,
+            capacity: l2.capacity,
+        }
+    }
+
+    /// Reset the statistics of the cache and of both tiers.
+    fn reset_stats(&self) {
+        self.l1.reset_stats();
+        self.l2.reset_stats();
+        self.counters.reset();
+        self.l1_hits.store(0, Ordering::Relaxed);
+    }
+
+    /// Get the number of items in the second tier.
+    fn len(&self) -> u64 {
+        self.l2.len()
+    }
+
+    /// Get the capacity of the second tier.
+    fn capacity(&self) -> u64 {
+        self.l2.capacity()
+    }
+
+    /// Change the capacity of the second tier, the first tier keeps its capacity.
+    fn change_capacity(&self, capacity: u64) {
+        self.l2.change_capacity(capacity);
+    }
+
+    /// Remove every item for which the predicate returns false from both tiers.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        self.l1.retain(&mut f);
+        self.l2.retain(&mut f);
+    }
+
+    /// Update a value in place in the second tier and copy the result into the first, a key which is only in the first tier is updated there.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut f = Some(f);
+        let mut update = |value: &mut V| {
+            if let Some(f) = f.take() {
+                f(value);
+            }
+        };
+        if self.l2.compute(key, &mut update) {
+            if let Some(value) = self.l2.peek(key) {
+                if self.l1.contains_key(key) {
+                    self.l1.set_arc(key.clone(), value);
+                }
+            }
+            return true;
+        }
+        self.l1.compute(key, update)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FIFOCache, LRUCache};
+
+    #[test]
+    fn test_tiered_cache() {
+        let cache = TieredCache::new(LRUCache::new(1), LRUCache::new(10));
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert!(cache.l1().peek(&1).is_none());
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert!(cache.l1().contains_key(&1));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&3), None);
+
+        let tiered = cache.tiered_stats();
+        assert_eq!(tiered.l1_hits, 2);
+        assert_eq!(tiered.l2_hits, 1);
+        assert_eq!(tiered.misses, 1);
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 3);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.size, 2);
+        assert_eq!(stats.capacity, 10);
+    }
+
+    #[test]
+    fn test_tiered_cache_remove_and_clear() {
+        let cache = TieredCache::new(FIFOCache::new(2), LRUCache::new(10));
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.remove(&1).map(|v| *v), Some(1));
+        assert!(!cache.contains_key(&1));
+        cache.clear();
+        assert!(cache.l1().is_empty());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_tiered_cache_compute() {
+        let cache = TieredCache::new(LRUCache::new(2), LRUCache::new(2));
+        cache.set(1, 1);
+        assert!(cache.compute(&1, |value| *value += 1));
+        assert_eq!(cache.l1().peek(&1).map(|v| *v), Some(2));
+        assert_eq!(cache.l2().peek(&1).map(|v| *v), Some(2));
+        cache.l2().remove(&1);
+        assert!(cache.compute(&1, |value| *value += 1));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(3));
+        assert!(!cache.compute(&4, |value| *value += 1));
+    }
+
+    #[test]
+    fn test_tiered_cache_reset_stats() {
+        let cache = TieredCache::new(LRUCache::new(2), LRUCache::new(2));
+        cache.set(1, 1);
+        cache.get(&1);
+        cache.get(&2);
+        cache.reset_stats();
+        assert_eq!(cache.stats().hits, 0);
+        assert_eq!(cache.tiered_stats().l1_hits, 0);
+        assert_eq!(cache.l2().stats().misses, 0);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::cache::sync::Mutex;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// A point in time read from a Clock, an `Instant` with the `std` feature.
+#[cfg(feature = "std")]
+pub type Timestamp = Instant;
+
+/// A point in time read from a Clock. Without the `std` feature there is no `Instant`, so it is the time since a fixed point chosen by the clock, such as when the device booted.
+#[cfg(not(feature = "std"))]
+pub type Timestamp = Duration;
+
+/// A source of the current time for caches with expiring items.
+///
+/// Caches use `SystemClock` by default, a `ManualClock` can be passed instead so expiry can be tested without sleeping. Without the `std` feature there is no system clock, so the caches with expiring items take a clock reading a timer of the target.
+pub trait Clock: Send + Sync {
+    /// Get the current time.
+    fn now(&self) -> Timestamp;
+}
+
+/// SystemClock reads the time from `Instant::now`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// How the expiry time of an item in a TTLCache is updated when it is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExpiryPolicy {
+    /// Every successful `get` extends the expiry of the item by its TTL, so an item only expires once it has not been read for the TTL.
+    #[default]
+    Sliding,
+    /// The expiry is fixed when the item is set, so an item expires the TTL after it was set regardless of how often it is read.
+    Fixed,
+}
+
+/// ManualClock is a clock which only moves when it is advanced, for deterministic tests of expiry.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, ManualClock, TTLCache};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let clock = Arc::new(ManualClock::new());
+/// let cache = TTLCache::<&str, String>::with_clock(Duration::from_secs(60), 10, clock.clone());
+///
+/// cache.set("key", "value".to_string());
+/// clock.advance(Duration::from_secs(59));
+/// assert!(cache.peek(&"key").is_some());
+/// clock.advance(Duration::from_secs(1));
+/// assert!(cache.peek(&"key").is_none());
+/// ```
+#[derive(Debug)]
+pub struct ManualClock {
+    start: Timestamp,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// Create a new ManualClock which starts at the current time, or at zero without the `std` feature.
+    pub fn new() -> Self {
+        ManualClock {
+            #[cfg(feature = "std")]
+            start: Instant::now(),
+            #[cfg(not(feature = "std"))]
+            start: Duration::ZERO,
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Move the clock forward by the given duration.
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock() += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Timestamp {
+        self.start + *self.elapsed.lock()
+    }
+}
@@ -0,0 +1,317 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// The length of one tick of the wheel, expiry times are rounded down to a whole tick.
+const TICK: Duration = Duration::from_millis(1);
+
+/// The number of bits of the tick used to pick a slot on each level.
+const SLOT_BITS: u32 = 6;
+
+/// The number of slots on each level of the wheel.
+const SLOTS: usize = 1 << SLOT_BITS;
+
+/// The number of levels, timers further away than the top level can cover wait in an overflow list.
+const LEVELS: usize = 4;
+
+/// The fewest cancelled timers which trigger a compaction, so a small wheel is not compacted on every cancel.
+const COMPACT_MIN: usize = 64;
+
+/// A handle to a scheduled timer, used to recognise a timer which has since been replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TimerHandle {
+    id: u64,
+    tick: u64,
+}
+
+/// A timer waiting in the wheel.
+struct Timer<K> {
+    key: K,
+    handle: TimerHandle,
+}
+
+/// TimerWheel is a hierarchical timer wheel which buckets keys by the tick they expire at.
+///
+/// Level 0 has a slot for each of the next 64 ticks, and each level above covers 64 times the span of the one below. Advancing the wheel pops the level 0 slot of each tick and, every 64 ticks, cascades the next slot of the level above down into the finer levels. Runs of ticks with no timers are skipped, so advancing costs time in the number of timers which fire rather than the number of keys.
+///
+/// A timer for a key which is removed or rescheduled is cancelled. Cancelled timers are dropped when they cascade or come due, and once they make up half of the waiting timers the whole wheel is compacted, so the wheel stays proportional to the number of live timers rather than the number of writes. A caller still compares the handle of a fired timer with the one it kept, since a timer which was already due when it was cancelled is returned anyway.
+pub(crate) struct TimerWheel<K> {
+    start: Instant,
+    current: u64,
+    next_id: u64,
+    levels: Vec<Vec<Vec<Timer<K>>>>,
+    counts: [usize; LEVELS],
+    overflow: Vec<Timer<K>>,
+    due: VecDeque<Timer<K>>,
+    cancelled: HashSet<u64>,
+}
+
+impl<K> TimerWheel<K> {
+    /// Create a new TimerWheel whose first tick starts at the given time.
+    pub(crate) fn new(start: Instant) -> Self {
+        TimerWheel {
+            start,
+            current: 0,
+            next_id: 0,
+            levels: (0..LEVELS)
+                .map(|_| (0..SLOTS).map(|_| Vec::new()).collect())
+                .collect(),
+            counts: [0; LEVELS],
+            overflow: Vec::new(),
+            due: VecDeque::new(),
+            cancelled: HashSet::new(),
+        }
+    }
+
+    /// Get the number of timers waiting in the wheel, including cancelled timers which have not been dropped yet.
+    pub(crate) fn len(&self) -> usize {
+        self.counts.iter().sum::<usize>() + self.overflow.len()
+    }
+
+    /// Cancel a timer so it never fires. A timer which has already fired or come due is left alone.
+    pub(crate) fn cancel(&mut self, handle: TimerHandle) {
+        // every timer still waiting is for a tick after the current one
+        if handle.tick <= self.current {
+            return;
+        }
+        self.cancelled.insert(handle.id);
+        if self.cancelled.len() >= COMPACT_MIN && self.cancelled.len() * 2 >= self.len() {
+            self.compact();
+        }
+    }
+
+    /// Drop every cancelled timer from the wheel.
+    fn compact(&mut self) {
+        let cancelled = std::mem::take(&mut self.cancelled);
+        for (level, slots) in self.levels.iter_mut().enumerate() {
+            for slot in slots.iter_mut() {
+                slot.retain(|timer| !cancelled.contains(&timer.handle.id));
+            }
+            self.counts[level] = slots.iter().map(Vec::len).sum();
+        }
+        self.overflow
+            .retain(|timer| !cancelled.contains(&timer.handle.id));
+    }
+
+    /// Get the tick which a time falls in, times before the start of the wheel fall in the first tick.
+    fn tick_of(&self, at: Instant) -> u64 {
+        (at.saturating_duration_since(self.start).as_nanos() / TICK.as_nanos()) as u64
+    }
+
+    /// Get the tick a timer for the given time would fire at, a timer is never scheduled for a tick which has already been processed.
+    pub(crate) fn tick_for(&self, at: Instant) -> u64 {
+        self.tick_of(at).max(self.current + 1)
+    }
+
+    /// Schedule a timer for the key which fires once the wheel is advanced past the given time.
+    pub(crate) fn schedule(&mut self, key: K, at: Instant) -> TimerHandle {
+        let handle = TimerHandle {
+            id: self.next_id,
+            tick: self.tick_for(at),
+        };
+        self.next_id += 1;
+        self.place(Timer { key, handle });
+        handle
+    }
+
+    /// Check whether a handle fires no later than a timer for the given time would.
+    pub(crate) fn fires_by(&self, handle: TimerHandle, at: Instant) -> bool {
+        handle.tick <= self.tick_for(at)
+    }
+
+    /// Put a timer in the level whose span first separates its tick from the current one, a cancelled timer is dropped instead.
+    fn place(&mut self, timer: Timer<K>) {
+        if self.cancelled.remove(&timer.handle.id) {
+            return;
+        }
+        let differing = timer.handle.tick ^ self.current;
+        let level = if differing == 0 {
+            0
+        } else {
+            ((u64::BITS - 1 - differing.leading_zeros()) / SLOT_BITS) as usize
+        };
+        if level >= LEVELS {
+            self.overflow.push(timer);
+            return;
+        }
+        let slot = (timer.handle.tick >> (SLOT_BITS * level as u32)) as usize & (SLOTS - 1);
+        self.levels[level][slot].push(timer);
+        self.counts[level] += 1;
+    }
+
+    /// Move the timers of a slot back into the wheel now that the current tick has reached it.
+    fn cascade(&mut self, level: usize) {
+        let slot = (self.current >> (SLOT_BITS * level as u32)) as usize & (SLOTS - 1);
+        let timers = std::mem::take(&mut self.levels[level][slot]);
+        self.counts[level] -= timers.len();
+        for timer in timers {
+            self.place(timer);
+        }
+    }
+
+    /// Advance the wheel towards the given time and return the key and handle of up to `limit` timers which fired, in the order of their ticks.
+    ///
+    /// Fired timers beyond the limit are kept and returned first by the next call, so a large batch of expiries can be handled in several smaller steps.
+    pub(crate) fn advance(&mut self, now: Instant, limit: usize) -> Vec<(K, TimerHandle)> {
+        let target = self.tick_of(now);
+        while self.due.len() < limit && self.current < target {
+            // skip straight to the next boundary at which a non empty level cascades
+            let empty_levels = self.counts.iter().take_while(|count| **count == 0).count();
+            if empty_levels > 0 {
+                if empty_levels == LEVELS && self.overflow.is_empty() {
+                    self.current = target;
+                    break;
+                }
+                let span = 1u64 << (SLOT_BITS * empty_levels as u32);
+                let boundary = (self.current / span + 1) * span;
+                if boundary > target {
+                    self.current = target;
+                    break;
+                }
+                self.current = boundary - 1;
+            }
+            self.current += 1;
+
+            if self
+                .current
+                .is_multiple_of(1u64 << (SLOT_BITS * LEVELS as u32))
+            {
+                for timer in std::mem::take(&mut self.overflow) {
+                    self.place(timer);
+                }
+            }
+            for level in (1..LEVELS).rev() {
+                if self
+                    .current
+                    .is_multiple_of(1u64 << (SLOT_BITS * level as u32))
+                {
+                    self.cascade(level);
+                }
+            }
+            let slot = self.current as usize & (SLOTS - 1);
+            let timers = std::mem::take(&mut self.levels[0][slot]);
+            self.counts[0] -= timers.len();
+            for timer in timers {
+                if !self.cancelled.remove(&timer.handle.id) {
+                    self.due.push_back(timer);
+                }
+            }
+        }
+        let count = limit.min(self.due.len());
+        self.due
+            .drain(..count)
+            .map(|timer| (timer.key, timer.handle))
+            .collect()
+    }
+
+    /// Remove every timer.
+    pub(crate) fn clear(&mut self) {
+        for level in self.levels.iter_mut() {
+            level.iter_mut().for_each(Vec::clear);
+        }
+        self.counts = [0; LEVELS];
+        self.overflow.clear();
+        self.due.clear();
+        self.cancelled.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the keys which fire when advancing the wheel to the given number of milliseconds after its start.
+    fn advance_to(wheel: &mut TimerWheel<u64>, start: Instant, millis: u64) -> Vec<u64> {
+        wheel
+            .advance(start + Duration::from_millis(millis), usize::MAX)
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    #[test]
+    fn test_timer_wheel_fires_in_order() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start);
+        wheel.schedule(3, start + Duration::from_millis(30));
+        wheel.schedule(1, start + Duration::from_millis(10));
+        wheel.schedule(2, start + Duration::from_millis(20));
+        assert!(advance_to(&mut wheel, start, 5).is_empty());
+        assert_eq!(advance_to(&mut wheel, start, 25), vec![1, 2]);
+        assert_eq!(advance_to(&mut wheel, start, 30), vec![3]);
+    }
+
+    #[test]
+    fn test_timer_wheel_cascades_distant_timers() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start);
+        let distant = [100, 5_000, 300_000, 20_000_000];
+        for millis in distant {
+            wheel.schedule(millis, start + Duration::from_millis(millis));
+        }
+        for millis in distant {
+            assert!(advance_to(&mut wheel, start, millis - 1).is_empty());
+            assert_eq!(advance_to(&mut wheel, start, millis), vec![millis]);
+        }
+    }
+
+    #[test]
+    fn test_timer_wheel_past_timers_fire_on_next_tick() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start);
+        advance_to(&mut wheel, start, 50);
+        let handle = wheel.schedule(1, start);
+        assert!(wheel.fires_by(handle, start + Duration::from_millis(51)));
+        assert_eq!(advance_to(&mut wheel, start, 51), vec![1]);
+    }
+
+    #[test]
+    fn test_timer_wheel_advance_limit() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start);
+        for key in 0..5 {
+            wheel.schedule(key, start + Duration::from_millis(10 + key));
+        }
+        let at = start + Duration::from_millis(100);
+        let first: Vec<u64> = wheel.advance(at, 2).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(first, vec![0, 1]);
+        let rest: Vec<u64> = wheel.advance(at, 10).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(rest, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn test_timer_wheel_cancelled_timers_do_not_fire() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start);
+        let cancelled = wheel.schedule(1, start + Duration::from_millis(10));
+        wheel.schedule(2, start + Duration::from_millis(20));
+        let distant = wheel.schedule(3, start + Duration::from_millis(20_000_000));
+        wheel.cancel(cancelled);
+        wheel.cancel(distant);
+        assert_eq!(advance_to(&mut wheel, start, 30_000_000), vec![2]);
+        assert_eq!(wheel.len(), 0);
+        assert!(wheel.cancelled.is_empty());
+    }
+
+    #[test]
+    fn test_timer_wheel_stays_bounded_when_timers_are_cancelled() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start);
+        wheel.schedule(0, start + Duration::from_secs(60));
+        for key in 1..100_000 {
+            let handle = wheel.schedule(key, start + Duration::from_secs(60));
+            wheel.cancel(handle);
+        }
+        assert!(wheel.len() <= 2 * COMPACT_MIN);
+        assert!(wheel.cancelled.len() <= COMPACT_MIN);
+        assert_eq!(advance_to(&mut wheel, start, 60_000), vec![0]);
+    }
+
+    #[test]
+    fn test_timer_wheel_clear() {
+        let start = Instant::now();
+        let mut wheel = TimerWheel::new(start);
+        wheel.schedule(1, start + Duration::from_millis(10));
+        wheel.clear();
+        assert!(advance_to(&mut wheel, start, 100).is_empty());
+    }
+}
@@ -1,30 +1,309 @@
 use linked_hash_map::LinkedHashMap;
-use std::hash::Hash;
-use std::sync::{Arc, Mutex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Weak};
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
-use crate::cache::{Cache, CacheStats};
+use crate::cache::eviction::{EvictionEvent, EvictionQueue, EvictionReason, EVICTION_EVENT_BOUND};
+#[cfg(feature = "async")]
+use crate::cache::single_flight::AsyncSingleFlight;
+use crate::cache::single_flight::SingleFlight;
+#[cfg(feature = "persistence")]
+use crate::cache::snapshot::{read_from_path, write_to_path};
+use crate::cache::snapshot::{CacheSnapshot, SnapshotEntry};
+use crate::cache::sync::{Condvar, Mutex};
+pub use crate::cache::time::ExpiryPolicy;
+use crate::cache::time::{Clock, SystemClock};
+use crate::cache::timer_wheel::{TimerHandle, TimerWheel};
+use crate::cache::{
+    retain_linked_hash_map, swap_if_equal, update_in_place, Cache, CacheStats, StatsCounters,
+};
+#[cfg(feature = "persistence")]
+use serde::{de::DeserializeOwned, Serialize};
+#[cfg(feature = "persistence")]
+use std::{io, path::Path};
 
-/// An internal struct of the TTL cache for storing data along with its expiry time.
+/// An internal struct of the TTL cache for storing data along with its expiry time and the TTL used to extend it.
+///
+/// `expiry` is when the item expires, the earlier of `ttl_expiry` and the end of its time-to-idle if the cache has one. `ttl` includes the jitter the item was given and `base_ttl` is the TTL it was set with before that, so a reload can be jittered afresh. `timer` is the timer wheel entry which fires no later than `expiry`, none for an item set without expiry. `cost` is how long the value took to compute, used for probabilistic early expiration.
 #[derive(Clone)]
 struct DataWithLifetime<V> {
     data: Arc<V>,
     expiry: Instant,
+    ttl_expiry: Instant,
+    ttl: Duration,
+    base_ttl: Duration,
+    timer: Option<TimerHandle>,
+    cost: Duration,
+}
+
+impl<V> DataWithLifetime<V> {
+    /// Check whether the item was set without expiry and has no time-to-idle which ends sooner.
+    fn never_expires(&self) -> bool {
+        self.base_ttl == NO_EXPIRY && self.expiry == self.ttl_expiry
+    }
+}
+
+/// Get when an item expires given the expiry from its TTL, it expires sooner if the cache has a time-to-idle which ends first.
+fn idle_expiry(ttl_expiry: Instant, time_to_idle: Option<Duration>, now: Instant) -> Instant {
+    match time_to_idle {
+        Some(time_to_idle) => ttl_expiry.min(now + time_to_idle),
+        None => ttl_expiry,
+    }
+}
+
+/// Schedule the timer for an item which expires at `expiry`. An item set without expiry gets no timer so it never waits in the wheel, anything more than half of `NO_EXPIRY` away counts so an item restored from a snapshot is recognised too.
+fn schedule_timer<K>(
+    timers: &mut TimerWheel<K>,
+    key: K,
+    expiry: Instant,
+    now: Instant,
+) -> Option<TimerHandle> {
+    if expiry.saturating_duration_since(now) > NO_EXPIRY / 2 {
+        return None;
+    }
+    Some(timers.schedule(key, expiry))
+}
+
+/// Get a random duration of up to `max` from the seeded generator if there is one, or the thread local generator otherwise.
+fn random_duration(rng: &mut Option<StdRng>, max: Duration) -> Duration {
+    let max = max.as_nanos() as u64;
+    Duration::from_nanos(match rng {
+        Some(rng) => rng.random_range(0..=max),
+        None => rand::rng().random_range(0..=max),
+    })
+}
+
+/// Get a random number in [0, 1) from the seeded generator if there is one, or the thread local generator otherwise.
+fn random_fraction(rng: &mut Option<StdRng>) -> f64 {
+    match rng {
+        Some(rng) => rng.random(),
+        None => rand::rng().random(),
+    }
+}
+
+/// The TTL used by a TTLCacheBuilder if none is set, by `TTLCache::default` and by `from_snapshot` for a snapshot without one.
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// The capacity used by a TTLCacheBuilder if none is set, and by `TTLCache::default`.
+const DEFAULT_CAPACITY: u64 = 1000;
+
+/// The TTL given to an item set with `set_with_ttl(key, value, None)`, far enough away that the item never expires in practice while expiry times stay ordinary `Instant`s. An item with this TTL is reported as having no expiry by `remaining_ttl` and in snapshots.
+const NO_EXPIRY: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// The most expired items a `set` removes before inserting, so a set after a mass expiry does not hold the lock while every expired item is removed. The rest are left to the background thread, to later sets, or to `cleanup`.
+const MAX_PURGE_PER_SET: usize = 16;
+
+/// The most refresh-ahead reloads which run at once, each on its own worker thread. Further reloads wait in a queue until a worker is free.
+const MAX_REFRESH_WORKERS: usize = 4;
+
+/// A builder for a TTLCache which also configures the background thread that removes expired items.
+///
+/// + `ttl` is the amount of time an item is stored before it expires, 60 seconds by default.
+/// + `capacity` is the maximum number of items, 1000 by default.
+/// + `expiry_policy` decides whether reading an item extends its expiry, `ExpiryPolicy::Sliding` by default.
+/// + `check_interval` is how often the background thread removes expired items, by default it is the TTL or the time-to-idle if that is shorter. A zero interval disables the thread so expired items are only removed when they are accessed.
+/// + `jitter` is the maximum random delay added to each check interval, zero by default. Spreading the checks of many caches avoids them all locking at the same moment.
+/// + `time_to_idle` expires items which have not been read for the given time even if their TTL has not elapsed, none by default.
+/// + `max_evictions_per_cycle` is the most expired items the background thread and `shrink_to` remove before releasing the lock, unlimited by default. They keep removing batches until no expired items are left, so other threads can use the cache between batches during a mass expiry. A `set` removes at most 16 expired items, or this limit if it is lower.
+/// + `seed` seeds the random numbers used for jitter and probabilistic early expiration so they are reproducible, by default the thread local generator is used.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, TTLCacheBuilder};
+/// use std::time::Duration;
+///
+/// let cache = TTLCacheBuilder::default()
+///     .ttl(Duration::from_secs(1))
+///     .capacity(100)
+///     .build::<&str, String>();
+///
+/// cache.set("key", "value".to_string());
+/// assert_eq!(*cache.get(&"key").unwrap(), "value".to_string());
+/// ```
+#[derive(Debug, Clone)]
+pub struct TTLCacheBuilder {
+    ttl: Duration,
+    capacity: u64,
+    expiry_policy: ExpiryPolicy,
+    check_interval: Option<Duration>,
+    jitter: Duration,
+    time_to_idle: Option<Duration>,
+    max_evictions_per_cycle: Option<u64>,
+    seed: Option<u64>,
+}
+
+impl Default for TTLCacheBuilder {
+    fn default() -> Self {
+        TTLCacheBuilder {
+            ttl: DEFAULT_TTL,
+            capacity: DEFAULT_CAPACITY,
+            expiry_policy: ExpiryPolicy::default(),
+            check_interval: None,
+            jitter: Duration::ZERO,
+            time_to_idle: None,
+            max_evictions_per_cycle: None,
+            seed: None,
+        }
+    }
+}
+
+impl TTLCacheBuilder {
+    /// Create a new TTLCacheBuilder with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the time-to-live of items in the cache.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set the maximum number of items in the cache.
+    pub fn capacity(mut self, capacity: u64) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Set whether reading an item extends its expiry.
+    pub fn expiry_policy(mut self, expiry_policy: ExpiryPolicy) -> Self {
+        self.expiry_policy = expiry_policy;
+        self
+    }
+
+    /// Expire items which have not been read for `time_to_idle`, as well as when their TTL elapses.
+    ///
+    /// Whichever expiry is earliest wins. With `ExpiryPolicy::Fixed` this gives an item both an expire-after-write limit from the TTL and an expire-after-access limit from the time-to-idle. Under the sliding policy both are extended by every read, so the shorter of the two applies.
+    pub fn time_to_idle(mut self, time_to_idle: Duration) -> Self {
+        self.time_to_idle = Some(time_to_idle);
+        self
+    }
+
+    /// Set how often the background thread removes expired items, a zero interval disables the thread. Setting an item which expires sooner than the next check brings the check forward.
+    pub fn check_interval(mut self, check_interval: Duration) -> Self {
+        self.check_interval = Some(check_interval);
+        self
+    }
+
+    /// Set the maximum random delay added to each check interval.
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the most expired items the background thread removes while holding the lock, it releases the lock between batches until every expired item is removed. A limit of zero is treated as one.
+    pub fn max_evictions_per_cycle(mut self, max_evictions_per_cycle: u64) -> Self {
+        self.max_evictions_per_cycle = Some(max_evictions_per_cycle);
+        self
+    }
+
+    /// Seed the random numbers of the cache and its background thread, so the check intervals and early expirations are the same on every run given the same sequence of operations.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Build the TTLCache, starting its background thread unless the check interval is zero.
+    pub fn build<K, V>(self) -> TTLCache<K, V>
+    where
+        K: Eq + Hash + Clone + Send + 'static,
+        V: Send + Sync + 'static,
+    {
+        self.build_with_hasher(RandomState::new())
+    }
+
+    /// Build the TTLCache with a custom hasher for the keys, starting its background thread unless the check interval is zero.
+    pub fn build_with_hasher<K, V, S>(self, hasher: S) -> TTLCache<K, V, S>
+    where
+        K: Eq + Hash + Clone + Send + 'static,
+        V: Send + Sync + 'static,
+        S: BuildHasher + Send + Sync + 'static,
+    {
+        let mut cache = TTLCache::with_expiry_policy_and_hasher(
+            self.ttl,
+            self.capacity,
+            self.expiry_policy,
+            hasher,
+        );
+        {
+            let mut inner = cache.inner.lock();
+            inner.time_to_idle = self.time_to_idle;
+            inner.rng = self.seed.map(StdRng::seed_from_u64);
+        }
+        let default_interval = match self.time_to_idle {
+            Some(time_to_idle) => self.ttl.min(time_to_idle),
+            None => self.ttl,
+        };
+        let check_interval = self.check_interval.unwrap_or(default_interval);
+        if !check_interval.is_zero() {
+            let batch = self
+                .max_evictions_per_cycle
+                .map_or(usize::MAX, |max| max.max(1) as usize);
+            let rng = self.seed.map(StdRng::seed_from_u64);
+            cache.spawn_reaper(check_interval, self.jitter, batch, rng);
+        }
+        cache
+    }
+}
+
+/// A loader which recomputes the value of a key for refresh-ahead.
+type Loader<K, V> = Arc<dyn Fn(&K) -> V + Send + Sync>;
+
+/// A refresh-ahead reload waiting for a worker: the key, the loader and the TTL the item it replaces was set with, before jitter.
+type RefreshJob<K, V> = (K, Loader<K, V>, Duration);
+
+/// The refresh-ahead settings of a TTLCache.
+struct RefreshAhead<K, V> {
+    window: Duration,
+    loader: Loader<K, V>,
 }
 
 /// The inner data structure for the TTLCache.
-struct TTLCacheInner<K, V> {
+struct TTLCacheInner<K, V, S> {
     ttl: Duration,
+    expiry_policy: ExpiryPolicy,
+    time_to_idle: Option<Duration>,
+    ttl_jitter: Duration,
+    rng: Option<StdRng>,
     capacity: u64,
-    key_value_map: LinkedHashMap<K, DataWithLifetime<V>>,
-    hits: u64,
-    misses: u64,
+    key_value_map: LinkedHashMap<K, DataWithLifetime<V>, S>,
+    eviction_queue: EvictionQueue<K, V>,
+    evictions: u64,
+    reaper_signal: Option<Arc<ReaperSignal>>,
+    /// The most expired items removed each time the lock is taken by the background thread or `shrink_to`.
+    purge_batch: usize,
+    timers: TimerWheel<K>,
+    refresh: Option<RefreshAhead<K, V>>,
+    early_expiration_beta: Option<f64>,
+    refreshing: HashSet<K>,
+    refresh_queue: VecDeque<RefreshJob<K, V>>,
+    /// The number of refresh-ahead worker threads which are running, at most `MAX_REFRESH_WORKERS`.
+    refresh_workers: usize,
+    clock: Arc<dyn Clock>,
 }
 
 /// TTLCache is a cache that uses adds a time-to-live (TTL) to each item.
 ///
 /// This cache will automatically evict items that have expired. The TTL is set when the item is added to the cache. If the cache is at capacity and a new item is added, the least recently accessed item is removed.
 ///
+/// Expired items are treated as missing and removed when they are accessed or when new items are set. A cache created with `TTLCacheBuilder` also runs a background thread which periodically removes every expired item, so memory is reclaimed for items which are never accessed again. The thread is stopped and joined when the cache is dropped.
+///
+/// By default reading an item extends its expiry, use `with_expiry_policy` and `ExpiryPolicy::Fixed` for items which must expire at a fixed time after they were set.
+///
+/// The time is read from a `Clock`, use `with_clock` and a `ManualClock` to test expiry without sleeping.
+///
+/// A listener set with `on_evict` is called with every item which leaves the cache and the reason it left.
+///
+/// With `refresh_ahead` a `get` which hits an item close to its expiry reloads it on a background worker thread while the current value is still returned, so frequently read keys do not miss when they expire. `probabilistic_early_expiration` instead lets a single reader miss shortly before the expiry and recompute the value itself.
+///
 /// All mutability is handled internally with a Mutex, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
 ///
 /// The TTLCache has additional parameters in its constructor compared to other caches.
@@ -48,180 +327,2409 @@ struct TTLCacheInner<K, V> {
 /// assert_eq!(*value.unwrap(), "value".to_string());
 /// println!("{:?}", cache.stats());
 /// ```
-pub struct TTLCache<K: Eq + Hash + Clone + Send + 'static, V: Send + Sync + 'static> {
-    inner: Arc<Mutex<TTLCacheInner<K, V>>>,
+pub struct TTLCache<
+    K: Eq + Hash + Clone + Send + 'static,
+    V: Send + Sync + 'static,
+    S = RandomState,
+> {
+    inner: Arc<Mutex<TTLCacheInner<K, V, S>>>,
+    counters: StatsCounters,
+    in_flight: SingleFlight<K, V>,
+    #[cfg(feature = "async")]
+    in_flight_async: AsyncSingleFlight<K, V>,
+    reaper: Option<Reaper>,
+}
+
+/// The state shared between a TTLCache and its background thread.
+struct ReaperState {
+    /// When the thread next removes expired items.
+    deadline: Instant,
+    check_interval: Duration,
+    jitter: Duration,
+    rng: Option<StdRng>,
+    shutdown: bool,
+}
+
+impl ReaperState {
+    /// Get the time to wait before the next check, the check interval plus a random delay of up to the jitter.
+    fn next_delay(&mut self) -> Duration {
+        self.check_interval + random_duration(&mut self.rng, self.jitter)
+    }
+}
+
+/// Wakes the background thread of a TTLCache, either to stop it or to bring its next check forward.
+struct ReaperSignal {
+    state: Mutex<ReaperState>,
+    condvar: Condvar,
+}
+
+impl ReaperSignal {
+    /// Create a new ReaperSignal which checks every `check_interval` plus a random delay of up to `jitter`, drawn from `rng` if it is given.
+    fn new(check_interval: Duration, jitter: Duration, rng: Option<StdRng>) -> Self {
+        let mut state = ReaperState {
+            deadline: Instant::now(),
+            check_interval,
+            jitter,
+            rng,
+            shutdown: false,
+        };
+        let delay = state.next_delay();
+        state.deadline += delay;
+        ReaperSignal {
+            state: Mutex::new(state),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Block until the deadline has passed and then schedule the following check, returning false if the thread should stop instead.
+    ///
+    /// The following check is scheduled under the same lock so a `rearm` which happens while expired items are being removed is not lost.
+    fn wait(&self) -> bool {
+        let mut state = self.state.lock();
+        loop {
+            if state.shutdown {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= state.deadline {
+                state.deadline = now + state.next_delay();
+                return true;
+            }
+            let timeout = state.deadline - now;
+            state = self.condvar.wait_timeout(state, timeout);
+        }
+    }
+
+    /// Bring the next check forward to the given time if it is sooner than the current one, so an item which expires before the next check is still removed promptly.
+    fn rearm(&self, deadline: Instant) {
+        let mut state = self.state.lock();
+        if deadline < state.deadline {
+            state.deadline = deadline;
+            self.condvar.notify_one();
+        }
+    }
+
+    /// Change the check interval, the next check is brought forward if it would otherwise wait longer than the new interval.
+    fn set_check_interval(&self, check_interval: Duration) {
+        let mut state = self.state.lock();
+        state.check_interval = check_interval;
+        let deadline = Instant::now() + check_interval;
+        if deadline < state.deadline {
+            state.deadline = deadline;
+            self.condvar.notify_one();
+        }
+    }
+
+    /// Change the maximum random delay added to each check interval, this applies from the check after the next one.
+    fn set_jitter(&self, jitter: Duration) {
+        self.state.lock().jitter = jitter;
+    }
+
+    /// Wake the thread and tell it to stop.
+    fn shut_down(&self) {
+        self.state.lock().shutdown = true;
+        self.condvar.notify_one();
+    }
+}
+
+/// A handle to the background thread of a TTLCache which removes expired items.
+struct Reaper {
+    signal: Arc<ReaperSignal>,
+    handle: JoinHandle<()>,
+}
+
+impl Reaper {
+    /// Signal the thread to exit and wait for it to finish.
+    fn stop(self) {
+        self.signal.shut_down();
+        // the last reference to a cache can be dropped by its own eviction listener on the reaper thread, which must not wait for itself
+        if self.handle.thread().id() != thread::current().id() {
+            let _ = self.handle.join();
+        }
+    }
 }
 
 impl<K: Eq + Hash + Clone + Send + 'static, V: Send + Sync + 'static> TTLCache<K, V> {
-    /// Create a new TTLCache with the given time-to-live (TTL), check interval, jitter, and capacity.
+    /// Create a new TTLCache with the given time-to-live (TTL) and capacity, use `TTLCacheBuilder` to configure the other settings.
     /// + The TTL is the amount of time an item will be stored in the cache before it is evicted.
     /// + The capacity is the maximum number of items that can be stored in the cache.
     pub fn new(ttl: Duration, capacity: u64) -> Self {
+        Self::with_expiry_policy(ttl, capacity, ExpiryPolicy::Sliding)
+    }
+
+    /// Create a new TTLCache with the given time-to-live (TTL), capacity and policy for whether reading an item extends its expiry.
+    pub fn with_expiry_policy(ttl: Duration, capacity: u64, expiry_policy: ExpiryPolicy) -> Self {
+        Self::with_expiry_policy_and_hasher(ttl, capacity, expiry_policy, RandomState::new())
+    }
+
+    /// Create a new TTLCache which never starts a background thread, for short lived programs and targets which cannot spawn threads.
+    ///
+    /// Expired items are removed when they are accessed, when new items are set or by calling `cleanup`. Each item set in the cache lives for its TTL plus a random extra time of up to `jitter`, so items set together do not all expire at the same moment.
+    pub fn new_lazy(ttl: Duration, jitter: Duration, capacity: u64) -> Self {
+        let cache = Self::new(ttl, capacity);
+        cache.inner.lock().ttl_jitter = jitter;
+        cache
+    }
+
+    /// Create a new TTLCache like `new_lazy` whose per-item jitter is drawn from a random number generator seeded with `seed`, caches created with the same seed give the same TTLs given the same sequence of operations.
+    pub fn new_lazy_with_seed(ttl: Duration, jitter: Duration, capacity: u64, seed: u64) -> Self {
+        let cache = Self::new_lazy(ttl, jitter, capacity);
+        cache.inner.lock().rng = Some(StdRng::seed_from_u64(seed));
+        cache
+    }
+
+    /// Create a new TTLCache with the given time-to-live (TTL) and capacity which reads the time from `clock`, for example a `ManualClock` in tests.
+    ///
+    /// Every expiry is decided by the clock. A background thread started by `TTLCacheBuilder` still sleeps for real time between checks, so with a `ManualClock` expired items are removed when they are accessed.
+    pub fn with_clock(ttl: Duration, capacity: u64, clock: Arc<dyn Clock>) -> Self {
+        let cache = Self::new(ttl, capacity);
+        let mut inner = cache.inner.lock();
+        inner.timers = TimerWheel::new(clock.now());
+        inner.clock = clock;
+        drop(inner);
+        cache
+    }
+}
+
+impl<
+        K: Eq + Hash + Clone + Send + 'static,
+        V: Send + Sync + 'static,
+        S: BuildHasher + Send + Sync + 'static,
+    > TTLCache<K, V, S>
+{
+    /// Create a new TTLCache with the given time-to-live (TTL) and capacity which hashes keys with the given hasher, for example a faster hasher for small keys.
+    pub fn with_hasher(ttl: Duration, capacity: u64, hasher: S) -> Self {
+        Self::with_expiry_policy_and_hasher(ttl, capacity, ExpiryPolicy::Sliding, hasher)
+    }
+
+    /// Create a new TTLCache with the given time-to-live (TTL), capacity, expiry policy and hasher.
+    pub fn with_expiry_policy_and_hasher(
+        ttl: Duration,
+        capacity: u64,
+        expiry_policy: ExpiryPolicy,
+        hasher: S,
+    ) -> Self {
         let inner = Arc::new(Mutex::new(TTLCacheInner {
             ttl,
+            expiry_policy,
+            time_to_idle: None,
+            ttl_jitter: Duration::ZERO,
+            rng: None,
             capacity,
-            key_value_map: LinkedHashMap::new(),
-            hits: 0,
-            misses: 0,
+            key_value_map: LinkedHashMap::with_hasher(hasher),
+            eviction_queue: EvictionQueue::new(),
+            evictions: 0,
+            reaper_signal: None,
+            purge_batch: usize::MAX,
+            timers: TimerWheel::new(Instant::now()),
+            refresh: None,
+            early_expiration_beta: None,
+            refreshing: HashSet::new(),
+            refresh_queue: VecDeque::new(),
+            refresh_workers: 0,
+            clock: Arc::new(SystemClock),
         }));
 
-        TTLCache { inner }
+        TTLCache {
+            inner,
+            counters: StatsCounters::new(),
+            in_flight: SingleFlight::new(),
+            #[cfg(feature = "async")]
+            in_flight_async: AsyncSingleFlight::new(),
+            reaper: None,
+        }
     }
 
-    /// Enforce the capacity of the cache by removing the least recently accessed item if the cache is at capacity.
-    fn enforce_capacity(inner: &mut TTLCacheInner<K, V>) {
-        if inner.key_value_map.len() as u64 >= inner.capacity {
-            if let Some(key) = inner.key_value_map.keys().next().cloned() {
-                inner.key_value_map.remove(&key);
-            }
+    /// Start a background thread which removes expired items every `check_interval` plus a random delay of up to `jitter`, drawn from `rng` if it is given, the thread is stopped when the cache is dropped.
+    ///
+    /// Setting an item which expires before the next check brings the check forward, so items are removed promptly even with a long check interval. At most `batch` timers are handled each time the lock is taken.
+    fn spawn_reaper(
+        &mut self,
+        check_interval: Duration,
+        jitter: Duration,
+        batch: usize,
+        rng: Option<StdRng>,
+    ) {
+        let signal = Arc::new(ReaperSignal::new(check_interval, jitter, rng));
+        {
+            let mut inner = self.inner.lock();
+            inner.reaper_signal = Some(signal.clone());
+            inner.purge_batch = batch;
         }
-    }
 
-    fn evict(inner: &mut TTLCacheInner<K, V>) {
-        let now = Instant::now();
-        while let Some((_, entry)) = inner.key_value_map.front() {
-            if entry.expiry < now {
-                inner.key_value_map.pop_front();
-            } else {
+        let inner = Arc::downgrade(&self.inner);
+        let thread_signal = signal.clone();
+        let handle = thread::spawn(move || loop {
+            if !thread_signal.wait() {
                 break;
             }
+            let Some(inner) = inner.upgrade() else {
+                break;
+            };
+            loop {
+                let mut guard = inner.lock();
+                let now = guard.clock.now();
+                let finished = Self::purge_expired(&mut guard, now, batch);
+                let evicted = guard.eviction_queue.take();
+                drop(guard);
+                evicted.notify();
+                if finished {
+                    break;
+                }
+            }
+        });
+        self.reaper = Some(Reaper { signal, handle });
+    }
+
+    /// Change the TTL given to items set from now on.
+    ///
+    /// Existing items keep their original expiry, and under the sliding policy a `get` keeps extending them by the TTL they were set with, until they are set again.
+    pub fn set_ttl(&self, ttl: Duration) {
+        self.inner.lock().ttl = ttl;
+    }
+
+    /// Change how often the background thread removes expired items, the next check is brought forward if the new interval ends sooner.
+    ///
+    /// This has no effect on a cache without a background thread, such as one created with `new` or `new_lazy`. A zero interval is ignored, since the thread can only be stopped by dropping the cache.
+    pub fn set_check_interval(&self, check_interval: Duration) {
+        if check_interval.is_zero() {
+            return;
+        }
+        if let Some(reaper) = &self.reaper {
+            reaper.signal.set_check_interval(check_interval);
         }
     }
-}
 
-impl<K: Eq + Hash + Clone + Send + Sync + 'static, V: Send + Sync + 'static> Cache<K, V>
-    for TTLCache<K, V>
-{
-    /// Get a value from the cache.
-    fn get(&self, key: &K) -> Option<Arc<V>> {
-        let now = Instant::now();
-        let (result, expired) = {
-            let mut inner = self.inner.lock().unwrap();
+    /// Change the maximum random delay added to each check interval of the background thread, this applies from the check after the next one. This has no effect on a cache without a background thread.
+    pub fn set_jitter(&self, jitter: Duration) {
+        if let Some(reaper) = &self.reaper {
+            reaper.signal.set_jitter(jitter);
+        }
+    }
+
+    /// Set a listener which is called with every item that leaves the cache and the reason it left, replacing any previous listener.
+    ///
+    /// The listener is called after the cache lock has been released, so it is free to use the cache.
+    pub fn on_evict<F>(&self, f: F)
+    where
+        F: Fn(&K, &Arc<V>, EvictionReason) + Send + Sync + 'static,
+    {
+        let mut inner = self.inner.lock();
+        inner.eviction_queue.set_listener(Arc::new(f));
+    }
+
+    /// Subscribe to the items which leave the cache from now on through a channel, as an alternative to `on_evict` for consumers which process evictions on another thread or task.
+    ///
+    /// Each event is the key, the value and the reason it left, including items removed by the background thread. Events are sent without blocking while the cache is locked, so a slow consumer cannot stall the cache: the channel holds up to 1024 events which have not been received and further events are dropped until there is room. Dropping the receiver unsubscribes it, and several receivers can be subscribed at once.
+    pub fn eviction_events(&self) -> Receiver<EvictionEvent<K, V>> {
+        let mut inner = self.inner.lock();
+        inner.eviction_queue.subscribe(EVICTION_EVENT_BOUND)
+    }
+
+    /// Reload items in the background when a `get` hits them within `refresh_window` of their expiry, replacing any previous loader.
+    ///
+    /// The current value is returned while `loader` runs on a worker thread without the cache lock held, then the new value is set with the TTL the item it replaces was set with, given new jitter rather than adding to the old. At most four reloads run at once, further reloads are queued and the workers exit once the queue is empty. Only one reload runs per key at a time, and the result is discarded if the key is removed or the cache is cleared while it runs. Under the sliding policy every hit extends the expiry, so only items which have not been read for most of their TTL are reloaded.
+    pub fn refresh_ahead<F>(&self, refresh_window: Duration, loader: F)
+    where
+        F: Fn(&K) -> V + Send + Sync + 'static,
+    {
+        let mut inner = self.inner.lock();
+        inner.refresh = Some(RefreshAhead {
+            window: refresh_window,
+            loader: Arc::new(loader),
+        });
+    }
+
+    /// Treat items as expired slightly before their expiry with a probability which rises as the expiry approaches, using the XFetch algorithm, so one reader recomputes a value ahead of the deadline instead of every reader missing at once.
+    ///
+    /// A `get` misses early if `now + cost * beta * -ln(r)` has passed the expiry, where `r` is a random number in (0, 1] and `cost` is how long the value took to compute. The cost is recorded by `get_or_compute`, `get_or_insert_async` and `set_with_cost`, items without a recorded cost never expire early. A larger `beta` recomputes earlier, 1.0 is the usual choice. An early miss is counted as a miss but leaves the item in the cache, so other readers keep getting the current value until it is replaced.
+    pub fn probabilistic_early_expiration(&self, beta: f64) {
+        let mut inner = self.inner.lock();
+        inner.early_expiration_beta = Some(beta);
+    }
+
+    /// Set a value in the cache along with how long it took to compute, which decides how early it may expire with probabilistic early expiration.
+    pub fn set_with_cost(&self, key: K, value: V, cost: Duration) -> Option<Arc<V>> {
+        self.insert_with_cost(key, Arc::new(value), cost)
+    }
+
+    /// Insert a value with the TTL of the cache and record how long it took to compute.
+    fn insert_with_cost(&self, key: K, value: Arc<V>, cost: Duration) -> Option<Arc<V>> {
+        self.update(|inner| {
             let ttl = inner.ttl;
-            if let Some(entry) = inner.key_value_map.get_refresh(key) {
-                if entry.expiry > now {
-                    entry.expiry = now + ttl;
-                    (Some(entry.data.clone()), false)
-                } else {
-                    (None, true)
-                }
-            } else {
-                (None, false)
+            let previous = Self::insert(inner, key.clone(), value, ttl);
+            if let Some(entry) = inner.key_value_map.get_mut(&key) {
+                entry.cost = cost;
             }
+            previous
+        })
+    }
+
+    /// Decide whether a live item should be treated as expired early, see `probabilistic_early_expiration`.
+    fn expires_early(inner: &mut TTLCacheInner<K, V, S>, key: &K, now: Instant) -> bool {
+        let Some(beta) = inner.early_expiration_beta else {
+            return false;
         };
+        let Some(entry) = inner.key_value_map.get(key) else {
+            return false;
+        };
+        if entry.cost.is_zero() || entry.expiry <= now {
+            return false;
+        }
+        let cost = entry.cost.as_secs_f64();
+        let remaining = (entry.expiry - now).as_secs_f64();
+        let r = 1.0 - random_fraction(&mut inner.rng);
+        cost * beta * -r.ln() >= remaining
+    }
 
-        // Update stats in a separate lock block
-        let mut inner = self.inner.lock().unwrap();
-        if result.is_some() {
-            inner.hits += 1;
-        } else {
-            inner.misses += 1;
-            if expired {
-                inner.key_value_map.remove(key);
+    /// Look up a key for a `get`, starting a refresh-ahead reload if one is due, and update the cache statistics. Returns the value and whether the key was treated as expired early.
+    fn read(&self, key: &K) -> (Option<Arc<V>>, bool) {
+        let (value, early, refresh) = self.update(|inner| {
+            let now = inner.clock.now();
+            if Self::expires_early(inner, key, now) {
+                return (None, true, false);
             }
+            let refresh = Self::start_refresh(inner, key, now);
+            (Self::lookup(inner, key, now), false, refresh)
+        });
+        if refresh {
+            Self::spawn_refresh_worker(Arc::downgrade(&self.inner));
+        }
+        (self.counters.record(value), early)
+    }
+
+    /// Queue a reload of a key if it is live, within the refresh window of its expiry and not already being reloaded. Returns whether a new worker should be spawned for the queue.
+    fn start_refresh(inner: &mut TTLCacheInner<K, V, S>, key: &K, now: Instant) -> bool {
+        let Some(refresh) = inner.refresh.as_ref() else {
+            return false;
+        };
+        let Some(entry) = inner.key_value_map.get(key) else {
+            return false;
+        };
+        if entry.expiry <= now || entry.expiry - now > refresh.window {
+            return false;
+        }
+        let job = (key.clone(), refresh.loader.clone(), entry.base_ttl);
+        if !inner.refreshing.insert(key.clone()) {
+            return false;
         }
+        inner.refresh_queue.push_back(job);
+        if inner.refresh_workers >= MAX_REFRESH_WORKERS {
+            return false;
+        }
+        inner.refresh_workers += 1;
+        true
+    }
+
+    /// Run queued reloads on a background thread until the queue is empty, setting each result unless the key was removed or the cache was cleared or dropped in the meantime.
+    fn spawn_refresh_worker(inner: Weak<Mutex<TTLCacheInner<K, V, S>>>) {
+        thread::spawn(move || loop {
+            let Some(cache) = inner.upgrade() else {
+                return;
+            };
+            let mut guard = cache.lock();
+            let Some((key, loader, ttl)) = guard.refresh_queue.pop_front() else {
+                guard.refresh_workers -= 1;
+                return;
+            };
+            if !guard.refreshing.contains(&key) {
+                continue;
+            }
+            drop(guard);
+            // the cache is not kept alive while the loader runs
+            drop(cache);
+            let value = panic::catch_unwind(AssertUnwindSafe(|| loader(&key)));
+            let Some(cache) = inner.upgrade() else {
+                return;
+            };
+            let mut guard = cache.lock();
+            if guard.refreshing.remove(&key) {
+                if let Ok(value) = value {
+                    Self::insert(&mut guard, key, Arc::new(value), ttl);
+                }
+            }
+            let evicted = guard.eviction_queue.take();
+            drop(guard);
+            evicted.notify();
+        });
+    }
+
+    /// Run `f` with the cache locked, then pass any items which left the cache to the eviction listener once the lock has been released.
+    fn update<R>(&self, f: impl FnOnce(&mut TTLCacheInner<K, V, S>) -> R) -> R {
+        let mut inner = self.inner.lock();
+        let result = f(&mut inner);
+        let evicted = inner.eviction_queue.take();
+        drop(inner);
+        evicted.notify();
         result
     }
 
-    /// Set a value in the cache.
-    fn set(&self, key: K, value: V) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        if !inner.key_value_map.contains_key(&key) {
-            Self::enforce_capacity(&mut inner);
+    /// Get a value from the cache, or compute it with `f`, insert it and return it if the key is missing or expired.
+    ///
+    /// Unlike `get_or_insert_with`, concurrent callers which miss on the same key are deduplicated: `f` runs once and every caller receives the same Arc. The cache lock is not held while `f` runs so other keys can be read and written in the meantime. The time `f` takes is recorded as the cost of the value for probabilistic early expiration.
+    pub fn get_or_compute<F: FnOnce() -> V>(&self, key: K, f: F) -> Arc<V>
+    where
+        K: Sync,
+    {
+        let (value, early) = self.read(&key);
+        if let Some(value) = value {
+            return value;
+        }
+        self.in_flight.run(&key, || {
+            // another caller may have finished computing the value since the miss above, an early miss must recompute the value it skipped
+            if !early {
+                if let Some(value) = self.peek(&key) {
+                    return value;
+                }
+            }
+            let start = Instant::now();
+            let value = Arc::new(f());
+            self.insert_with_cost(key.clone(), value.clone(), start.elapsed());
+            value
+        })
+    }
+
+    /// Get a value from the cache, or await the future returned by `f`, insert its output and return it if the key is missing or expired.
+    ///
+    /// Concurrent tasks which miss on the same key share a single computation: one task awaits its future and the others wait for its result without blocking their threads. The cache lock is never held across an await. The time the future takes is recorded as the cost of the value for probabilistic early expiration.
+    #[cfg(feature = "async")]
+    pub async fn get_or_insert_async<F, Fut>(&self, key: K, f: F) -> Arc<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = V>,
+        K: Sync,
+    {
+        let (value, early) = self.read(&key);
+        if let Some(value) = value {
+            return value;
         }
-        let expiry = Instant::now() + inner.ttl;
+        self.in_flight_async
+            .run(&key, || async {
+                // another task may have finished computing the value since the miss above, an early miss must recompute the value it skipped
+                if !early {
+                    if let Some(value) = self.peek(&key) {
+                        return value;
+                    }
+                }
+                let start = Instant::now();
+                let value = Arc::new(f().await);
+                self.insert_with_cost(key.clone(), value.clone(), start.elapsed());
+                value
+            })
+            .await
+    }
+
+    /// Set a value in the cache with its own TTL instead of the TTL the cache was created with, returning the previous value.
+    ///
+    /// The TTL is kept with the item, so under the sliding policy a `get` extends its expiry by this TTL rather than the cache wide one. A TTL of `None` gives an item which never expires, neither `get` nor the background thread remove it by time but it can still be evicted to make room or expire from a time-to-idle.
+    ///
+    /// Example:
+    /// ```
+    /// use arcache::{Cache, TTLCache};
+    /// use std::time::Duration;
+    ///
+    /// let cache = TTLCache::new(Duration::from_secs(60), 10);
+    /// cache.set_with_ttl("session", 1, Duration::from_secs(5));
+    /// cache.set_with_ttl("config", 2, None);
+    /// assert!(cache.contains_key(&"config"));
+    /// ```
+    pub fn set_with_ttl(
+        &self,
+        key: K,
+        value: V,
+        ttl: impl Into<Option<Duration>>,
+    ) -> Option<Arc<V>> {
+        let ttl = ttl.into().unwrap_or(NO_EXPIRY);
+        self.update(|inner| Self::insert(inner, key, Arc::new(value), ttl))
+    }
 
-        Self::evict(&mut inner);
+    /// Extend the expiry of a live item to its TTL from now without reading it, returning whether the key was present and had not expired.
+    ///
+    /// This applies under either expiry policy and does not update the cache statistics, so it suits keep-alive signals for keys which will be needed soon. The item also becomes the most recently used.
+    pub fn touch(&self, key: &K) -> bool {
+        let mut inner = self.inner.lock();
+        let now = inner.clock.now();
+        let time_to_idle = inner.time_to_idle;
+        match inner.key_value_map.get_refresh(key) {
+            Some(entry) if entry.expiry > now => {
+                entry.ttl_expiry = now + entry.ttl;
+                entry.expiry = idle_expiry(entry.ttl_expiry, time_to_idle, now);
+                true
+            }
+            _ => false,
+        }
+    }
 
+    /// Get how long a live item has left before it expires, or `None` if the key is missing, expired or was set without expiry. This does not extend the expiry or update the cache statistics.
+    pub fn remaining_ttl(&self, key: &K) -> Option<Duration> {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
         inner
             .key_value_map
-            .insert(
-                key,
-                DataWithLifetime {
-                    data: Arc::new(value),
-                    expiry,
-                },
-            )
-            .map(|entry| entry.data)
+            .get(key)
+            .filter(|entry| entry.expiry > now && !entry.never_expires())
+            .map(|entry| entry.expiry.saturating_duration_since(now))
     }
 
-    /// Remove a value from the cache.
-    fn remove(&self, key: &K) -> Option<Arc<V>> {
-        let mut inner = self.inner.lock().unwrap();
-        inner.key_value_map.remove(key).map(|entry| entry.data)
+    /// Get the mean age of the live items, measured from when each was set or its expiry last extended, for example to tell whether items are served soon after being set or linger until they expire when tuning the TTL. This is zero if there are no live items.
+    ///
+    /// Under the sliding policy a read extends the expiry, so the age of an item is the time since it was last read. This does not extend the expiry of the items or update the cache statistics.
+    pub fn average_age(&self) -> Duration {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        let (count, total) = inner
+            .key_value_map
+            .values()
+            .filter(|entry| entry.expiry > now)
+            .map(|entry| match entry.ttl_expiry.checked_sub(entry.ttl) {
+                Some(start) => now.saturating_duration_since(start),
+                None => Duration::ZERO,
+            })
+            .fold((0u128, 0u128), |(count, total), age| {
+                (count + 1, total + age.as_nanos())
+            });
+        if count == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos((total / count) as u64)
     }
 
-    /// Clear the cache, removing all data.
-    fn clear(&self) {
-        let mut inner = self.inner.lock().unwrap();
-        inner.key_value_map.clear();
+    /// Remove every item from the cache under a single lock and return them, ordered from least to most recently used. Expired items are removed but not returned.
+    ///
+    /// Unlike `iter` followed by `clear`, no item set by another thread in between can be lost. The eviction listener is told about each item as if it had been removed.
+    pub fn drain(&self) -> Vec<(K, Arc<V>)> {
+        self.update(|inner| {
+            inner.refreshing.clear();
+            inner.refresh_queue.clear();
+            let mut items = Vec::with_capacity(inner.key_value_map.len());
+            while let Some((key, entry)) = inner.key_value_map.pop_front() {
+                if let Some(value) = Self::evicted(inner, &key, entry, EvictionReason::Manual) {
+                    items.push((key, value));
+                }
+            }
+            inner.timers.clear();
+            items
+        })
     }
 
-    /// Get the cache statistics.
-    fn stats(&self) -> CacheStats {
-        let inner = self.inner.lock().unwrap();
-        CacheStats {
-            hits: inner.hits,
-            misses: inner.misses,
-            size: inner.key_value_map.len() as u64,
-            capacity: inner.capacity,
-        }
+    /// Remove every expired item now rather than waiting for it to be accessed or for the background thread, returning how many were removed.
+    ///
+    /// This is how caches created with `new_lazy` reclaim memory for items which are never accessed again, and it is also useful before reading the size of a cache or taking a snapshot. Removed items count as evictions and are reported to the eviction listener as expired.
+    pub fn cleanup(&self) -> u64 {
+        self.update(|inner| {
+            let evictions = inner.evictions;
+            let now = inner.clock.now();
+            Self::purge_expired(inner, now, usize::MAX);
+            inner.evictions - evictions
+        })
     }
 
-    /// Change the capacity of the cache, if the new capacity is smaller than the current size, the oldest items are removed. Because the TTL is the same for all items this is identical as the ones which expire soonest.
-    fn change_capacity(&self, capacity: u64) {
-        let mut inner = self.inner.lock().unwrap();
-        let old_capacity = inner.capacity;
-        inner.capacity = capacity;
+    /// Remove every expired item now like `cleanup`, returning the keys of the removed items ordered by when they expired, for example to invalidate them in downstream systems.
+    pub fn clear_expired(&self) -> Vec<K> {
+        let mut expired = self.update(|inner| {
+            let now = inner.clock.now();
+            let mut expired = Vec::new();
+            Self::purge_expired_with(inner, now, usize::MAX, |key, expiry| {
+                expired.push((expiry, key.clone()));
+            });
+            expired
+        });
+        expired.sort_by_key(|(expiry, _)| *expiry);
+        expired.into_iter().map(|(_, key)| key).collect()
+    }
+
+    /// Reserve room for at least `additional` more items, for example before a bulk insert so the map is not rehashed while the cache warms up. The capacity of the cache is unchanged.
+    pub fn reserve(&self, additional: usize) {
+        let mut inner = self.inner.lock();
+        inner.key_value_map.reserve(additional);
+    }
+
+    /// Release any memory the cache has allocated beyond what its current items need, for example after a large cache has been shrunk. The capacity of the cache is unchanged.
+    pub fn shrink_to_fit(&self) {
+        let mut inner = self.inner.lock();
+        inner.key_value_map.shrink_to_fit();
+    }
+
+    /// Get an exponentially weighted moving average of the hit ratio, which reflects recent lookups rather than the whole lifetime of the cache like `stats().hit_ratio()`, for example to alert on a sudden rise in misses. This is 0.0 if there have been no lookups.
+    pub fn recent_hit_ratio(&self) -> f64 {
+        self.counters.recent_hit_ratio()
+    }
+
+    /// Set the weight given to each new lookup in `recent_hit_ratio`, between 0.0 and 1.0. A larger decay reacts faster but is noisier, the default of 0.01 mostly reflects the last hundred or so lookups.
+    pub fn set_hit_ratio_decay(&self, decay: f64) {
+        self.counters.set_decay(decay);
+    }
 
-        while inner.key_value_map.len() as u64 > inner.capacity {
-            if let Some(key) = inner.key_value_map.keys().next().cloned() {
-                inner.key_value_map.remove(&key);
+    /// Remove expired items and then the least recently used items until there are at most `target_size`, returning how many were removed, for example to release memory when the system is under pressure.
+    ///
+    /// Unlike `change_capacity` the capacity is left unchanged, so the cache can grow back afterwards. Expired items are removed in batches of `max_evictions_per_cycle`, releasing the lock between batches like the background thread. Removed items count as evictions and are reported to the eviction listener.
+    pub fn shrink_to(&self, target_size: u64) -> u64 {
+        let mut removed = 0;
+        loop {
+            let (purged, finished) = self.update(|inner| {
+                let evictions = inner.evictions;
+                let now = inner.clock.now();
+                let finished = Self::purge_expired(inner, now, inner.purge_batch);
+                (inner.evictions - evictions, finished)
+            });
+            removed += purged;
+            if finished {
+                break;
             }
         }
+        removed
+            + self.update(|inner| {
+                let evictions = inner.evictions;
+                while inner.key_value_map.len() as u64 > target_size {
+                    Self::pop_front(inner);
+                }
+                inner.evictions - evictions
+            })
+    }
 
-        if capacity > old_capacity {
-            let additional = (capacity - old_capacity) as usize;
-            inner.key_value_map.reserve(additional);
+    /// Take an owned snapshot of the live items in the cache in recency order, along with the time each has left before it expires, which can be restored into another cache with `restore`.
+    ///
+    /// The values are shared as Arcs rather than cloned. Taking the snapshot does not extend the expiry of the items or affect the cache statistics.
+    pub fn snapshot(&self) -> CacheSnapshot<K, V> {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        CacheSnapshot {
+            capacity: inner.capacity,
+            ttl: Some(inner.ttl),
+            expiry_policy: inner.expiry_policy,
+            entries: inner
+                .key_value_map
+                .iter()
+                .filter(|(_, entry)| entry.expiry > now)
+                .map(|(key, entry)| {
+                    // an item set without expiry is written without one rather than with a lifetime of a century
+                    let (remaining, ttl) = if entry.base_ttl == NO_EXPIRY {
+                        (None, None)
+                    } else {
+                        (Some(entry.expiry - now), Some(entry.base_ttl))
+                    };
+                    SnapshotEntry {
+                        key: key.clone(),
+                        value: entry.data.clone(),
+                        remaining,
+                        ttl,
+                    }
+                })
+                .collect(),
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::thread;
-    use std::time::Duration;
+    /// Replace the items in the cache with those from a snapshot, preserving their recency order, for example to move warm data into a cache with a different capacity.
+    ///
+    /// The capacity, TTL and expiry policy of this cache are kept. The expiry of each item is recomputed as now plus the time it had left when the snapshot was taken. An item without one never expires if the snapshot was taken from a cache with a TTL, where it was set without expiry, and otherwise gets the full TTL. Like a newly set item it also expires once it has been idle for the time to idle of this cache. The current items are reported to the eviction listener as removed, and if the snapshot holds more items than the capacity the least recently used are evicted.
+    pub fn restore(&self, snapshot: CacheSnapshot<K, V>) {
+        self.update(|inner| {
+            Self::remove_all(inner);
+            let now = inner.clock.now();
+            for entry in snapshot.entries {
+                let never_expires =
+                    entry.remaining.is_none() && entry.ttl.is_none() && snapshot.ttl.is_some();
+                let entry_ttl = match entry.ttl {
+                    _ if never_expires => NO_EXPIRY,
+                    Some(ttl) => ttl,
+                    None => inner.ttl,
+                };
+                let ttl_expiry = now + entry.remaining.unwrap_or(entry_ttl);
+                let expiry = idle_expiry(ttl_expiry, inner.time_to_idle, now);
+                if let Some(signal) = &inner.reaper_signal {
+                    signal.rearm(expiry);
+                }
+                let timer = schedule_timer(&mut inner.timers, entry.key.clone(), expiry, now);
+                inner.key_value_map.insert(
+                    entry.key,
+                    DataWithLifetime {
+                        data: entry.value,
+                        expiry,
+                        ttl_expiry,
+                        ttl: entry_ttl,
+                        base_ttl: entry_ttl,
+                        timer,
+                        cost: Duration::ZERO,
+                    },
+                );
+            }
+            while inner.key_value_map.len() as u64 > inner.capacity {
+                Self::pop_front(inner);
+            }
+        });
+    }
 
-    #[test]
-    fn test_ttl_cache() {
-        let cache = TTLCache::new(Duration::from_secs(1), 2);
-        cache.set(1, 1);
-        cache.set(2, 2);
-        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
-        thread::sleep(Duration::from_secs(2));
-        assert_eq!(cache.get(&1), None);
-        assert_eq!(cache.get(&2), None);
+    /// Get a snapshot of the live items in the cache, ordered from least to most recently used. Expired items are excluded.
+    ///
+    /// The items are collected under a single lock so the lock is not held while the snapshot is iterated. Taking the snapshot does not extend the expiry of the items or affect the cache statistics.
+    pub fn iter(&self) -> Vec<(K, Arc<V>)> {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .iter()
+            .filter(|(_, entry)| entry.expiry > now)
+            .map(|(key, entry)| (key.clone(), entry.data.clone()))
+            .collect()
     }
 
-    #[test]
-    fn test_ttl_cache_change_capacity() {
-        let cache = TTLCache::new(Duration::from_secs(1), 2);
-        cache.set(1, 1);
-        cache.set(2, 2);
-        cache.change_capacity(1);
-        // Depending on insertion order, one key is evicted.
-        assert_eq!(cache.get(&1), None);
-        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+    /// Get a snapshot of the keys of the live items in the cache, ordered from least to most recently used. Expired items are excluded.
+    pub fn keys(&self) -> Vec<K> {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .iter()
+            .filter(|(_, entry)| entry.expiry > now)
+            .map(|(key, _)| key.clone())
+            .collect()
     }
 
-    #[test]
-    fn test_ttl_cache_clear() {
-        let cache = TTLCache::new(Duration::from_secs(1), 2);
-        cache.set(1, 1);
-        cache.set(2, 2);
-        cache.clear();
-        assert_eq!(cache.get(&1), None);
-        assert_eq!(cache.get(&2), None);
+    /// Get a snapshot of the values of the live items in the cache, ordered from least to most recently used. Expired items are excluded.
+    pub fn values(&self) -> Vec<Arc<V>> {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .values()
+            .filter(|entry| entry.expiry > now)
+            .map(|entry| entry.data.clone())
+            .collect()
+    }
+
+    /// Record an item which left the cache for the eviction listener and cancel its timer, an expired item is always reported as expired. Returns the value if the item had not expired.
+    fn evicted(
+        inner: &mut TTLCacheInner<K, V, S>,
+        key: &K,
+        entry: DataWithLifetime<V>,
+        reason: EvictionReason,
+    ) -> Option<Arc<V>> {
+        if let Some(timer) = entry.timer {
+            inner.timers.cancel(timer);
+        }
+        if entry.expiry <= inner.clock.now() {
+            inner
+                .eviction_queue
+                .push(key, entry.data, EvictionReason::Expired);
+            None
+        } else {
+            inner.eviction_queue.push(key, entry.data.clone(), reason);
+            Some(entry.data)
+        }
+    }
+
+    /// Remove every item, reporting each to the eviction listener.
+    fn remove_all(inner: &mut TTLCacheInner<K, V, S>) {
+        inner.refreshing.clear();
+        inner.refresh_queue.clear();
+        if inner.eviction_queue.is_listening() {
+            while let Some((key, entry)) = inner.key_value_map.pop_front() {
+                Self::evicted(inner, &key, entry, EvictionReason::Manual);
+            }
+        }
+        inner.key_value_map.clear();
+        inner.timers.clear();
+    }
+
+    /// Remove the least recently accessed item.
+    fn pop_front(inner: &mut TTLCacheInner<K, V, S>) {
+        if let Some((key, entry)) = inner.key_value_map.pop_front() {
+            inner.evictions += 1;
+            Self::evicted(inner, &key, entry, EvictionReason::Capacity);
+        }
+    }
+
+    /// Get the key of the live item which expires soonest, for example to log what is about to go. This looks at every item and does not extend the expiry of any item or update the cache statistics.
+    pub fn oldest_key(&self) -> Option<K> {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .iter()
+            .filter(|(_, entry)| entry.expiry > now)
+            .min_by_key(|(_, entry)| entry.expiry)
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Get the key of the live item which expires last. This looks at every item and does not extend the expiry of any item or update the cache statistics.
+    pub fn newest_key(&self) -> Option<K> {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .iter()
+            .filter(|(_, entry)| entry.expiry > now)
+            .max_by_key(|(_, entry)| entry.expiry)
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Make room for a new key by removing the least recently accessed item if the cache is full. This is only called before inserting a key which is not already present, so the cache holds up to `capacity` items.
+    fn enforce_capacity(inner: &mut TTLCacheInner<K, V, S>) {
+        if inner.key_value_map.len() as u64 >= inner.capacity {
+            Self::pop_front(inner);
+        }
+    }
+
+    /// Look up a key, extending its expiry under the sliding policy, an expired item is removed and treated as missing.
+    fn lookup(inner: &mut TTLCacheInner<K, V, S>, key: &K, now: Instant) -> Option<Arc<V>> {
+        let sliding = inner.expiry_policy == ExpiryPolicy::Sliding;
+        let time_to_idle = inner.time_to_idle;
+        let (result, expired) = match inner.key_value_map.get_refresh(key) {
+            Some(entry) if entry.expiry > now => {
+                if sliding {
+                    entry.ttl_expiry = now + entry.ttl;
+                }
+                entry.expiry = idle_expiry(entry.ttl_expiry, time_to_idle, now);
+                (Some(entry.data.clone()), false)
+            }
+            Some(_) => (None, true),
+            None => (None, false),
+        };
+
+        if expired {
+            if let Some(entry) = inner.key_value_map.remove(key) {
+                inner.evictions += 1;
+                Self::evicted(inner, key, entry, EvictionReason::Expired);
+            }
+        }
+        result
+    }
+
+    /// Insert a value which expires after the given TTL, making room for it if needed, and return the previous value. At most `MAX_PURGE_PER_SET` expired items are removed first, so the lock is held for a bounded time.
+    fn insert(
+        inner: &mut TTLCacheInner<K, V, S>,
+        key: K,
+        value: Arc<V>,
+        ttl: Duration,
+    ) -> Option<Arc<V>> {
+        if inner.capacity == 0 {
+            return None;
+        }
+        let now = inner.clock.now();
+        let limit = inner.purge_batch.min(MAX_PURGE_PER_SET);
+        Self::purge_expired(inner, now, limit);
+        if !inner.key_value_map.contains_key(&key) {
+            Self::enforce_capacity(inner);
+        }
+        let base_ttl = ttl;
+        let ttl = if inner.ttl_jitter.is_zero() {
+            ttl
+        } else {
+            ttl + random_duration(&mut inner.rng, inner.ttl_jitter)
+        };
+        let ttl_expiry = now + ttl;
+        let expiry = idle_expiry(ttl_expiry, inner.time_to_idle, now);
+        if let Some(signal) = &inner.reaper_signal {
+            signal.rearm(expiry);
+        }
+
+        // a replaced item keeps its timer if that fires first, the timer is moved on when it fires
+        let timer = match inner
+            .key_value_map
+            .get(&key)
+            .and_then(|existing| existing.timer)
+        {
+            Some(existing) if inner.timers.fires_by(existing, expiry) => Some(existing),
+            _ => schedule_timer(&mut inner.timers, key.clone(), expiry, now),
+        };
+        let cost = inner
+            .key_value_map
+            .get(&key)
+            .map_or(Duration::ZERO, |existing| existing.cost);
+        let entry = DataWithLifetime {
+            data: value,
+            expiry,
+            ttl_expiry,
+            ttl,
+            base_ttl,
+            timer,
+            cost,
+        };
+        if let Some(existing) = inner.key_value_map.get_refresh(&key) {
+            let mut previous = std::mem::replace(existing, entry);
+            // a kept timer belongs to the new value now, so it must not be cancelled with the old one
+            if previous.timer == timer {
+                previous.timer = None;
+            }
+            return Self::evicted(inner, &key, previous, EvictionReason::Replaced);
+        }
+        inner.key_value_map.insert(key, entry);
+        None
+    }
+
+    /// Remove every expired item by advancing the timer wheel, so only the buckets which have come due are visited rather than every item.
+    ///
+    /// A timer which no longer belongs to its item is skipped, and one for an item whose expiry was extended since it was scheduled is moved on to the new expiry. At most `limit` timers are handled, returns whether fewer were due so none are left.
+    fn purge_expired(inner: &mut TTLCacheInner<K, V, S>, now: Instant, limit: usize) -> bool {
+        Self::purge_expired_with(inner, now, limit, |_, _| {})
+    }
+
+    /// Remove every expired item like `purge_expired`, calling `on_removed` with the key and expiry of each item which is removed.
+    fn purge_expired_with(
+        inner: &mut TTLCacheInner<K, V, S>,
+        now: Instant,
+        limit: usize,
+        mut on_removed: impl FnMut(&K, Instant),
+    ) -> bool {
+        let fired = inner.timers.advance(now, limit);
+        let finished = fired.len() < limit;
+        for (key, timer) in fired {
+            let Some(entry) = inner.key_value_map.get_mut(&key) else {
+                continue;
+            };
+            if entry.timer != Some(timer) {
+                continue;
+            }
+            if entry.expiry > now {
+                entry.timer = schedule_timer(&mut inner.timers, key, entry.expiry, now);
+                continue;
+            }
+            if let Some(entry) = inner.key_value_map.remove(&key) {
+                inner.evictions += 1;
+                on_removed(&key, entry.expiry);
+                Self::evicted(inner, &key, entry, EvictionReason::Expired);
+            }
+        }
+        finished
+    }
+}
+
+impl<
+        K: Eq + Hash + Clone + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        S: BuildHasher + Send + Sync + 'static,
+    > fmt::Debug for TTLCache<K, V, S>
+{
+    /// Format the TTL, capacity, size and hit and miss counts of the cache, the items are not listed so neither keys nor values need to implement Debug.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ttl = self.inner.lock().ttl;
+        let stats = self.stats();
+        f.debug_struct("TTLCache")
+            .field("ttl", &ttl)
+            .field("capacity", &stats.capacity)
+            .field("size", &stats.size)
+            .field("hits", &stats.hits)
+            .field("misses", &stats.misses)
+            .finish()
+    }
+}
+
+impl<
+        K: Eq + Hash + Clone + Send + 'static,
+        V: Send + Sync + 'static,
+        S: BuildHasher + Default + Send + Sync + 'static,
+    > Default for TTLCache<K, V, S>
+{
+    /// Create a new TTLCache with a TTL of 60 seconds and a capacity of 1000 items, without a background thread.
+    fn default() -> Self {
+        Self::with_expiry_policy_and_hasher(
+            DEFAULT_TTL,
+            DEFAULT_CAPACITY,
+            ExpiryPolicy::default(),
+            S::default(),
+        )
+    }
+}
+
+impl<K: Eq + Hash + Clone + Send + 'static, V: Send + Sync + 'static, S> Drop
+    for TTLCache<K, V, S>
+{
+    /// Stop the background thread, if the cache has one.
+    fn drop(&mut self) {
+        if let Some(reaper) = self.reaper.take() {
+            reaper.stop();
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<
+        K: Eq + Hash + Clone + Send + 'static,
+        V: Send + Sync + 'static,
+        S: BuildHasher + Default + Send + Sync + 'static,
+    > TTLCache<K, V, S>
+{
+    /// Take a snapshot of the live items in the cache, along with the time each has left before it expires, which can be serialized and later restored with `from_snapshot`. This is the same as `snapshot`.
+    pub fn to_snapshot(&self) -> CacheSnapshot<K, V> {
+        self.snapshot()
+    }
+
+    /// Create a new TTLCache from a snapshot, the expiry of each item is recomputed as now plus the time it had left when the snapshot was taken.
+    ///
+    /// A snapshot without a TTL, for example one taken from an LRUCache created without one, gives the cache the default TTL of 60 seconds.
+    pub fn from_snapshot(snapshot: CacheSnapshot<K, V>) -> Self {
+        let ttl = snapshot.ttl.unwrap_or(DEFAULT_TTL);
+        let cache = Self::with_expiry_policy_and_hasher(
+            ttl,
+            snapshot.capacity,
+            snapshot.expiry_policy,
+            S::default(),
+        );
+        cache.restore(snapshot);
+        cache
+    }
+}
+
+#[cfg(feature = "persistence")]
+impl<
+        K: Eq + Hash + Clone + Send + 'static,
+        V: Send + Sync + 'static,
+        S: BuildHasher + Default + Send + Sync + 'static,
+    > TTLCache<K, V, S>
+{
+    /// Save a snapshot of the cache to a file which can be loaded with `load_from_path`, for example to keep a cache between runs of a program.
+    ///
+    /// The file starts with a magic header and a format version so files from an incompatible version of this crate are rejected on load.
+    pub fn save_to_path(&self, path: &Path) -> io::Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        write_to_path(path, self.to_snapshot())
+    }
+
+    /// Load a cache from a file written by `save_to_path`, preserving the recency order of the items. Items which expired since the file was written are skipped.
+    ///
+    /// Returns an `InvalidData` error if the file was written by a cache without a TTL.
+    pub fn load_from_path(path: &Path) -> io::Result<Self>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        let snapshot = read_from_path(path)?;
+        if snapshot.ttl.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the snapshot was written by a cache without a TTL",
+            ));
+        }
+        Ok(Self::from_snapshot(snapshot))
+    }
+}
+impl<
+        K: Eq + Hash + Clone + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        S: BuildHasher + Send + Sync + 'static,
+    > Cache<K, V> for TTLCache<K, V, S>
+{
+    /// Get a value from the cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        self.read(key).0
+    }
+
+    /// Get several values from the cache under a single lock, each key is checked for expiry individually and the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        self.update(|inner| {
+            let now = inner.clock.now();
+            keys.iter()
+                .map(|key| {
+                    let value = if Self::expires_early(inner, key, now) {
+                        None
+                    } else {
+                        Self::lookup(inner, key, now)
+                    };
+                    self.counters.record(value)
+                })
+                .collect()
+        })
+    }
+
+    /// Peek at a value in the cache without extending its expiry or updating the cache statistics, expired items are not returned.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .get(key)
+            .filter(|entry| entry.expiry > now)
+            .map(|entry| entry.data.clone())
+    }
+
+    /// Check whether a key is in the cache without extending its expiry or updating the cache statistics, expired items which have not been removed yet are reported as missing.
+    fn contains_key(&self, key: &K) -> bool {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .get(key)
+            .is_some_and(|entry| entry.expiry > now)
+    }
+
+    /// Set a value in the cache, returning the previous value if the key was present and had not expired.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        self.update(|inner| {
+            let ttl = inner.ttl;
+            Self::insert(inner, key, value, ttl)
+        })
+    }
+
+    /// Set a value under a single lock if the key is missing or expired, returning the value which is in the cache afterwards. An existing value keeps its expiry.
+    fn put_if_absent(&self, key: K, value: V) -> Arc<V> {
+        self.update(|inner| {
+            let now = inner.clock.now();
+            if let Some(entry) = inner
+                .key_value_map
+                .get(&key)
+                .filter(|entry| entry.expiry > now)
+            {
+                return entry.data.clone();
+            }
+            let value = Arc::new(value);
+            let ttl = inner.ttl;
+            Self::insert(inner, key, value.clone(), ttl);
+            value
+        })
+    }
+
+    /// Replace the value of a key under a single lock if it is present and has not expired, returning the previous value. The item gets a new expiry from the cache wide TTL as if it had been set.
+    fn replace(&self, key: &K, value: V) -> Option<Arc<V>> {
+        self.update(|inner| {
+            let now = inner.clock.now();
+            let present = inner
+                .key_value_map
+                .get(key)
+                .is_some_and(|entry| entry.expiry > now);
+            if !present {
+                return None;
+            }
+            let ttl = inner.ttl;
+            Self::insert(inner, key.clone(), Arc::new(value), ttl)
+        })
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        self.update(|inner| {
+            items
+                .into_iter()
+                .map(|(key, value)| {
+                    let ttl = inner.ttl;
+                    Self::insert(inner, key, Arc::new(value), ttl)
+                })
+                .collect()
+        })
+    }
+
+    /// Remove a value from the cache, an expired value is removed but not returned.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        self.update(|inner| {
+            inner.refreshing.remove(key);
+            let entry = inner.key_value_map.remove(key)?;
+            Self::evicted(inner, key, entry, EvictionReason::Manual)
+        })
+    }
+
+    /// Clear the cache, removing all data.
+    fn clear(&self) {
+        self.update(Self::remove_all);
+    }
+
+    /// Get the cache statistics, the size includes expired items which have not been removed yet unlike `len`.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.key_value_map.len() as u64,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of live items in the cache, expired items which have not been removed yet are not counted. This scans every item so it is more expensive than for other caches.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .values()
+            .filter(|entry| entry.expiry > now)
+            .count() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, if the new capacity is smaller than the current size the least recently accessed items are removed.
+    fn change_capacity(&self, capacity: u64) {
+        self.update(|inner| {
+            let old_capacity = inner.capacity;
+            inner.capacity = capacity;
+
+            while inner.key_value_map.len() as u64 > inner.capacity {
+                Self::pop_front(inner);
+            }
+
+            if capacity > old_capacity {
+                let additional = (capacity - old_capacity) as usize;
+                inner.key_value_map.reserve(additional);
+            }
+        });
+    }
+
+    /// Remove every item for which the predicate returns false, expired items are always removed.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        self.update(|inner| {
+            let now = inner.clock.now();
+            let removed = retain_linked_hash_map(&mut inner.key_value_map, |key, entry| {
+                entry.expiry > now && f(key, &entry.data)
+            });
+            for (key, entry) in removed {
+                Self::evicted(inner, &key, entry, EvictionReason::Manual);
+            }
+        });
+    }
+
+    /// Update a value in place without extending its expiry or updating the cache statistics, an expired item is not updated.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        let now = inner.clock.now();
+        let value = inner
+            .key_value_map
+            .get_mut(key)
+            .filter(|entry| entry.expiry > now)
+            .map(|entry| &mut entry.data);
+        update_in_place(value, f)
+    }
+
+    /// Swap the value of a live key if it equals `expected` without extending its expiry, the current value is returned on failure from under the same lock.
+    fn compare_and_swap(&self, key: &K, expected: &V, new: V) -> Result<(), Option<Arc<V>>>
+    where
+        V: Clone + PartialEq,
+    {
+        let mut inner = self.inner.lock();
+        let now = inner.clock.now();
+        let value = inner
+            .key_value_map
+            .get_mut(key)
+            .filter(|entry| entry.expiry > now)
+            .map(|entry| &mut entry.data);
+        swap_if_equal(value, expected, new)
+    }
+}
+
+impl<
+        K: Eq + Hash + Clone + Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        S: BuildHasher + Send + Sync + 'static,
+    > Extend<(K, V)> for TTLCache<K, V, S>
+{
+    /// Set every item under a single lock with the cache wide TTL, evicting the least recently accessed items as needed to stay within the capacity.
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.set_many(iter.into_iter().collect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::time::ManualClock;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ttl_cache() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(1), 2, clock.clone());
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_with_clock_sliding_expiry() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 2, clock.clone());
+        cache.set(1, 1);
+        cache.set(2, 2);
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        clock.advance(Duration::from_secs(6));
+        // 1 was read so its expiry was extended, 2 was not
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.peek(&2), None);
+        assert_eq!(cache.len(), 1);
+        clock.advance(Duration::from_secs(10));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_ttl_cache_change_capacity() {
+        let cache = TTLCache::new(Duration::from_secs(1), 2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.change_capacity(1);
+        // Depending on insertion order, one key is evicted.
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+    }
+
+    #[test]
+    fn test_ttl_cache_clear() {
+        let cache = TTLCache::new(Duration::from_secs(1), 2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.clear();
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_peek_does_not_extend_expiry() {
+        let cache = TTLCache::new(Duration::from_millis(200), 2);
+        cache.set(1, 1);
+        thread::sleep(Duration::from_millis(120));
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(1));
+        thread::sleep(Duration::from_millis(120));
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.stats().hits, 0);
+        assert_eq!(cache.stats().misses, 0);
+    }
+
+    #[test]
+    fn test_ttl_cache_contains_key() {
+        let cache = TTLCache::new(Duration::from_millis(100), 2);
+        cache.set(1, 1);
+        assert!(cache.contains_key(&1));
+        assert!(!cache.contains_key(&2));
+        thread::sleep(Duration::from_millis(150));
+        assert!(!cache.contains_key(&1));
+    }
+
+    #[test]
+    fn test_ttl_cache_len_excludes_expired() {
+        let cache = TTLCache::new(Duration::from_millis(100), 2);
+        assert!(cache.is_empty());
+        cache.set(1, 1);
+        assert_eq!(cache.len(), 1);
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(cache.len(), 0);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_ttl_cache_iter_excludes_expired() {
+        let cache = TTLCache::new(Duration::from_millis(100), 3);
+        cache.set(1, 1);
+        thread::sleep(Duration::from_millis(150));
+        cache.set(2, 2);
+        cache.set(3, 3);
+        let items: Vec<(i32, i32)> = cache.iter().into_iter().map(|(k, v)| (k, *v)).collect();
+        assert_eq!(items, vec![(2, 2), (3, 3)]);
+    }
+
+    #[test]
+    fn test_ttl_cache_keys_and_values() {
+        let cache = TTLCache::new(Duration::from_secs(1), 3);
+        cache.set(1, 10);
+        cache.set(2, 20);
+        assert_eq!(cache.keys(), vec![1, 2]);
+        let values: Vec<i32> = cache.values().into_iter().map(|v| *v).collect();
+        assert_eq!(values, vec![10, 20]);
+    }
+
+    #[test]
+    fn test_ttl_cache_retain() {
+        let cache = TTLCache::new(Duration::from_secs(1), 10);
+        for i in 0..10 {
+            cache.set(i, i);
+        }
+        cache.retain(|key, _| key % 2 != 0);
+        assert_eq!(cache.stats().size, 5);
+        assert_eq!(cache.keys(), vec![1, 3, 5, 7, 9]);
+    }
+
+    #[test]
+    fn test_ttl_cache_get_many() {
+        let cache = TTLCache::new(Duration::from_millis(50), 10);
+        cache.set(1, 1);
+        thread::sleep(Duration::from_millis(60));
+        cache.set(2, 2);
+        let values = cache.get_many(&[1, 2, 3]);
+        assert_eq!(
+            values
+                .into_iter()
+                .map(|v| v.map(|v| *v))
+                .collect::<Vec<_>>(),
+            vec![None, Some(2), None]
+        );
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.size, 1);
+    }
+
+    #[test]
+    fn test_ttl_cache_set_many() {
+        let cache = TTLCache::new(Duration::from_secs(1), 10);
+        let previous = cache.set_many(vec![(1, 1), (2, 2), (1, 3)]);
+        assert_eq!(
+            previous
+                .into_iter()
+                .map(|v| v.map(|v| *v))
+                .collect::<Vec<_>>(),
+            vec![None, None, Some(1)]
+        );
+        assert_eq!(cache.stats().size, 2);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(3));
+    }
+
+    #[test]
+    fn test_ttl_cache_set_with_ttl() {
+        let cache = TTLCache::new(Duration::from_secs(1), 10);
+        cache.set_with_ttl(1, 1, Duration::from_millis(50));
+        cache.set(2, 2);
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(1));
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+    }
+
+    #[test]
+    fn test_ttl_cache_fixed_expiry() {
+        let cache =
+            TTLCache::with_expiry_policy(Duration::from_millis(50), 10, ExpiryPolicy::Fixed);
+        cache.set(1, 1);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_sliding_expiry() {
+        let cache = TTLCache::new(Duration::from_millis(50), 10);
+        cache.set(1, 1);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+    }
+
+    #[test]
+    fn test_ttl_cache_on_evict() {
+        use std::sync::Mutex;
+
+        let cache = TTLCache::new(Duration::from_millis(50), 2);
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        cache.on_evict(move |key, value, reason| log.lock().unwrap().push((*key, **value, reason)));
+        cache.set(1, 1);
+        cache.set(1, 10);
+        cache.remove(&1);
+        cache.set(2, 2);
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            vec![
+                (1, 1, EvictionReason::Replaced),
+                (1, 10, EvictionReason::Manual),
+                (2, 2, EvictionReason::Expired),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ttl_cache_clear_expired_returns_keys_in_expiry_order() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(60), 10, clock.clone());
+        cache.set_with_ttl(1, 1, Duration::from_secs(3));
+        cache.set_with_ttl(2, 2, Duration::from_secs(1));
+        cache.set_with_ttl(3, 3, Duration::from_secs(2));
+        cache.set(4, 4);
+        assert!(cache.clear_expired().is_empty());
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(cache.clear_expired(), vec![2, 3, 1]);
+        assert_eq!(cache.keys(), vec![4]);
+        assert_eq!(cache.stats().evictions, 3);
+    }
+
+    #[test]
+    fn test_ttl_cache_set_without_expiry() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(1), 2, clock.clone());
+        cache.set_with_ttl(1, 1, None);
+        cache.set_with_ttl(2, 2, Duration::from_secs(1));
+        clock.advance(Duration::from_secs(365 * 24 * 60 * 60));
+        assert_eq!(cache.clear_expired(), vec![2]);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        // an item without expiry is still evicted to make room
+        cache.set(3, 3);
+        cache.set(4, 4);
+        assert_eq!(cache.peek(&1), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_average_age() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(60), 10, clock.clone());
+        assert_eq!(cache.average_age(), Duration::ZERO);
+        cache.set(1, 1);
+        clock.advance(Duration::from_secs(10));
+        cache.set(2, 2);
+        cache.set_with_ttl(3, 3, Duration::from_secs(5));
+        clock.advance(Duration::from_secs(10));
+        // 3 has expired so only 1 and 2 count
+        assert_eq!(cache.average_age(), Duration::from_secs(15));
+        // a sliding read makes the item new again
+        cache.get(&1);
+        assert_eq!(cache.average_age(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_ttl_cache_oldest_and_newest_key_by_expiry() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(60), 10, clock.clone());
+        assert_eq!(cache.oldest_key(), None);
+        cache.set(1, 1);
+        cache.set_with_ttl(2, 2, Duration::from_secs(5));
+        cache.set_with_ttl(3, 3, Duration::from_secs(120));
+        assert_eq!(cache.oldest_key(), Some(2));
+        assert_eq!(cache.newest_key(), Some(3));
+        clock.advance(Duration::from_secs(10));
+        // 2 has expired so 1 expires soonest
+        assert_eq!(cache.oldest_key(), Some(1));
+    }
+
+    #[test]
+    fn test_ttl_cache_timers_stay_bounded_under_churn() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(60), 10, clock.clone());
+        for i in 0..10_000u64 {
+            cache.set(i % 20, i);
+            cache.set(i % 20, i + 1);
+            cache.remove(&(i % 7));
+        }
+        // an item without expiry never waits in the wheel
+        cache.set_with_ttl(100, 100, None);
+        let timers = cache.inner.lock().timers.len();
+        assert!(
+            timers <= 2 * 64 + 10,
+            "{timers} timers for {} items",
+            cache.len()
+        );
+        clock.advance(Duration::from_secs(61));
+        cache.cleanup();
+        assert_eq!(cache.keys(), vec![100]);
+        assert_eq!(cache.inner.lock().timers.len(), 0);
+    }
+
+    #[test]
+    fn test_ttl_cache_set_purges_a_bounded_batch() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(1), 1000, clock.clone());
+        for i in 0..100 {
+            cache.set(i, i);
+        }
+        clock.advance(Duration::from_secs(2));
+        cache.set(1000, 1000);
+        assert_eq!(cache.stats().evictions, MAX_PURGE_PER_SET as u64);
+        assert_eq!(cache.cleanup(), 100 - MAX_PURGE_PER_SET as u64);
+        assert_eq!(cache.keys(), vec![1000]);
+    }
+
+    #[test]
+    fn test_ttl_cache_restore_applies_time_to_idle() {
+        let cache = TTLCache::new_lazy(Duration::from_secs(60), Duration::ZERO, 10);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        let restored = TTLCacheBuilder::new()
+            .ttl(Duration::from_secs(60))
+            .time_to_idle(Duration::from_millis(100))
+            .check_interval(Duration::ZERO)
+            .build();
+        restored.restore(cache.snapshot());
+        assert!(restored.remaining_ttl(&1).unwrap() <= Duration::from_millis(100));
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(restored.get(&1).map(|v| *v), Some(1));
+        thread::sleep(Duration::from_millis(60));
+        // 2 was never read after the restore so it has been idle for longer than the time to idle
+        assert_eq!(restored.get(&2), None);
+        assert_eq!(restored.get(&1).map(|v| *v), Some(1));
+    }
+
+    #[test]
+    fn test_ttl_cache_items_without_expiry_have_no_remaining_ttl() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 10, clock.clone());
+        cache.set_with_ttl(1, 1, None);
+        cache.set(2, 2);
+        assert_eq!(cache.remaining_ttl(&1), None);
+        assert!(cache.contains_key(&1));
+
+        let snapshot = cache.snapshot();
+        assert_eq!(snapshot.entries[0].remaining, None);
+        assert_eq!(snapshot.entries[0].ttl, None);
+        let restored = TTLCache::with_clock(Duration::from_secs(10), 10, clock.clone());
+        restored.restore(snapshot);
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(restored.get(&1).map(|v| *v), Some(1));
+        assert_eq!(restored.remaining_ttl(&1), None);
+        assert_eq!(restored.get(&2), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_restore_keeps_remaining_lifetime() {
+        let cache = TTLCache::new_lazy(Duration::from_secs(60), Duration::ZERO, 10);
+        cache.set(1, 1);
+        cache.set_with_ttl(2, 2, Duration::from_millis(50));
+        let restored = TTLCache::new_lazy(Duration::from_secs(1), Duration::ZERO, 1);
+        restored.set(3, 3);
+        restored.restore(cache.snapshot());
+        assert_eq!(restored.keys(), vec![2]);
+        assert_eq!(restored.stats().evictions, 1);
+        assert!(restored.remaining_ttl(&2).unwrap() <= Duration::from_millis(50));
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(restored.get(&2), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_ttl_cache_snapshot() {
+        let cache =
+            TTLCache::with_expiry_policy(Duration::from_millis(100), 10, ExpiryPolicy::Fixed);
+        cache.set(1, 1);
+        cache.set_with_ttl(2, 2, Duration::from_millis(20));
+        thread::sleep(Duration::from_millis(30));
+        let snapshot = cache.to_snapshot();
+        assert_eq!(snapshot.entries.len(), 1);
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let restored: TTLCache<i32, i32> =
+            TTLCache::from_snapshot(serde_json::from_str(&json).unwrap());
+        assert_eq!(restored.get(&1).map(|v| *v), Some(1));
+        assert_eq!(restored.get(&2), None);
+        // the remaining lifetime carries over rather than restarting from the full TTL
+        thread::sleep(Duration::from_millis(80));
+        assert_eq!(restored.get(&1), None);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn test_ttl_cache_save_and_load_skips_expired() {
+        let path = std::env::temp_dir().join(format!("arcache-ttl-{}", std::process::id()));
+        let cache = TTLCache::new(Duration::from_secs(60), 10);
+        cache.set(1, 1);
+        cache.set_with_ttl(2, 2, Duration::from_millis(20));
+        cache.save_to_path(&path).unwrap();
+        thread::sleep(Duration::from_millis(30));
+        let loaded = TTLCache::<i32, i32>::load_from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.keys(), vec![1]);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn test_ttl_cache_load_rejects_a_snapshot_without_ttl() {
+        use crate::LRUCache;
+
+        let path = std::env::temp_dir().join(format!("arcache-ttl-lru-{}", std::process::id()));
+        let lru = LRUCache::new(10);
+        lru.set(1, 1);
+        lru.save_to_path(&path).unwrap();
+        let error = TTLCache::<i32, i32>::load_from_path(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let cache = TTLCache::<i32, i32>::from_snapshot(lru.to_snapshot());
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert!(cache.remaining_ttl(&1).unwrap() <= DEFAULT_TTL);
+    }
+
+    #[test]
+    fn test_ttl_cache_stats_across_threads() {
+        let cache = Arc::new(TTLCache::new(Duration::from_secs(10), 10));
+        cache.set(1, 1);
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = cache.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        cache.get(&1);
+                        cache.get(&2);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 400);
+        assert_eq!(stats.misses, 400);
+    }
+
+    #[test]
+    fn test_ttl_cache_counts_evictions() {
+        let cache = TTLCache::new(Duration::from_millis(50), 2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        assert_eq!(cache.stats().evictions, 1);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.stats().evictions, 2);
+    }
+
+    #[test]
+    fn test_ttl_cache_builder() {
+        let cache = TTLCacheBuilder::default()
+            .ttl(Duration::from_millis(50))
+            .capacity(2)
+            .expiry_policy(ExpiryPolicy::Fixed)
+            .build();
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        assert_eq!(cache.stats().capacity, 2);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+    }
+
+    #[test]
+    fn test_ttl_cache_builder_reaper_removes_expired() {
+        use std::sync::Mutex;
+
+        let cache = TTLCacheBuilder::new()
+            .ttl(Duration::from_millis(50))
+            .check_interval(Duration::from_millis(20))
+            .jitter(Duration::from_millis(5))
+            .build();
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let listener_evicted = evicted.clone();
+        cache.on_evict(move |key, _, reason| listener_evicted.lock().unwrap().push((*key, reason)));
+        cache.set(1, 1);
+        cache.set(2, 2);
+        thread::sleep(Duration::from_millis(200));
+        // the items are removed without being accessed
+        assert_eq!(cache.stats().size, 0);
+        assert_eq!(cache.stats().evictions, 2);
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            vec![(1, EvictionReason::Expired), (2, EvictionReason::Expired)]
+        );
+    }
+
+    #[test]
+    fn test_ttl_cache_builder_zero_check_interval() {
+        let cache = TTLCacheBuilder::new()
+            .ttl(Duration::from_millis(20))
+            .check_interval(Duration::ZERO)
+            .build();
+        cache.set(1, 1);
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.stats().size, 1);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.stats().size, 0);
+    }
+
+    #[test]
+    fn test_ttl_cache_with_hasher() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        let cache = TTLCache::with_hasher(
+            Duration::from_millis(50),
+            2,
+            BuildHasherDefault::<DefaultHasher>::default(),
+        );
+        cache.set(1, 1);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_ttl_cache_get_or_insert_async_recomputes_expired() {
+        let cache = TTLCache::new(Duration::from_millis(50), 10);
+        assert_eq!(*cache.get_or_insert_async(1, || async { 1 }).await, 1);
+        assert_eq!(*cache.get_or_insert_async(1, || async { 2 }).await, 1);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(*cache.get_or_insert_async(1, || async { 3 }).await, 3);
+    }
+
+    #[test]
+    fn test_ttl_cache_drop_stops_reaper() {
+        let cache = TTLCacheBuilder::new()
+            .ttl(Duration::from_secs(600))
+            .build::<u64, u64>();
+        assert!(cache.reaper.is_some());
+        let started = Instant::now();
+        drop(cache);
+        // dropping joins the thread without waiting for the check interval
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_ttl_cache_reaper_rearms_for_sooner_expiry() {
+        let cache = TTLCacheBuilder::new()
+            .ttl(Duration::from_secs(600))
+            .build::<u64, u64>();
+        cache.set(1, 1);
+        cache.set_with_ttl(2, 2, Duration::from_millis(20));
+        thread::sleep(Duration::from_millis(200));
+        // the item with a short TTL is removed long before the ten minute check interval
+        assert_eq!(cache.stats().size, 1);
+        assert!(cache.contains_key(&1));
+    }
+
+    #[test]
+    fn test_ttl_cache_is_interchangeable_with_other_caches() {
+        fn fill_and_read<C: Cache<u64, u64>>(cache: &C) -> Option<u64> {
+            cache.set(1, 1);
+            cache.get(&1).map(|v| *v)
+        }
+
+        let ttl_cache = Arc::new(TTLCache::new(Duration::from_secs(10), 10));
+        let lru_cache = crate::LRUCache::new(10);
+        assert_eq!(fill_and_read(&*ttl_cache), Some(1));
+        assert_eq!(fill_and_read(&lru_cache), Some(1));
+
+        // get only needs a shared reference, so the cache can be read from several threads
+        let shared = ttl_cache.clone();
+        let read = thread::spawn(move || shared.get(&1).map(|v| *v));
+        assert_eq!(read.join().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_ttl_cache_as_trait_object() {
+        let caches: Vec<Box<dyn Cache<u64, u64>>> = vec![
+            Box::new(crate::LRUCache::new(10)),
+            Box::new(TTLCache::new(Duration::from_secs(10), 10)),
+        ];
+        for cache in &caches {
+            cache.set(1, 1);
+            assert!(cache.try_set(2, 2));
+            assert_eq!(cache.get_many(&[1, 3]), vec![Some(Arc::new(1)), None]);
+            assert_eq!(cache.remove(&2).map(|v| *v), Some(2));
+            assert_eq!(cache.len(), 1);
+            assert_eq!(cache.capacity(), 10);
+        }
+    }
+
+    #[test]
+    fn test_ttl_cache_set_returns_previous_value() {
+        let cache = TTLCache::new(Duration::from_millis(50), 10);
+        assert_eq!(cache.set(1, 1), None);
+        assert_eq!(cache.set(1, 2).map(|v| *v), Some(1));
+        thread::sleep(Duration::from_millis(100));
+        // an expired value is not handed back as the previous value
+        assert_eq!(cache.set(1, 3), None);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(3));
+    }
+
+    #[test]
+    fn test_ttl_cache_refresh_ahead() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        let cache =
+            TTLCache::with_expiry_policy(Duration::from_millis(300), 10, ExpiryPolicy::Fixed);
+        let loads = Arc::new(AtomicU64::new(0));
+        let counter = loads.clone();
+        cache.refresh_ahead(Duration::from_millis(200), move |key: &u64| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            thread::sleep(Duration::from_millis(50));
+            key * 10
+        });
+        cache.set(1, 1);
+        // outside the refresh window nothing is reloaded
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        thread::sleep(Duration::from_millis(150));
+        // inside the window the stale value is served and a single reload starts
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(loads.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(10));
+        // the reloaded value has a fresh expiry past the original one
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(10));
+    }
+
+    #[test]
+    fn test_ttl_cache_refresh_ahead_bounds_worker_threads() {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        let cache =
+            TTLCache::with_expiry_policy(Duration::from_millis(500), 20, ExpiryPolicy::Fixed);
+        let running = Arc::new(AtomicU64::new(0));
+        let most_running = Arc::new(AtomicU64::new(0));
+        let loads = Arc::new(AtomicU64::new(0));
+        let (counter, most, loaded) = (running.clone(), most_running.clone(), loads.clone());
+        cache.refresh_ahead(Duration::from_millis(500), move |key: &u64| {
+            most.fetch_max(counter.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            counter.fetch_sub(1, Ordering::SeqCst);
+            loaded.fetch_add(1, Ordering::SeqCst);
+            key * 10
+        });
+        for key in 0..12 {
+            cache.set(key, key);
+        }
+        // every item is inside the window so each get queues a reload
+        for key in 0..12 {
+            assert_eq!(cache.get(&key).map(|v| *v), Some(key));
+        }
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(loads.load(Ordering::SeqCst), 12);
+        assert!(most_running.load(Ordering::SeqCst) <= MAX_REFRESH_WORKERS as u64);
+        assert_eq!(cache.inner.lock().refresh_workers, 0);
+        for key in 0..12 {
+            assert_eq!(cache.peek(&key).map(|v| *v), Some(key * 10));
+        }
+    }
+
+    #[test]
+    fn test_ttl_cache_refresh_ahead_does_not_grow_the_ttl() {
+        let ttl = Duration::from_secs(1);
+        let jitter = Duration::from_secs(1);
+        let cache = TTLCache::new_lazy_with_seed(ttl, jitter, 10, 3);
+        cache.refresh_ahead(Duration::from_secs(10), |key: &u64| *key);
+        cache.set(1, 1);
+        for _ in 0..50 {
+            assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+            while !cache.inner.lock().refreshing.is_empty() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            let item_ttl = cache.inner.lock().key_value_map[&1].ttl;
+            assert!(item_ttl >= ttl && item_ttl <= ttl + jitter);
+        }
+    }
+
+    #[test]
+    fn test_ttl_cache_compute_skips_expired() {
+        let cache = TTLCache::new(Duration::from_millis(50), 2);
+        cache.set(1, 1);
+        assert!(cache.compute(&1, |value| *value += 1));
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(2));
+        thread::sleep(Duration::from_millis(100));
+        assert!(!cache.compute(&1, |value| *value += 1));
+    }
+
+    #[test]
+    fn test_ttl_cache_touch() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 2, clock.clone());
+        cache.set(1, 1);
+        clock.advance(Duration::from_secs(6));
+        assert!(cache.touch(&1));
+        assert!(!cache.touch(&2));
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(1));
+        clock.advance(Duration::from_secs(6));
+        assert!(!cache.touch(&1));
+        assert_eq!(cache.stats().hits, 0);
+    }
+
+    #[test]
+    fn test_ttl_cache_remaining_ttl() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 2, clock.clone());
+        cache.set(1, 1);
+        assert_eq!(cache.remaining_ttl(&1), Some(Duration::from_secs(10)));
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(cache.remaining_ttl(&1), Some(Duration::from_secs(6)));
+        assert_eq!(cache.remaining_ttl(&2), None);
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(cache.remaining_ttl(&1), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_zero_capacity_stores_nothing() {
+        let cache = TTLCache::new(Duration::from_secs(10), 0);
+        assert_eq!(cache.set(1, 1), None);
+        assert_eq!(cache.set(1, 2), None);
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.is_empty());
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.evictions, 0);
+    }
+
+    #[test]
+    fn test_ttl_cache_holds_capacity_items() {
+        let cache = TTLCache::new(Duration::from_secs(10), 3);
+        for key in 0..3 {
+            cache.set(key, key);
+        }
+        for key in 0..3 {
+            assert_eq!(cache.get(&key).map(|v| *v), Some(key));
+        }
+        // replacing a key does not evict another
+        cache.set(0, 10);
+        assert_eq!(cache.len(), 3);
+        cache.set(3, 3);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_time_to_idle() {
+        let cache = TTLCacheBuilder::new()
+            .ttl(Duration::from_millis(400))
+            .time_to_idle(Duration::from_millis(150))
+            .expiry_policy(ExpiryPolicy::Fixed)
+            .check_interval(Duration::ZERO)
+            .build();
+        cache.set(1, 1);
+        cache.set(2, 2);
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(100));
+            assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        }
+        // 2 was idle for longer than the time-to-idle, 1 was read often enough
+        assert_eq!(cache.peek(&2), None);
+        // the TTL still applies to 1 because the policy is fixed
+        thread::sleep(Duration::from_millis(120));
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_drain_skips_expired() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 3, clock.clone());
+        cache.set_with_ttl(1, 1, Duration::from_secs(1));
+        cache.set(2, 2);
+        cache.set(3, 3);
+        clock.advance(Duration::from_secs(2));
+        let drained: Vec<_> = cache.drain().into_iter().map(|(k, v)| (k, *v)).collect();
+        assert_eq!(drained, vec![(2, 2), (3, 3)]);
+        assert_eq!(cache.len(), 0);
+        assert_eq!(cache.peek(&1), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_extend() {
+        let mut cache = TTLCache::new(Duration::from_secs(10), 2);
+        cache.extend((0..3).map(|key| (key, key)));
+        assert_eq!(cache.keys(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_ttl_cache_set_removes_items_expiring_out_of_order() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(60), 10, clock.clone());
+        cache.set_with_ttl(1, 1, Duration::from_secs(30));
+        cache.set_with_ttl(2, 2, Duration::from_secs(5));
+        cache.set_with_ttl(3, 3, Duration::from_secs(10));
+        // extending an item moves its timer on rather than expiring it
+        cache.set_with_ttl(2, 2, Duration::from_secs(20));
+
+        clock.advance(Duration::from_secs(15));
+        cache.set(4, 4);
+        assert_eq!(cache.stats().size, 3);
+        assert_eq!(cache.stats().evictions, 1);
+        assert!(cache.peek(&3).is_none());
+
+        clock.advance(Duration::from_secs(10));
+        cache.set(5, 5);
+        assert_eq!(cache.keys(), vec![1, 4, 5]);
+        assert_eq!(cache.stats().size, 3);
+    }
+
+    #[test]
+    fn test_ttl_cache_new_lazy_cleanup() {
+        let cache = TTLCache::new_lazy(Duration::from_millis(50), Duration::from_millis(20), 10);
+        assert!(cache.reaper.is_none());
+        cache.set(1, 1);
+        cache.set_with_ttl(2, 2, Duration::from_secs(10));
+        let remaining = cache.remaining_ttl(&1).unwrap();
+        assert!(remaining > Duration::from_millis(30) && remaining <= Duration::from_millis(70));
+        assert_eq!(cache.cleanup(), 0);
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(cache.stats().size, 2);
+        assert_eq!(cache.cleanup(), 1);
+        assert_eq!(cache.stats().size, 1);
+        assert!(cache.contains_key(&2));
+    }
+
+    #[test]
+    fn test_ttl_cache_cleanup_with_clock() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 10, clock.clone());
+        let expired = Arc::new(Mutex::new(Vec::new()));
+        let listener_expired = expired.clone();
+        cache.on_evict(move |key, _, reason| {
+            assert_eq!(reason, EvictionReason::Expired);
+            listener_expired.lock().push(*key);
+        });
+        cache.set(1, 1);
+        cache.set_with_ttl(2, 2, Duration::from_secs(30));
+        cache.set_with_ttl(3, 3, Duration::from_secs(5));
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cache.stats().size, 3);
+        assert_eq!(cache.cleanup(), 2);
+        assert_eq!(cache.stats().size, 1);
+        assert_eq!(cache.stats().evictions, 2);
+        assert_eq!(*expired.lock(), vec![3, 1]);
+        assert_eq!(cache.cleanup(), 0);
+    }
+
+    #[test]
+    fn test_ttl_cache_builder_max_evictions_per_cycle() {
+        let cache = TTLCacheBuilder::new()
+            .ttl(Duration::from_millis(50))
+            .capacity(100)
+            .check_interval(Duration::from_millis(20))
+            .max_evictions_per_cycle(3)
+            .build();
+        cache.set_many((0..20).map(|i| (i, i)).collect());
+        thread::sleep(Duration::from_millis(200));
+        // the reaper removes the items in batches until none are left
+        assert_eq!(cache.stats().size, 0);
+        assert_eq!(cache.stats().evictions, 20);
+    }
+
+    #[test]
+    fn test_ttl_cache_reaper_listener_can_use_cache() {
+        let cache = Arc::new(
+            TTLCacheBuilder::new()
+                .ttl(Duration::from_millis(50))
+                .capacity(1)
+                .check_interval(Duration::from_millis(20))
+                .build(),
+        );
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let log = evicted.clone();
+        let handle = Arc::downgrade(&cache);
+        cache.on_evict(move |key, _, reason| {
+            // both capacity evictions and the reaper call the listener without the lock held
+            let len = handle.upgrade().map(|cache| cache.stats().size);
+            log.lock().push((*key, reason, len));
+        });
+        cache.set(1, 1);
+        cache.set(2, 2);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(
+            *evicted.lock(),
+            vec![
+                (1, EvictionReason::Capacity, Some(1)),
+                (2, EvictionReason::Expired, Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ttl_cache_put_if_absent() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 10, clock.clone());
+        assert_eq!(*cache.put_if_absent(1, 1), 1);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(*cache.put_if_absent(1, 10), 1);
+        // the existing value keeps its expiry
+        assert_eq!(cache.remaining_ttl(&1), Some(Duration::from_secs(5)));
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(*cache.put_if_absent(1, 20), 20);
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(20));
+    }
+
+    #[test]
+    fn test_ttl_cache_replace_resets_expiry() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 10, clock.clone());
+        assert_eq!(cache.replace(&1, 1), None);
+        cache.set(1, 1);
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(cache.replace(&1, 2).map(|v| *v), Some(1));
+        assert_eq!(cache.remaining_ttl(&1), Some(Duration::from_secs(10)));
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cache.replace(&1, 3), None);
+        assert_eq!(cache.peek(&1), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_compare_and_swap_skips_expired() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 10, clock.clone());
+        cache.set(1, 1);
+        assert_eq!(cache.compare_and_swap(&1, &1, 2), Ok(()));
+        assert_eq!(cache.remaining_ttl(&1), Some(Duration::from_secs(10)));
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cache.compare_and_swap(&1, &2, 3), Err(None));
+    }
+
+    #[test]
+    fn test_ttl_cache_default() {
+        let cache: TTLCache<u64, u64> = TTLCache::default();
+        assert!(cache.reaper.is_none());
+        assert_eq!(cache.capacity(), 1000);
+        cache.set(1, 1);
+        assert_eq!(
+            cache
+                .remaining_ttl(&1)
+                .map(|ttl| ttl <= Duration::from_secs(60)),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_ttl_cache_debug() {
+        let cache = TTLCache::new(Duration::from_secs(5), 10);
+        cache.set(1, 1);
+        cache.get(&1);
+        assert_eq!(
+            format!("{:?}", cache),
+            "TTLCache { ttl: 5s, capacity: 10, size: 1, hits: 1, misses: 0 }"
+        );
+    }
+
+    #[test]
+    fn test_ttl_cache_probabilistic_early_expiration() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 4, clock.clone());
+        cache.probabilistic_early_expiration(1.0);
+        cache.set_with_cost(1, 1, Duration::from_nanos(1));
+        cache.set(2, 2);
+        clock.advance(Duration::from_millis(9_999));
+        // a cheap value is almost never recomputed early and one without a cost never is
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+
+        // an expensive value close to its expiry is recomputed early but stays for other readers
+        cache.set_with_cost(1, 1, Duration::from_secs(1000));
+        clock.advance(Duration::from_millis(9_999));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.stats().misses, 1);
+        assert_eq!(*cache.get_or_compute(1, || 10), 10);
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(10));
+    }
+
+    #[test]
+    fn test_ttl_cache_set_ttl() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(1), 4, clock.clone());
+        cache.set(1, 1);
+        cache.set_ttl(Duration::from_secs(10));
+        cache.set(2, 2);
+        assert_eq!(cache.remaining_ttl(&2), Some(Duration::from_secs(10)));
+        clock.advance(Duration::from_secs(2));
+        // the existing item keeps its original expiry
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+    }
+
+    #[test]
+    fn test_ttl_cache_set_check_interval_and_jitter() {
+        let cache = TTLCacheBuilder::default()
+            .ttl(Duration::from_millis(20))
+            .check_interval(Duration::from_secs(3600))
+            .build::<u64, u64>();
+        cache.set_check_interval(Duration::from_millis(10));
+        cache.set_check_interval(Duration::ZERO);
+        cache.set_jitter(Duration::from_millis(5));
+        let state = cache.reaper.as_ref().unwrap().signal.state.lock();
+        assert_eq!(state.check_interval, Duration::from_millis(10));
+        assert_eq!(state.jitter, Duration::from_millis(5));
+        assert!(state.deadline <= Instant::now() + Duration::from_millis(15));
+        drop(state);
+
+        // a cache without a background thread ignores the changes
+        let lazy = TTLCache::<u64, u64>::new_lazy(Duration::from_secs(1), Duration::ZERO, 2);
+        lazy.set_check_interval(Duration::from_millis(10));
+        assert!(lazy.reaper.is_none());
+    }
+
+    #[test]
+    fn test_ttl_cache_seeded_jitter_is_reproducible() {
+        let ttl = Duration::from_secs(1);
+        let first = TTLCache::new_lazy_with_seed(ttl, Duration::from_secs(1), 10, 7);
+        let second = TTLCache::new_lazy_with_seed(ttl, Duration::from_secs(1), 10, 7);
+        for i in 0..10 {
+            first.set(i, i);
+            second.set(i, i);
+        }
+        let ttls = |cache: &TTLCache<u64, u64>| -> Vec<Duration> {
+            let inner = cache.inner.lock();
+            (0..10).map(|i| inner.key_value_map[&i].ttl).collect()
+        };
+        assert_eq!(ttls(&first), ttls(&second));
+        assert!(ttls(&first).iter().any(|item_ttl| *item_ttl > ttl));
+    }
+
+    #[test]
+    fn test_ttl_cache_seeded_reaper_intervals_are_reproducible() {
+        let signal = |seed| {
+            ReaperSignal::new(
+                Duration::from_secs(1),
+                Duration::from_secs(1),
+                Some(StdRng::seed_from_u64(seed)),
+            )
+        };
+        let (first, second) = (signal(3), signal(3));
+        let mut first = first.state.lock();
+        let mut second = second.state.lock();
+        for _ in 0..10 {
+            assert_eq!(first.next_delay(), second.next_delay());
+        }
+
+        let cache = TTLCacheBuilder::default().seed(3).build::<u64, u64>();
+        assert!(cache.inner.lock().rng.is_some());
+    }
+
+    #[test]
+    fn test_ttl_cache_shrink_to() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 4, clock.clone());
+        cache.set_with_ttl(0, 0, Duration::from_secs(1));
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.set(3, 3);
+        clock.advance(Duration::from_secs(2));
+        // the expired item goes first, then the least recently used
+        assert_eq!(cache.shrink_to(2), 2);
+        assert_eq!(cache.keys(), vec![2, 3]);
+        assert_eq!(cache.capacity(), 4);
+        cache.set(4, 4);
+        cache.set(5, 5);
+        assert_eq!(cache.len(), 4);
+    }
+
+    #[test]
+    fn test_ttl_cache_reserve_and_shrink_to_fit() {
+        let cache = TTLCache::new(Duration::from_secs(60), 10_000);
+        cache.reserve(5_000);
+        assert!(cache.inner.lock().key_value_map.capacity() >= 5_000);
+        cache.set_many((0..1_000).map(|i| (i, i)).collect());
+        cache.shrink_to(10);
+        cache.shrink_to_fit();
+        assert!(cache.inner.lock().key_value_map.capacity() < 1_000);
+        assert_eq!(cache.capacity(), 10_000);
+    }
+
+    #[test]
+    fn test_ttl_cache_eviction_events() {
+        let cache = TTLCacheBuilder::default()
+            .ttl(Duration::from_millis(20))
+            .check_interval(Duration::from_millis(10))
+            .build::<u64, u64>();
+        let events = cache.eviction_events();
+        cache.set(1, 1);
+        // the background thread sends the expired item to the channel
+        let (key, value, reason) = events.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!((key, *value, reason), (1, 1, EvictionReason::Expired));
     }
 }
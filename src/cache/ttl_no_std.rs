@@ -0,0 +1,401 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hash::Hash;
+use core::time::Duration;
+
+use crate::cache::ordered_map::OrderedMap;
+use crate::cache::sync::Mutex;
+pub use crate::cache::time::ExpiryPolicy;
+use crate::cache::time::{Clock, Timestamp};
+use crate::cache::{update_in_place, Cache, CacheStats, StatsCounters};
+
+/// An internal struct of the TTL cache for storing data along with its expiry time and the TTL used to extend it, both `None` for an item set without expiry.
+struct DataWithLifetime<V> {
+    data: Arc<V>,
+    expiry: Option<Timestamp>,
+    ttl: Option<Duration>,
+}
+
+impl<V> DataWithLifetime<V> {
+    /// Check whether the item has expired at the given time.
+    fn is_expired(&self, now: Timestamp) -> bool {
+        self.expiry.is_some_and(|expiry| expiry <= now)
+    }
+}
+
+/// The most expired items a `set` removes before inserting, so a set after a mass expiry does not hold the lock while every expired item is removed. The rest are left to later accesses or to `cleanup`.
+const MAX_PURGE_PER_SET: usize = 16;
+
+/// TTLCacheInner contains the inner data structure for the TTLCache, the items are ordered from least to most recently accessed.
+struct TTLCacheInner<K, V> {
+    ttl: Duration,
+    expiry_policy: ExpiryPolicy,
+    capacity: u64,
+    clock: Arc<dyn Clock>,
+    key_value_map: OrderedMap<K, DataWithLifetime<V>>,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> TTLCacheInner<K, V> {
+    /// Look up a key and make it the most recently accessed, extending its expiry under the sliding policy. An expired item is removed and treated as missing.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        let now = self.clock.now();
+        let sliding = self.expiry_policy == ExpiryPolicy::Sliding;
+        let entry = self.key_value_map.get_refresh(key)?;
+        if !entry.is_expired(now) {
+            if sliding {
+                entry.expiry = entry.ttl.map(|ttl| now + ttl);
+            }
+            return Some(entry.data.clone());
+        }
+        self.key_value_map.remove(key);
+        self.evictions += 1;
+        None
+    }
+
+    /// Get the value of a live key without changing the order or the expiry.
+    fn peek(&self, key: &K) -> Option<&DataWithLifetime<V>> {
+        let now = self.clock.now();
+        self.key_value_map
+            .get(key)
+            .filter(|entry| !entry.is_expired(now))
+    }
+
+    /// Insert a value which expires after the given TTL, or never for `None`, as the most recently accessed item and return the previous value.
+    ///
+    /// Before a new key is inserted up to `MAX_PURGE_PER_SET` expired items are removed from the front, then the least recently accessed item is evicted if the cache is still full.
+    fn set(&mut self, key: K, value: Arc<V>, ttl: Option<Duration>) -> Option<Arc<V>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let now = self.clock.now();
+        if !self.key_value_map.contains_key(&key) {
+            self.purge_expired(now, MAX_PURGE_PER_SET);
+            if self.key_value_map.len() as u64 >= self.capacity {
+                self.key_value_map.pop_front();
+                self.evictions += 1;
+            }
+        }
+        let entry = DataWithLifetime {
+            data: value,
+            expiry: ttl.map(|ttl| now + ttl),
+            ttl,
+        };
+        self.key_value_map
+            .insert(key, entry)
+            .filter(|previous| !previous.is_expired(now))
+            .map(|previous| previous.data)
+    }
+
+    /// Remove expired items from the front until a live item is reached or `limit` were removed, counting each as an eviction, and return how many were removed.
+    fn purge_expired(&mut self, now: Timestamp, limit: usize) -> u64 {
+        let mut removed = 0;
+        while removed < limit
+            && self
+                .key_value_map
+                .front()
+                .is_some_and(|(_, entry)| entry.is_expired(now))
+        {
+            self.key_value_map.pop_front();
+            removed += 1;
+        }
+        self.evictions += removed as u64;
+        removed as u64
+    }
+}
+
+/// TTLCache is a cache that adds a time-to-live (TTL) to each item, for builds without the standard library.
+///
+/// There is no system clock without the standard library, so the time is read from the `Clock` the cache is created with, which reads a timer of the target. If the cache is at capacity and a new item is added, the least recently accessed item is removed.
+///
+/// Expiry is lazy as there is no background thread: expired items are treated as missing and removed when they are accessed, a `set` of a new key removes up to 16 expired items from the least recently accessed end first, and `cleanup` removes every expired item.
+///
+/// By default reading an item extends its expiry, use `with_expiry_policy_and_clock` and `ExpiryPolicy::Fixed` for items which must expire at a fixed time after they were set.
+///
+/// All mutability is handled internally with a spin lock, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, ManualClock, TTLCache};
+/// use std::sync::Arc;
+/// use std::time::Duration;
+///
+/// let clock = Arc::new(ManualClock::new());
+/// let cache = TTLCache::<&str, u64>::with_clock(Duration::from_secs(60), 10, clock.clone());
+///
+/// cache.set("key", 1);
+/// clock.advance(Duration::from_secs(60));
+/// assert!(cache.get(&"key").is_none());
+/// ```
+pub struct TTLCache<K: Eq + Hash + Clone + Send + Sync, V: Send + Sync> {
+    inner: Mutex<TTLCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Clone + Send + Sync, V: Send + Sync> TTLCache<K, V> {
+    /// Create a new TTLCache with the given TTL and capacity which reads the time from the clock, reading an item extends its expiry.
+    pub fn with_clock(ttl: Duration, capacity: u64, clock: Arc<dyn Clock>) -> Self {
+        Self::with_expiry_policy_and_clock(ttl, capacity, ExpiryPolicy::Sliding, clock)
+    }
+
+    /// Create a new TTLCache with the given TTL, capacity and expiry policy which reads the time from the clock.
+    pub fn with_expiry_policy_and_clock(
+        ttl: Duration,
+        capacity: u64,
+        expiry_policy: ExpiryPolicy,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        TTLCache {
+            inner: Mutex::new(TTLCacheInner {
+                ttl,
+                expiry_policy,
+                capacity,
+                clock,
+                key_value_map: OrderedMap::with_capacity(capacity as usize),
+                evictions: 0,
+            }),
+            counters: StatsCounters::new(),
+        }
+    }
+
+    /// Set a value with its own TTL instead of the TTL of the cache, `None` sets it without expiry so it stays until it is evicted or removed.
+    pub fn set_with_ttl(
+        &self,
+        key: K,
+        value: V,
+        ttl: impl Into<Option<Duration>>,
+    ) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, Arc::new(value), ttl.into())
+    }
+
+    /// Get how long a live item has left before it expires, or `None` if the key is missing, expired or was set without expiry. This does not extend the expiry or update the cache statistics.
+    pub fn remaining_ttl(&self, key: &K) -> Option<Duration> {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .peek(key)
+            .and_then(|entry| entry.expiry)
+            .map(|expiry| expiry - now)
+    }
+
+    /// Remove every expired item now rather than waiting for it to be accessed, returning how many were removed. Removed items count as evictions.
+    pub fn cleanup(&self) -> u64 {
+        let mut inner = self.inner.lock();
+        let now = inner.clock.now();
+        let removed = inner
+            .key_value_map
+            .retain(|_, entry| !entry.is_expired(now))
+            .len() as u64;
+        inner.evictions += removed;
+        removed
+    }
+
+    /// Get the keys of the live items, ordered from least to most recently accessed.
+    pub fn keys(&self) -> Vec<K> {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+impl<K: Eq + Hash + Clone + Send + Sync, V: Send + Sync> Cache<K, V> for TTLCache<K, V> {
+    /// Get a value from the cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without extending its expiry or updating the cache statistics, expired items are not returned.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner.peek(key).map(|entry| entry.data.clone())
+    }
+
+    /// Check whether a key is in the cache without extending its expiry or updating the cache statistics, expired items are reported as missing.
+    fn contains_key(&self, key: &K) -> bool {
+        let inner = self.inner.lock();
+        inner.peek(key).is_some()
+    }
+
+    /// Set a value in the cache with the TTL of the cache.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        let ttl = inner.ttl;
+        inner.set(key, value, Some(ttl))
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        let ttl = inner.ttl;
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value), Some(ttl)))
+            .collect()
+    }
+
+    /// Remove a value from the cache, an expired item is removed but not returned.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .remove(key)
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| entry.data)
+    }
+
+    /// Clear the cache, removing all items.
+    fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.key_value_map.clear();
+    }
+
+    /// Get the cache statistics, the size includes expired items which have not been removed yet unlike `len`.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.key_value_map.len() as u64,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of live items in the cache, expired items which have not been removed yet are not counted, which requires scanning every item.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .iter()
+            .filter(|(_, entry)| !entry.is_expired(now))
+            .count() as u64
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, if the new capacity is smaller than the current size, the least recently accessed items are removed.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity;
+        while inner.key_value_map.len() as u64 > capacity {
+            inner.key_value_map.pop_front();
+            inner.evictions += 1;
+        }
+    }
+
+    /// Remove every item for which the predicate returns false, expired items are always removed.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        let now = inner.clock.now();
+        inner
+            .key_value_map
+            .retain(|key, entry| !entry.is_expired(now) && f(key, &entry.data));
+    }
+
+    /// Update a value in place without extending its expiry or updating the cache statistics, an expired item is not updated.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        let now = inner.clock.now();
+        let value = inner
+            .key_value_map
+            .get_mut(key)
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| &mut entry.data);
+        update_in_place(value, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::time::ManualClock;
+
+    #[test]
+    fn test_ttl_cache_sliding_expiry_is_extended_by_get() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 10, clock.clone());
+        cache.set(1, 1);
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(cache.remaining_ttl(&1), Some(Duration::from_secs(2)));
+        assert_eq!(cache.peek(&1).map(|v| *v), Some(1));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_ttl_cache_fixed_expiry_ignores_reads() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_expiry_policy_and_clock(
+            Duration::from_secs(10),
+            10,
+            ExpiryPolicy::Fixed,
+            clock.clone(),
+        );
+        cache.set(1, 1);
+        clock.advance(Duration::from_secs(8));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_set_purges_expired_items_before_evicting() {
+        let clock = Arc::new(ManualClock::new());
+        let cache = TTLCache::with_clock(Duration::from_secs(10), 3, clock.clone());
+        cache.set_with_ttl(1, 1, Duration::from_secs(1));
+        cache.set_with_ttl(2, 2, None);
+        cache.set(3, 3);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.len(), 2);
+        cache.set(4, 4);
+        // the expired item made room, so no live item was evicted
+        assert_eq!(cache.keys(), vec![2, 3, 4]);
+        assert_eq!(cache.stats().evictions, 1);
+        assert_eq!(cache.remaining_ttl(&2), None);
+        cache.set(5, 5);
+        assert_eq!(cache.keys(), vec![3, 4, 5]);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(cache.stats().size, 3);
+        assert_eq!(cache.cleanup(), 3);
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().evictions, 5);
+    }
+}
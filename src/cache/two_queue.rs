@@ -0,0 +1,332 @@
+use crate::cache::sync::Mutex;
+use crate::cache::{retain_linked_hash_map, update_in_place, Cache, CacheStats, StatsCounters};
+use linked_hash_map::LinkedHashMap;
+use linked_hash_set::LinkedHashSet;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// The fraction of the capacity used for the A1in queue of first time accesses.
+const A1IN_RATIO: f64 = 0.25;
+
+/// The number of ghost keys remembered in A1out as a fraction of the capacity.
+const A1OUT_RATIO: f64 = 0.5;
+
+/// The inner data structure for the TwoQueueCache.
+///
+/// + `a1in` is a FIFO queue of items seen once.
+/// + `a1out` is a ghost FIFO of keys recently evicted from `a1in`.
+/// + `am` is an LRU of items that were accessed again after leaving `a1in`.
+struct TwoQueueCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    capacity: u64,
+    a1in_capacity: u64,
+    a1out_capacity: u64,
+    a1in: LinkedHashMap<K, Arc<V>>,
+    a1out: LinkedHashSet<K>,
+    am: LinkedHashMap<K, Arc<V>>,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> TwoQueueCacheInner<K, V> {
+    /// Create a new TwoQueueCacheInner with the given capacity, internally capacity is reserved for the necessary data structures.
+    fn new(capacity: u64) -> Self {
+        let mut inner = TwoQueueCacheInner {
+            capacity,
+            a1in_capacity: 0,
+            a1out_capacity: 0,
+            a1in: LinkedHashMap::new(),
+            a1out: LinkedHashSet::new(),
+            am: LinkedHashMap::with_capacity(capacity as usize),
+            evictions: 0,
+        };
+        inner.compute_queue_capacities();
+        inner
+    }
+
+    /// Derive the size of the A1in and A1out queues from the total capacity.
+    fn compute_queue_capacities(&mut self) {
+        self.a1in_capacity = (self.capacity as f64 * A1IN_RATIO) as u64;
+        self.a1out_capacity = (self.capacity as f64 * A1OUT_RATIO) as u64;
+    }
+
+    /// The number of resident items.
+    fn len(&self) -> u64 {
+        (self.a1in.len() + self.am.len()) as u64
+    }
+
+    /// Remember a key evicted from A1in, forgetting the oldest ghost if A1out is full.
+    fn push_ghost(&mut self, key: K) {
+        self.a1out.insert(key);
+        while self.a1out.len() as u64 > self.a1out_capacity {
+            if self.a1out.pop_front().is_none() {
+                break;
+            }
+        }
+    }
+
+    /// Evict a single item, A1in is drained first while it is over its share of the capacity.
+    fn evict(&mut self) {
+        if self.a1in.len() as u64 > self.a1in_capacity || self.am.is_empty() {
+            if let Some((key, _)) = self.a1in.pop_front() {
+                self.push_ghost(key);
+                self.evictions += 1;
+            }
+        } else if self.am.pop_front().is_some() {
+            self.evictions += 1;
+        }
+    }
+
+    /// Make room for a new item if the cache is at capacity.
+    fn reclaim(&mut self) {
+        while self.len() >= self.capacity && self.len() > 0 {
+            self.evict();
+        }
+    }
+
+    /// Look up a key, updating the eviction order.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        let mut result = self.am.get_refresh(key).cloned();
+        if result.is_none() {
+            result = self.a1in.get(key).cloned();
+        }
+
+        result
+    }
+
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        if let Some(entry) = self.am.get_refresh(&key) {
+            return Some(std::mem::replace(entry, value));
+        }
+        if let Some(entry) = self.a1in.get_mut(&key) {
+            return Some(std::mem::replace(entry, value));
+        }
+        if self.capacity == 0 {
+            return None;
+        }
+
+        self.reclaim();
+        if self.a1out.remove(&key) {
+            self.am.insert(key, value);
+        } else {
+            self.a1in.insert(key, value);
+        }
+        None
+    }
+}
+
+/// TwoQueueCache is a cache that uses the simplified 2Q algorithm to evict items.
+///
+/// Items seen for the first time are placed in a FIFO queue (A1in). When they are pushed out of that queue only their key is remembered in a ghost queue (A1out). If a key in the ghost queue is set again it is promoted straight into an LRU of frequently used items (Am). Items which are only ever seen once therefore never displace the frequently used items, which makes the cache resistant to scans.
+///
+/// By default 25% of the capacity is used for A1in and up to 50% of the capacity worth of keys is remembered in A1out.
+///
+/// All mutability is handled internally with a Mutex, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, TwoQueueCache};
+///
+/// let cache = TwoQueueCache::<&str, String>::new(10);
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct TwoQueueCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    inner: Mutex<TwoQueueCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> TwoQueueCache<K, V> {
+    /// Create a new TwoQueueCache with the given capacity.
+    pub fn new(capacity: u64) -> Self {
+        TwoQueueCache {
+            inner: Mutex::new(TwoQueueCacheInner::new(capacity)),
+            counters: StatsCounters::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for TwoQueueCache<K, V> {
+    /// Get a value from the cache, hits in Am refresh the item while hits in A1in leave the FIFO order untouched.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner.am.get(key).or_else(|| inner.a1in.get(key)).cloned()
+    }
+
+    /// Set a value in the cache, keys remembered in A1out are promoted straight into Am.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.am.remove(key).or_else(|| inner.a1in.remove(key))
+    }
+
+    /// Clear the cache, removing all items and ghost entries.
+    fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.a1in.clear();
+        inner.a1out.clear();
+        inner.am.clear();
+    }
+
+    /// Get the cache statistics, the size is the combined number of items resident in A1in and Am.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.len(),
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.len()
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, the queue sizes are recomputed and items are evicted until the cache fits.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.lock();
+        let old_capacity = inner.capacity;
+        inner.capacity = capacity;
+        inner.compute_queue_capacities();
+        while inner.len() > inner.capacity {
+            inner.evict();
+        }
+        while inner.a1out.len() as u64 > inner.a1out_capacity {
+            inner.a1out.pop_front();
+        }
+
+        if inner.capacity > old_capacity {
+            let additional = (inner.capacity - old_capacity) as usize;
+            inner.am.reserve(additional);
+        }
+    }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        retain_linked_hash_map(&mut inner.a1in, &mut f);
+        retain_linked_hash_map(&mut inner.am, &mut f);
+    }
+
+    /// Update a value in place without promoting it or updating the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        let TwoQueueCacheInner { a1in, am, .. } = &mut *inner;
+        update_in_place(am.get_mut(key).or_else(|| a1in.get_mut(key)), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_two_queue_cache() {
+        let cache = TwoQueueCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        // A1in is strictly FIFO so the get does not save 1
+        cache.set(3, 3);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+    }
+
+    #[test]
+    fn test_two_queue_cache_scan_resistance() {
+        let cache = TwoQueueCache::new(4);
+        for i in 0..5 {
+            cache.set(i, i);
+        }
+        // 0 has been pushed into the ghost queue, setting it again promotes it into Am
+        assert_eq!(cache.get(&0), None);
+        cache.set(0, 0);
+        for i in 100..120 {
+            cache.set(i, i);
+        }
+        assert_eq!(cache.get(&0).map(|v| *v), Some(0));
+        assert_eq!(cache.stats().size, 4);
+    }
+
+    #[test]
+    fn test_two_queue_cache_change_capacity() {
+        let cache = TwoQueueCache::new(4);
+        for i in 0..4 {
+            cache.set(i, i);
+        }
+        cache.change_capacity(2);
+        assert_eq!(cache.stats().size, 2);
+        assert_eq!(cache.get(&0), None);
+        assert_eq!(cache.get(&3).map(|v| *v), Some(3));
+    }
+
+    #[test]
+    fn test_two_queue_cache_clear() {
+        let cache = TwoQueueCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.clear();
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), None);
+    }
+}
@@ -0,0 +1,208 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use crate::cache::sync::RwLock;
+use crate::cache::{swap_if_equal, update_in_place, Cache, CacheStats, HashMap, StatsCounters};
+
+/// UnboundedCache is a cache which never evicts items, for memoising a key space which is known to be small, such as the results for every variant of an enum.
+///
+/// There is no capacity limit so an item stays until it is removed, `capacity` reports `u64::MAX` and `change_capacity` has no effect. Use a bounded cache if the number of keys could grow without limit.
+///
+/// All mutability is handled internally with a RwLock, so the cache can be shared between threads. A `get` does not change any eviction order so it only takes a shared read lock. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, UnboundedCache};
+///
+/// let cache = UnboundedCache::<&str, String>::new();
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct UnboundedCache<K: Eq + Hash + Send, V: Send + Sync> {
+    key_value_map: RwLock<HashMap<K, Arc<V>>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Sync + Send, V: Send + Sync> UnboundedCache<K, V> {
+    /// Create a new empty UnboundedCache.
+    pub fn new() -> Self {
+        UnboundedCache {
+            key_value_map: RwLock::new(HashMap::new()),
+            counters: StatsCounters::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Sync + Send, V: Send + Sync> Default for UnboundedCache<K, V> {
+    /// Create a new empty UnboundedCache.
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for UnboundedCache<K, V> {
+    /// Get a value from the cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let key_value_map = self.key_value_map.read();
+        self.counters.record(key_value_map.get(key).cloned())
+    }
+
+    /// Get several values from the cache under a single read lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let key_value_map = self.key_value_map.read();
+        keys.iter()
+            .map(|key| self.counters.record(key_value_map.get(key).cloned()))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let key_value_map = self.key_value_map.read();
+        key_value_map.get(key).cloned()
+    }
+
+    /// Set a value in the cache, nothing is ever evicted.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut key_value_map = self.key_value_map.write();
+        key_value_map.insert(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut key_value_map = self.key_value_map.write();
+        items
+            .into_iter()
+            .map(|(key, value)| key_value_map.insert(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Set a value under a single lock if the key is missing, returning the value which is in the cache afterwards.
+    fn put_if_absent(&self, key: K, value: V) -> Arc<V> {
+        let mut key_value_map = self.key_value_map.write();
+        key_value_map
+            .entry(key)
+            .or_insert_with(|| Arc::new(value))
+            .clone()
+    }
+
+    /// Replace a value under a single lock if the key is present, returning the value it replaced.
+    fn replace(&self, key: &K, value: V) -> Option<Arc<V>> {
+        let mut key_value_map = self.key_value_map.write();
+        let entry = key_value_map.get_mut(key)?;
+        Some(core::mem::replace(entry, Arc::new(value)))
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut key_value_map = self.key_value_map.write();
+        key_value_map.remove(key)
+    }
+
+    /// Clear the cache.
+    fn clear(&self) {
+        let mut key_value_map = self.key_value_map.write();
+        key_value_map.clear();
+    }
+
+    /// Get cache statistics, the evictions are always zero and the capacity is `u64::MAX`.
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: 0,
+            size: self.len(),
+            capacity: u64::MAX,
+        }
+    }
+
+    /// Reset the hit and miss counts.
+    fn reset_stats(&self) {
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let key_value_map = self.key_value_map.read();
+        key_value_map.len() as u64
+    }
+
+    /// Get the maximum number of items in the cache, which is always `u64::MAX`.
+    fn capacity(&self) -> u64 {
+        u64::MAX
+    }
+
+    /// Do nothing, an UnboundedCache has no capacity to change.
+    fn change_capacity(&self, _capacity: u64) {}
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut key_value_map = self.key_value_map.write();
+        key_value_map.retain(|key, value| f(key, value));
+    }
+
+    /// Update a value in place without updating the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut key_value_map = self.key_value_map.write();
+        update_in_place(key_value_map.get_mut(key), f)
+    }
+
+    /// Compare and swap a value under a single lock.
+    fn compare_and_swap(&self, key: &K, expected: &V, new: V) -> Result<(), Option<Arc<V>>>
+    where
+        V: Clone + PartialEq,
+    {
+        let mut key_value_map = self.key_value_map.write();
+        swap_if_equal(key_value_map.get_mut(key), expected, new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unbounded_cache_never_evicts() {
+        let cache = UnboundedCache::new();
+        for i in 0..10_000 {
+            cache.set(i, i);
+        }
+        cache.change_capacity(1);
+        assert_eq!(cache.len(), 10_000);
+        assert_eq!(cache.get(&0).map(|v| *v), Some(0));
+        assert_eq!(cache.get(&10_000), None);
+        let stats = cache.stats();
+        assert_eq!(stats.evictions, 0);
+        assert_eq!(stats.capacity, u64::MAX);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+    }
+
+    #[test]
+    fn test_unbounded_cache_updates() {
+        let cache = UnboundedCache::default();
+        assert_eq!(*cache.put_if_absent(1, 1), 1);
+        assert_eq!(*cache.put_if_absent(1, 2), 1);
+        assert_eq!(cache.replace(&2, 2), None);
+        assert_eq!(cache.replace(&1, 3).map(|v| *v), Some(1));
+        assert_eq!(cache.compare_and_swap(&1, &3, 4), Ok(()));
+        assert!(cache.compute(&1, |value| *value += 1));
+        assert_eq!(cache.remove(&1).map(|v| *v), Some(5));
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+use crate::cache::sync::Mutex;
+use crate::cache::{CacheStats, StatsCounters};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Weak};
+
+/// The inner data structure for the WeakCache.
+struct WeakCacheInner<K: Eq + Hash, V> {
+    key_value_map: HashMap<K, Weak<V>>,
+    /// The number of entries after the last sweep, the next sweep runs once the map has grown to twice this.
+    live_after_sweep: usize,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash, V> WeakCacheInner<K, V> {
+    /// Remove every entry whose value has been dropped, returning how many were removed.
+    fn sweep(&mut self) -> usize {
+        let before = self.key_value_map.len();
+        self.key_value_map
+            .retain(|_, value| value.strong_count() > 0);
+        let removed = before - self.key_value_map.len();
+        self.live_after_sweep = self.key_value_map.len();
+        self.evictions += removed as u64;
+        removed
+    }
+}
+
+/// WeakCache holds `Weak` references to its values, so it only caches values which are still alive somewhere else and never keeps a value alive by itself.
+///
+/// This suits shared handles, such as open files or loaded assets, where the cache should find the handle while it is in use but not be the reason it stays open. A value is stored with `set` from an Arc the caller keeps, and once every Arc has been dropped a `get` for its key is a miss and removes the dead entry. Dead entries which are never looked up again are swept whenever the cache has doubled in size since the last sweep, or by calling `purge_dead`.
+///
+/// There is no capacity, an entry stays until its value is dropped or it is removed.
+///
+/// Example:
+/// ```
+/// use arcache::WeakCache;
+/// use std::sync::Arc;
+///
+/// let cache = WeakCache::<&str, String>::new();
+///
+/// let value = Arc::new("value".to_string());
+/// cache.set("key", &value);
+/// assert_eq!(cache.get(&"key"), Some(value.clone()));
+///
+/// drop(value);
+/// assert_eq!(cache.get(&"key"), None);
+/// assert!(cache.is_empty());
+/// ```
+pub struct WeakCache<K: Eq + Hash + Send, V: Send + Sync> {
+    inner: Mutex<WeakCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Send, V: Send + Sync> WeakCache<K, V> {
+    /// Create a new empty WeakCache.
+    pub fn new() -> Self {
+        WeakCache {
+            inner: Mutex::new(WeakCacheInner {
+                key_value_map: HashMap::new(),
+                live_after_sweep: 0,
+                evictions: 0,
+            }),
+            counters: StatsCounters::new(),
+        }
+    }
+
+    /// Get a value from the cache if it is still alive, a dropped value is a miss and its entry is removed.
+    pub fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        let value = inner.key_value_map.get(key).map(Weak::upgrade);
+        if let Some(None) = value {
+            inner.key_value_map.remove(key);
+            inner.evictions += 1;
+        }
+        self.counters.record(value.flatten())
+    }
+
+    /// Get a value from the cache if it is still alive, without updating the cache statistics or removing a dead entry.
+    pub fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner.key_value_map.get(key).and_then(Weak::upgrade)
+    }
+
+    /// Check whether a key holds a value which is still alive, without updating the cache statistics.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    /// Store a weak reference to `value` for a key, returning the previous value if it was still alive.
+    ///
+    /// The cache does not keep `value` alive, the entry is a miss once every Arc for it has been dropped.
+    pub fn set(&self, key: K, value: &Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        let previous = inner
+            .key_value_map
+            .insert(key, Arc::downgrade(value))
+            .and_then(|previous| previous.upgrade());
+        if inner.key_value_map.len() >= (inner.live_after_sweep * 2).max(1) {
+            inner.sweep();
+        }
+        previous
+    }
+
+    /// Remove a value from the cache, returning it if it was still alive.
+    pub fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner
+            .key_value_map
+            .remove(key)
+            .and_then(|value| value.upgrade())
+    }
+
+    /// Remove every entry whose value has been dropped, returning how many were removed.
+    pub fn purge_dead(&self) -> usize {
+        self.inner.lock().sweep()
+    }
+
+    /// Clear the cache.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.key_value_map.clear();
+        inner.live_after_sweep = 0;
+    }
+
+    /// Get the number of entries in the cache, which can include dead entries which have not been removed yet.
+    pub fn len(&self) -> u64 {
+        self.inner.lock().key_value_map.len() as u64
+    }
+
+    /// Check whether the cache holds no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get cache statistics, the evictions are the dead entries which have been removed and the capacity is `u64::MAX`.
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.key_value_map.len() as u64,
+            capacity: u64::MAX,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    pub fn reset_stats(&self) {
+        self.inner.lock().evictions = 0;
+        self.counters.reset();
+    }
+}
+
+impl<K: Eq + Hash + Send, V: Send + Sync> Default for WeakCache<K, V> {
+    /// Create a new empty WeakCache.
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weak_cache_only_holds_live_values() {
+        let cache = WeakCache::new();
+        let one = Arc::new(1);
+        let two = Arc::new(2);
+        cache.set(1, &one);
+        cache.set(2, &two);
+        assert_eq!(Arc::strong_count(&one), 1);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+
+        drop(one);
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.len(), 1);
+
+        drop(two);
+        assert_eq!(cache.purge_dead(), 1);
+        assert!(cache.is_empty());
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.evictions, 2);
+    }
+
+    #[test]
+    fn test_weak_cache_sweeps_dead_entries_as_it_grows() {
+        let cache = WeakCache::new();
+        for i in 0..1000 {
+            cache.set(i, &Arc::new(i));
+        }
+        // every value was dropped straight away so the sweeps keep the map small
+        assert!(cache.len() <= 1);
+        let kept = Arc::new(0);
+        cache.set(0, &kept);
+        assert_eq!(cache.get(&0), Some(kept));
+    }
+}
@@ -0,0 +1,334 @@
+use crate::cache::sync::Mutex;
+use crate::cache::{retain_linked_hash_map, Cache, CacheStats, StatsCounters};
+use linked_hash_map::LinkedHashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// A function which computes the weight of an item.
+type Weigher<K, V> = Box<dyn Fn(&K, &V) -> u64 + Send + Sync>;
+
+/// A value stored in the WeightedLRUCache along with its weight.
+struct WeightedValue<V> {
+    value: Arc<V>,
+    weight: u64,
+}
+
+/// The inner data structure for the WeightedLRUCache.
+struct WeightedLRUCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    capacity: u64,
+    weight: u64,
+    weigher: Weigher<K, V>,
+    key_value_map: LinkedHashMap<K, WeightedValue<V>>,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> WeightedLRUCacheInner<K, V> {
+    /// Create a new WeightedLRUCacheInner with the given maximum total weight and weigher.
+    fn new(capacity: u64, weigher: Weigher<K, V>) -> Self {
+        WeightedLRUCacheInner {
+            capacity,
+            weight: 0,
+            weigher,
+            key_value_map: LinkedHashMap::new(),
+            evictions: 0,
+        }
+    }
+
+    /// Look up a key, updating the eviction order.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        self.key_value_map
+            .get_refresh(key)
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Insert a value, evicting the least recently used items until the total weight fits, and return the previous value.
+    ///
+    /// An item heavier than the capacity is rejected, any previous value for the key is still removed so it is not served after the set.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let weight = (self.weigher)(&key, &value);
+        let result = self.remove(&key);
+        if weight > self.capacity {
+            return result;
+        }
+
+        self.weight += weight;
+        self.key_value_map
+            .insert(key, WeightedValue { value, weight });
+        self.enforce_capacity();
+        result
+    }
+
+    /// Remove an item, subtracting its weight from the total.
+    fn remove(&mut self, key: &K) -> Option<Arc<V>> {
+        let entry = self.key_value_map.remove(key)?;
+        self.weight -= entry.weight;
+        Some(entry.value)
+    }
+
+    /// Evict the least recently used items until the total weight fits within the capacity.
+    fn enforce_capacity(&mut self) {
+        while self.weight > self.capacity {
+            match self.key_value_map.pop_front() {
+                Some((_, entry)) => {
+                    self.weight -= entry.weight;
+                    self.evictions += 1;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// WeightedLRUCache is a cache that uses the Least Recently Used (LRU) algorithm to evict items, with the capacity measured as a total weight rather than a number of items.
+///
+/// The weight of each item is computed once on insertion by a weigher function, for example the size of the value in bytes. When the summed weight exceeds the capacity the least recently accessed items are removed until it fits. An item which is heavier than the whole capacity is never stored.
+///
+/// The size in the cache statistics is the number of items, use `weight` to get the current total weight.
+///
+/// All mutability is handled internally with a Mutex, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, WeightedLRUCache};
+///
+/// let cache = WeightedLRUCache::<&str, String>::new(1024, |_, value| value.len() as u64);
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// assert_eq!(cache.weight(), 5);
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct WeightedLRUCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    inner: Mutex<WeightedLRUCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> WeightedLRUCache<K, V> {
+    /// Create a new WeightedLRUCache with the given maximum total weight and a function to compute the weight of each item.
+    pub fn new<F>(capacity: u64, weigher: F) -> Self
+    where
+        F: Fn(&K, &V) -> u64 + Send + Sync + 'static,
+    {
+        WeightedLRUCache {
+            inner: Mutex::new(WeightedLRUCacheInner::new(capacity, Box::new(weigher))),
+            counters: StatsCounters::new(),
+        }
+    }
+
+    /// Get the summed weight of all items in the cache.
+    pub fn weight(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.weight
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for WeightedLRUCache<K, V> {
+    /// Get a value from the cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner
+            .key_value_map
+            .get(key)
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Set a value in the cache, an item heavier than the capacity is rejected and any previous value for the key is removed.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.remove(key)
+    }
+
+    /// Clear the cache, removing all items.
+    fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.key_value_map.clear();
+        inner.weight = 0;
+    }
+
+    /// Get the cache statistics, the capacity is the maximum total weight.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.key_value_map.len() as u64,
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.key_value_map.len() as u64
+    }
+
+    /// Get the maximum total weight of the items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
+    /// Change the maximum total weight of the cache, if the new capacity is smaller than the current weight the least recently used items are removed until it fits.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity;
+        inner.enforce_capacity();
+    }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        let removed =
+            retain_linked_hash_map(&mut inner.key_value_map, |key, entry| f(key, &entry.value));
+        for (_, entry) in removed {
+            inner.weight -= entry.weight;
+        }
+    }
+
+    /// Update a value in place without updating its recency or the cache statistics, the item is weighed again afterwards and removed if it has become heavier than the capacity.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        let WeightedLRUCacheInner {
+            weigher,
+            key_value_map,
+            ..
+        } = &mut *inner;
+        let Some(entry) = key_value_map.get_mut(key) else {
+            return false;
+        };
+        f(Arc::make_mut(&mut entry.value));
+        let old_weight = entry.weight;
+        entry.weight = weigher(key, &entry.value);
+        let new_weight = entry.weight;
+        inner.weight = inner.weight - old_weight + new_weight;
+        if new_weight > inner.capacity {
+            inner.remove(key);
+        }
+        inner.enforce_capacity();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weighted_lru_cache() {
+        let cache = WeightedLRUCache::new(10, |_, value: &u64| *value);
+        cache.set(1, 4);
+        cache.set(2, 4);
+        cache.get(&1);
+        // 2 is the least recently used item so it is evicted to make room
+        cache.set(3, 5);
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(4));
+        assert_eq!(cache.get(&3).map(|v| *v), Some(5));
+        assert_eq!(cache.weight(), 9);
+    }
+
+    #[test]
+    fn test_weighted_lru_cache_evicts_until_fits() {
+        let cache = WeightedLRUCache::new(10, |_, value: &u64| *value);
+        for i in 1..=5 {
+            cache.set(i, 2);
+        }
+        cache.set(6, 7);
+        assert_eq!(cache.stats().size, 2);
+        assert_eq!(cache.weight(), 9);
+        assert_eq!(cache.get(&5).map(|v| *v), Some(2));
+    }
+
+    #[test]
+    fn test_weighted_lru_cache_rejects_oversized_item() {
+        let cache = WeightedLRUCache::new(10, |_, value: &u64| *value);
+        cache.set(1, 5);
+        cache.set(2, 5);
+        assert!(cache.set(3, 11).is_none());
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.stats().size, 2);
+        // an oversized update removes the previous value
+        assert_eq!(cache.set(1, 11).map(|v| *v), Some(5));
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.weight(), 5);
+    }
+
+    #[test]
+    fn test_weighted_lru_cache_change_capacity() {
+        let cache = WeightedLRUCache::new(10, |_, value: &u64| *value);
+        cache.set(1, 3);
+        cache.set(2, 3);
+        cache.set(3, 3);
+        cache.change_capacity(6);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.weight(), 6);
+        cache.remove(&2);
+        assert_eq!(cache.weight(), 3);
+        cache.clear();
+        assert_eq!(cache.weight(), 0);
+    }
+
+    #[test]
+    fn test_weighted_lru_cache_compute_reweighs() {
+        let cache = WeightedLRUCache::new(10, |_, value: &u64| *value);
+        cache.set(1, 4);
+        cache.set(2, 4);
+        assert!(cache.compute(&2, |value| *value = 7));
+        // the heavier item pushes the least recently used item out
+        assert_eq!(cache.weight(), 7);
+        assert_eq!(cache.get(&1), None);
+        assert!(cache.compute(&2, |value| *value = 11));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.weight(), 0);
+    }
+}
@@ -0,0 +1,396 @@
+use crate::cache::eviction::EvictionReason;
+use crate::cache::lru::LRUCache;
+use crate::cache::sync::Mutex;
+use crate::cache::{Cache, CacheStats};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// A slow store which a WriteBackCache writes dirty items to, for example a database.
+///
+/// Any `Fn(&K, &V)` closure is a WriteSink.
+pub trait WriteSink<K, V>: Send + Sync {
+    /// Write the value of a key to the store.
+    fn write(&self, key: &K, value: &V);
+}
+
+impl<K, V, F> WriteSink<K, V> for F
+where
+    F: Fn(&K, &V) + Send + Sync,
+{
+    fn write(&self, key: &K, value: &V) {
+        self(key, value)
+    }
+}
+
+/// The keys of a WriteBackCache which have not been written to the sink.
+///
+/// `changing` counts the changes to each key which are in progress. An item which leaves the cache while its key is changing is written even if a flush has just marked it clean, because the flush may have read the value from before the change.
+struct Dirty<K> {
+    keys: HashSet<K>,
+    changing: HashMap<K, usize>,
+}
+
+/// The state of a WriteBackCache which is shared with its eviction listener and flusher thread.
+struct WriteBackShared<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S> {
+    cache: LRUCache<K, V>,
+    sink: S,
+    dirty: Mutex<Dirty<K>>,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync, S: WriteSink<K, V>>
+    WriteBackShared<K, V, S>
+{
+    /// Write every dirty item to the sink and mark it clean, returning how many were written.
+    ///
+    /// The values are read while the dirty set is locked, so an item evicted at the same time is written either here or by the eviction listener but never lost.
+    fn flush(&self) -> usize {
+        let items: Vec<(K, Arc<V>)> = {
+            let mut dirty = self.dirty.lock();
+            dirty
+                .keys
+                .drain()
+                .filter_map(|key| self.cache.peek(&key).map(|value| (key, value)))
+                .collect()
+        };
+        for (key, value) in &items {
+            self.sink.write(key, value);
+        }
+        items.len()
+    }
+
+    /// Write an item which is leaving the cache to the sink if it is dirty or being changed.
+    fn write_if_dirty(&self, key: &K, value: &V) {
+        let write = {
+            let mut dirty = self.dirty.lock();
+            dirty.keys.remove(key) || dirty.changing.contains_key(key)
+        };
+        if write {
+            self.sink.write(key, value);
+        }
+    }
+
+    /// Mark a key as dirty and changing before it is changed, returning whether it was dirty already.
+    ///
+    /// Marking it first means an eviction which races with the change still sees the item as dirty and writes it.
+    fn begin_change(&self, key: &K) -> bool {
+        let mut dirty = self.dirty.lock();
+        *dirty.changing.entry(key.clone()).or_default() += 1;
+        !dirty.keys.insert(key.clone())
+    }
+
+    /// Finish a change to a key, marking it dirty again if `dirty` as a flush during the change may have written the old value, or clean otherwise.
+    fn end_change(&self, key: &K, dirty: bool) {
+        let mut state = self.dirty.lock();
+        if let Some(count) = state.changing.get_mut(key) {
+            *count -= 1;
+            if *count == 0 {
+                state.changing.remove(key);
+            }
+        }
+        if dirty {
+            state.keys.insert(key.clone());
+        } else {
+            state.keys.remove(key);
+        }
+    }
+}
+
+/// WriteBackCache is an LRUCache for writes to a slow store, a `set` only marks the item as dirty and dirty items are written to a WriteSink later in batches.
+///
+/// Dirty items are written by `flush`, by a background thread every flush interval if the cache was created with `with_flush_interval`, and when the cache is dropped. A dirty item which leaves the cache, whether it is evicted to make room or removed with `remove`, `clear` or `retain`, is written before it is dropped so no write is lost. Replacing a dirty value only keeps the newest value to write.
+///
+/// The cache uses the eviction listener of the inner LRUCache, and the sink is called without any cache lock held. Reads do not touch the sink, a key missing from the cache is a miss.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, WriteBackCache};
+/// use std::sync::{Arc, Mutex};
+///
+/// let store = Arc::new(Mutex::new(Vec::new()));
+/// let sink_store = store.clone();
+/// let cache = WriteBackCache::new(10, move |key: &String, value: &u64| {
+///     sink_store.lock().unwrap().push((key.clone(), *value));
+/// });
+///
+/// cache.set("a".to_string(), 1);
+/// cache.set("a".to_string(), 2);
+/// assert!(store.lock().unwrap().is_empty());
+///
+/// assert_eq!(cache.flush(), 1);
+/// assert_eq!(*store.lock().unwrap(), vec![("a".to_string(), 2)]);
+/// ```
+pub struct WriteBackCache<
+    K: Eq + Hash + Clone + Sync + Send + 'static,
+    V: Send + Sync + 'static,
+    S: WriteSink<K, V> + 'static,
+> {
+    shared: Arc<WriteBackShared<K, V, S>>,
+    flusher: Option<(Sender<()>, JoinHandle<()>)>,
+}
+
+impl<
+        K: Eq + Hash + Clone + Sync + Send + 'static,
+        V: Send + Sync + 'static,
+        S: WriteSink<K, V> + 'static,
+    > WriteBackCache<K, V, S>
+{
+    /// Create a new WriteBackCache with the given capacity which writes dirty items to `sink` when `flush` is called.
+    pub fn new(capacity: u64, sink: S) -> Self {
+        let shared = Arc::new(WriteBackShared {
+            cache: LRUCache::new(capacity),
+            sink,
+            dirty: Mutex::new(Dirty {
+                keys: HashSet::new(),
+                changing: HashMap::new(),
+            }),
+        });
+        let listener: Weak<WriteBackShared<K, V, S>> = Arc::downgrade(&shared);
+        shared.cache.on_evict(move |key, value, reason| {
+            if reason == EvictionReason::Replaced {
+                return;
+            }
+            if let Some(shared) = listener.upgrade() {
+                shared.write_if_dirty(key, value);
+            }
+        });
+        WriteBackCache {
+            shared,
+            flusher: None,
+        }
+    }
+
+    /// Create a new WriteBackCache with the given capacity and a background thread which writes dirty items to `sink` every `flush_interval`.
+    ///
+    /// The thread holds a weak reference to the cache and exits when the cache is dropped.
+    pub fn with_flush_interval(capacity: u64, sink: S, flush_interval: Duration) -> Self {
+        let mut cache = Self::new(capacity, sink);
+        let (shutdown, wait) = mpsc::channel::<()>();
+        let shared = Arc::downgrade(&cache.shared);
+        let handle = thread::spawn(move || {
+            // the channel only disconnects when the cache is dropped
+            while let Err(RecvTimeoutError::Timeout) = wait.recv_timeout(flush_interval) {
+                let Some(shared) = shared.upgrade() else {
+                    break;
+                };
+                shared.flush();
+            }
+        });
+        cache.flusher = Some((shutdown, handle));
+        cache
+    }
+
+    /// Write every dirty item to the sink now and mark it clean, returning how many items were written.
+    pub fn flush(&self) -> usize {
+        self.shared.flush()
+    }
+
+    /// Get the number of items which have been set but not yet written to the sink.
+    pub fn dirty_len(&self) -> usize {
+        self.shared.dirty.lock().keys.len()
+    }
+}
+
+impl<
+        K: Eq + Hash + Clone + Sync + Send + 'static,
+        V: Send + Sync + 'static,
+        S: WriteSink<K, V> + 'static,
+    > Drop for WriteBackCache<K, V, S>
+{
+    /// Stop the background thread, if there is one, and write any dirty items to the sink.
+    fn drop(&mut self) {
+        if let Some((shutdown, handle)) = self.flusher.take() {
+            drop(shutdown);
+            // the last reference can be dropped on the flusher thread itself, which must not wait for itself
+            if handle.thread().id() != thread::current().id() {
+                let _ = handle.join();
+            }
+        }
+        self.shared.flush();
+    }
+}
+
+impl<
+        K: Eq + Hash + Clone + Sync + Send + 'static,
+        V: Send + Sync + 'static,
+        S: WriteSink<K, V> + 'static,
+    > Cache<K, V> for WriteBackCache<K, V, S>
+{
+    /// Get a value from the cache.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        self.shared.cache.get(key)
+    }
+
+    /// Get several values from the cache.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        self.shared.cache.get_many(keys)
+    }
+
+    /// Peek at a value in the cache.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        self.shared.cache.peek(key)
+    }
+
+    /// Set a value in the cache and mark it dirty, it is written to the sink by the next flush or when it leaves the cache.
+    ///
+    /// A value which the cache does not keep, for example because the capacity is zero, is written straight away. If another thread evicts the value as soon as it is stored it can be written twice, but it is never lost.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        self.shared.begin_change(&key);
+        let previous = self.shared.cache.set_arc(key.clone(), value.clone());
+        let kept = self.shared.cache.contains_key(&key);
+        self.shared.end_change(&key, kept);
+        if !kept {
+            self.shared.sink.write(&key, &value);
+        }
+        previous
+    }
+
+    /// Remove a value from the cache, a dirty value is written to the sink first.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        self.shared.cache.remove(key)
+    }
+
+    /// Clear the cache, dirty values are written to the sink first.
+    fn clear(&self) {
+        self.shared.cache.clear();
+    }
+
+    /// Get the statistics of the cache.
+    fn stats(&self) -> CacheStats {
+        self.shared.cache.stats()
+    }
+
+    /// Reset the statistics of the cache.
+    fn reset_stats(&self) {
+        self.shared.cache.reset_stats();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        self.shared.cache.len()
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        self.shared.cache.capacity()
+    }
+
+    /// Change the capacity of the cache, dirty values which are evicted are written to the sink first.
+    fn change_capacity(&self, capacity: u64) {
+        self.shared.cache.change_capacity(capacity);
+    }
+
+    /// Remove every item for which the predicate returns false, dirty values are written to the sink first.
+    fn retain<F>(&self, f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        self.shared.cache.retain(f);
+    }
+
+    /// Update a value in place and mark it dirty.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let was_dirty = self.shared.begin_change(key);
+        let computed = self.shared.cache.compute(key, f);
+        self.shared.end_change(key, computed || was_dirty);
+        computed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex as StdMutex;
+
+    type Writes = Arc<StdMutex<Vec<(u64, u64)>>>;
+
+    /// Create a sink which records every write.
+    fn recording_sink() -> (Writes, impl WriteSink<u64, u64>) {
+        let writes = Arc::new(StdMutex::new(Vec::new()));
+        let sink_writes = writes.clone();
+        let sink = move |key: &u64, value: &u64| sink_writes.lock().unwrap().push((*key, *value));
+        (writes, sink)
+    }
+
+    #[test]
+    fn test_write_back_cache_flushes_dirty_entry_on_eviction() {
+        let (writes, sink) = recording_sink();
+        let cache = WriteBackCache::new(2, sink);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.flush(), 2);
+        writes.lock().unwrap().clear();
+
+        cache.set(1, 10);
+        // 2 is clean so it is evicted without a write
+        cache.set(3, 3);
+        assert!(writes.lock().unwrap().is_empty());
+        // 1 is dirty so it is written when it is evicted
+        cache.set(4, 4);
+        assert_eq!(*writes.lock().unwrap(), vec![(1, 10)]);
+        assert_eq!(cache.dirty_len(), 2);
+
+        assert!(cache.compute(&3, |value| *value += 1));
+        cache.remove(&3);
+        assert_eq!(*writes.lock().unwrap(), vec![(1, 10), (3, 4)]);
+        drop(cache);
+        assert_eq!(*writes.lock().unwrap(), vec![(1, 10), (3, 4), (4, 4)]);
+    }
+
+    #[test]
+    fn test_write_back_cache_background_flush() {
+        let (writes, sink) = recording_sink();
+        let cache = WriteBackCache::with_flush_interval(10, sink, Duration::from_millis(10));
+        cache.set(1, 1);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(*writes.lock().unwrap(), vec![(1, 1)]);
+        assert_eq!(cache.dirty_len(), 0);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+    }
+
+    #[test]
+    fn test_write_back_cache_set_racing_flush_loses_no_write() {
+        use std::sync::Barrier;
+
+        const ROUNDS: u64 = 2000;
+        let (writes, sink) = recording_sink();
+        let cache = Arc::new(WriteBackCache::new(8, sink));
+        let barrier = Arc::new(Barrier::new(2));
+        let flusher = {
+            let (cache, barrier) = (cache.clone(), barrier.clone());
+            thread::spawn(move || {
+                for _ in 0..ROUNDS {
+                    barrier.wait();
+                    for _ in 0..8 {
+                        cache.flush();
+                    }
+                    barrier.wait();
+                }
+            })
+        };
+        for round in 0..ROUNDS {
+            barrier.wait();
+            for key in 0..8 {
+                cache.set(key, round);
+            }
+            barrier.wait();
+            cache.flush();
+            // whichever flush wrote it, the last write of each key is the value it was set to in this round
+            let mut last = HashMap::new();
+            for (key, value) in writes.lock().unwrap().drain(..) {
+                last.insert(key, value);
+            }
+            for key in 0..8 {
+                assert_eq!(last.get(&key), Some(&round));
+            }
+        }
+        flusher.join().unwrap();
+    }
+}
@@ -0,0 +1,424 @@
+use crate::cache::sketch::CountMinSketch;
+use crate::cache::sync::Mutex;
+use crate::cache::{retain_linked_hash_map, update_in_place, Cache, CacheStats, StatsCounters};
+use linked_hash_map::LinkedHashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// The default fraction of the capacity given to the admission window.
+const DEFAULT_WINDOW_RATIO: f64 = 0.01;
+
+/// The fraction of the main region reserved for the protected segment.
+const PROTECTED_RATIO: f64 = 0.8;
+
+/// The inner data structure for the WTinyLFUCache.
+struct WTinyLFUCacheInner<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    capacity: u64,
+    window_ratio: f64,
+    window_capacity: u64,
+    protected_capacity: u64,
+    window: LinkedHashMap<K, Arc<V>>,
+    probation: LinkedHashMap<K, Arc<V>>,
+    protected: LinkedHashMap<K, Arc<V>>,
+    sketch: CountMinSketch,
+    evictions: u64,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> WTinyLFUCacheInner<K, V> {
+    /// Create a new WTinyLFUCacheInner with the given capacity, window ratio and sketch sample size.
+    fn new(capacity: u64, window_ratio: f64, sample_size: u64) -> Self {
+        let mut inner = WTinyLFUCacheInner {
+            capacity,
+            window_ratio: window_ratio.clamp(0.0, 1.0),
+            window_capacity: 0,
+            protected_capacity: 0,
+            window: LinkedHashMap::new(),
+            probation: LinkedHashMap::new(),
+            protected: LinkedHashMap::new(),
+            sketch: CountMinSketch::new(capacity, sample_size),
+            evictions: 0,
+        };
+        inner.compute_segment_capacities();
+        inner
+    }
+
+    /// Split the total capacity between the window and the protected segment of the main region.
+    fn compute_segment_capacities(&mut self) {
+        let window = (self.capacity as f64 * self.window_ratio).round() as u64;
+        self.window_capacity = window.max(1).min(self.capacity);
+        let main_capacity = self.capacity - self.window_capacity;
+        self.protected_capacity = (main_capacity as f64 * PROTECTED_RATIO) as u64;
+    }
+
+    /// The capacity of the main region (probation and protected segments combined).
+    fn main_capacity(&self) -> u64 {
+        self.capacity - self.window_capacity
+    }
+
+    /// The number of items in the main region.
+    fn main_len(&self) -> u64 {
+        (self.probation.len() + self.protected.len()) as u64
+    }
+
+    /// The total number of items stored in the cache.
+    fn len(&self) -> u64 {
+        self.window.len() as u64 + self.main_len()
+    }
+
+    /// Demote the least recently used protected items into probation until the protected segment fits.
+    fn enforce_protected_capacity(&mut self) {
+        while self.protected.len() as u64 > self.protected_capacity {
+            match self.protected.pop_front() {
+                Some((key, value)) => {
+                    self.probation.insert(key, value);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Move items that overflow the window into the main region, subject to the TinyLFU admission policy.
+    fn enforce_window_capacity(&mut self) {
+        while self.window.len() as u64 > self.window_capacity {
+            match self.window.pop_front() {
+                Some((key, value)) => self.admit(key, value),
+                None => break,
+            }
+        }
+    }
+
+    /// Offer a candidate evicted from the window to the main region.
+    ///
+    /// If the main region is full the candidate is only admitted when it has been seen more often than the main region's victim, a rejected candidate is evicted.
+    fn admit(&mut self, key: K, value: Arc<V>) {
+        if self.main_capacity() == 0 {
+            self.evictions += 1;
+            return;
+        }
+        if self.main_len() < self.main_capacity() {
+            self.probation.insert(key, value);
+            return;
+        }
+        let victim_freq = match self.probation.front().or_else(|| self.protected.front()) {
+            Some((victim, _)) => self.sketch.frequency(victim),
+            None => return,
+        };
+        if self.sketch.frequency(&key) > victim_freq {
+            self.evict_main();
+            self.probation.insert(key, value);
+        } else {
+            self.evictions += 1;
+        }
+    }
+
+    /// Evict the least recently used item of the main region, preferring the probation segment.
+    fn evict_main(&mut self) {
+        if self.probation.pop_front().is_some() || self.protected.pop_front().is_some() {
+            self.evictions += 1;
+        }
+    }
+
+    /// Evict items until every segment fits within its share of the capacity.
+    fn enforce_capacity(&mut self) {
+        self.enforce_window_capacity();
+        while self.main_len() > self.main_capacity() {
+            self.evict_main();
+        }
+        self.enforce_protected_capacity();
+    }
+
+    /// Look up a key, updating the eviction order.
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        self.sketch.increment(key);
+
+        let mut result = self.window.get_refresh(key).cloned();
+        if result.is_none() {
+            result = self.protected.get_refresh(key).cloned();
+        }
+        if result.is_none() {
+            if let Some(value) = self.probation.remove(key) {
+                self.protected.insert(key.clone(), value.clone());
+                self.enforce_protected_capacity();
+                result = Some(value);
+            }
+        }
+
+        result
+    }
+
+    /// Insert a value, evicting items as needed to stay within the capacity, and return the previous value.
+    fn set(&mut self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        self.sketch.increment(&key);
+
+        if let Some(entry) = self.window.get_refresh(&key) {
+            return Some(std::mem::replace(entry, value));
+        }
+        if let Some(entry) = self.protected.get_refresh(&key) {
+            return Some(std::mem::replace(entry, value));
+        }
+        if let Some(entry) = self.probation.get_refresh(&key) {
+            return Some(std::mem::replace(entry, value));
+        }
+
+        if self.capacity > 0 {
+            self.window.insert(key, value);
+            self.enforce_window_capacity();
+        }
+        None
+    }
+}
+
+/// WTinyLFUCache is a cache that uses the Window TinyLFU (W-TinyLFU) algorithm to evict items.
+///
+/// New items enter a small LRU admission window. When an item is pushed out of the window it competes with the least recently used item of the main region, which is a segmented LRU made of a probation and a protected segment. A count-min sketch estimates how often each key has been accessed and the more frequent of the two is kept. This gives high hit ratios for workloads with skewed popularity while still admitting new popular items quickly.
+///
+/// All mutability is handled internally with a Mutex, so the cache can be shared between threads. Values are returned as Arcs to allow for shared ownership.
+///
+/// Example:
+/// ```
+/// use arcache::{Cache, WTinyLFUCache};
+///
+/// let cache = WTinyLFUCache::<&str, String>::new(10);
+///
+/// let original_value = cache.set("key", "value".to_string());
+///
+/// assert!(original_value.is_none());
+///
+/// let value = cache.get(&"key");
+///
+/// assert!(value.is_some());
+/// assert_eq!(*value.unwrap(), "value".to_string());
+/// println!("{:?}", cache.stats());
+/// ```
+pub struct WTinyLFUCache<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> {
+    inner: Mutex<WTinyLFUCacheInner<K, V>>,
+    counters: StatsCounters,
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> WTinyLFUCache<K, V> {
+    /// Create a new WTinyLFUCache with the given capacity, 1% of the capacity is used for the admission window.
+    pub fn new(capacity: u64) -> Self {
+        Self::with_window_ratio(capacity, DEFAULT_WINDOW_RATIO)
+    }
+
+    /// Create a new WTinyLFUCache with the given capacity and the fraction of the capacity to use for the admission window.
+    pub fn with_window_ratio(capacity: u64, window_ratio: f64) -> Self {
+        Self::with_config(capacity, window_ratio, capacity.saturating_mul(10))
+    }
+
+    /// Create a new WTinyLFUCache with the given capacity, window ratio and sketch sample size.
+    /// + The window ratio is the fraction of the capacity used for the admission window, the remainder is used for the main region.
+    /// + The sample size is the number of recorded accesses after which all frequency counts are halved.
+    pub fn with_config(capacity: u64, window_ratio: f64, sample_size: u64) -> Self {
+        WTinyLFUCache {
+            inner: Mutex::new(WTinyLFUCacheInner::new(capacity, window_ratio, sample_size)),
+            counters: StatsCounters::new(),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone + Sync + Send, V: Send + Sync> Cache<K, V> for WTinyLFUCache<K, V> {
+    /// Get a value from the cache, a hit in the probation segment promotes the item to the protected segment.
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        self.counters.record(inner.get(key))
+    }
+
+    /// Get several values from the cache under a single lock, the results are in the same order as the keys.
+    fn get_many(&self, keys: &[K]) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        keys.iter()
+            .map(|key| self.counters.record(inner.get(key)))
+            .collect()
+    }
+
+    /// Peek at a value in the cache without updating its recency or the cache statistics.
+    fn peek(&self, key: &K) -> Option<Arc<V>> {
+        let inner = self.inner.lock();
+        inner
+            .window
+            .get(key)
+            .or_else(|| inner.probation.get(key))
+            .or_else(|| inner.protected.get(key))
+            .cloned()
+    }
+
+    /// Set a value in the cache, new items are placed in the admission window.
+    fn set_arc(&self, key: K, value: Arc<V>) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner.set(key, value)
+    }
+
+    /// Set several values in the cache under a single lock, capacity is enforced after each insertion and the previous values are returned in the same order as the items.
+    fn set_many(&self, items: Vec<(K, V)>) -> Vec<Option<Arc<V>>> {
+        let mut inner = self.inner.lock();
+        items
+            .into_iter()
+            .map(|(key, value)| inner.set(key, Arc::new(value)))
+            .collect()
+    }
+
+    /// Remove a value from the cache.
+    fn remove(&self, key: &K) -> Option<Arc<V>> {
+        let mut inner = self.inner.lock();
+        inner
+            .window
+            .remove(key)
+            .or_else(|| inner.probation.remove(key))
+            .or_else(|| inner.protected.remove(key))
+    }
+
+    /// Clear the cache, removing all items and forgetting recorded frequencies.
+    fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.window.clear();
+        inner.probation.clear();
+        inner.protected.clear();
+        inner.sketch.clear();
+    }
+
+    /// Get the cache statistics.
+    fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock();
+        CacheStats {
+            hits: self.counters.hits(),
+            misses: self.counters.misses(),
+            evictions: inner.evictions,
+            size: inner.len(),
+            capacity: inner.capacity,
+        }
+    }
+
+    /// Reset the hit, miss and eviction counts.
+    fn reset_stats(&self) {
+        let mut inner = self.inner.lock();
+        inner.evictions = 0;
+        self.counters.reset();
+    }
+
+    /// Get the number of items in the cache.
+    fn len(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.len()
+    }
+
+    /// Get the maximum number of items in the cache.
+    fn capacity(&self) -> u64 {
+        let inner = self.inner.lock();
+        inner.capacity
+    }
+
+    /// Change the capacity of the cache, the window and main region are resized proportionally and overflowing items are evicted.
+    fn change_capacity(&self, capacity: u64) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity;
+        inner.compute_segment_capacities();
+        inner.enforce_capacity();
+    }
+
+    /// Remove every item for which the predicate returns false.
+    fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &Arc<V>) -> bool,
+    {
+        let mut inner = self.inner.lock();
+        retain_linked_hash_map(&mut inner.window, &mut f);
+        retain_linked_hash_map(&mut inner.probation, &mut f);
+        retain_linked_hash_map(&mut inner.protected, &mut f);
+    }
+
+    /// Update a value in place without recording an access or updating the cache statistics.
+    fn compute<F>(&self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+        V: Clone,
+    {
+        let mut inner = self.inner.lock();
+        let WTinyLFUCacheInner {
+            window,
+            probation,
+            protected,
+            ..
+        } = &mut *inner;
+        let value = window
+            .get_mut(key)
+            .or_else(|| probation.get_mut(key))
+            .or_else(|| protected.get_mut(key));
+        update_in_place(value, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wtinylfu_cache() {
+        let cache = WTinyLFUCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        assert_eq!(cache.get(&1).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&2).map(|v| *v), Some(2));
+        assert_eq!(cache.set(1, 10).map(|v| *v), Some(1));
+        assert_eq!(cache.get(&1).map(|v| *v), Some(10));
+        cache.set(3, 3);
+        assert_eq!(cache.stats().size, 2);
+    }
+
+    #[test]
+    fn test_wtinylfu_cache_keeps_frequent_items() {
+        let cache = WTinyLFUCache::new(100);
+        for _ in 0..10 {
+            for i in 0..10 {
+                cache.set(i, i);
+                cache.get(&i);
+            }
+        }
+        for i in 100..1000 {
+            cache.set(i, i);
+        }
+        for i in 0..10 {
+            assert_eq!(cache.get(&i).map(|v| *v), Some(i));
+        }
+        assert_eq!(cache.stats().size, 100);
+    }
+
+    #[test]
+    fn test_wtinylfu_cache_change_capacity() {
+        let cache = WTinyLFUCache::with_window_ratio(10, 0.2);
+        for i in 0..10 {
+            cache.set(i, i);
+        }
+        assert_eq!(cache.stats().size, 10);
+        cache.change_capacity(4);
+        assert_eq!(cache.stats().size, 4);
+        assert_eq!(cache.stats().capacity, 4);
+        cache.change_capacity(0);
+        assert_eq!(cache.stats().size, 0);
+    }
+
+    #[test]
+    fn test_wtinylfu_cache_clear() {
+        let cache = WTinyLFUCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.clear();
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[test]
+    fn test_wtinylfu_cache_stats() {
+        let cache = WTinyLFUCache::new(2);
+        cache.set(1, 1);
+        cache.set(2, 2);
+        cache.get(&1);
+        cache.get(&2);
+        cache.get(&3);
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.size, 2);
+        assert_eq!(stats.capacity, 2);
+    }
+}
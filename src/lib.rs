@@ -1,11 +1,73 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "no_std")))]
+compile_error!("either the `std` feature or, for a build without the standard library, the `no_std` feature must be enabled");
+
+extern crate alloc;
+
 pub mod cache;
+#[cfg(feature = "std")]
+pub use crate::cache::adaptive::AdaptiveCache;
+#[cfg(feature = "std")]
+pub use crate::cache::approx_lru::ApproxLRUCache;
+#[cfg(feature = "std")]
+pub use crate::cache::arc::ARCCache;
+pub use crate::cache::clock::ClockCache;
 pub use crate::cache::fifo::FIFOCache;
+#[cfg(feature = "std")]
+pub use crate::cache::gdsf::GDSFCache;
+#[cfg(feature = "std")]
+pub use crate::cache::grouped::GroupedCache;
+#[cfg(feature = "std")]
+pub use crate::cache::intern::InternCache;
+#[cfg(feature = "std")]
 pub use crate::cache::lfu::LFUCache;
 pub use crate::cache::lifo::LIFOCache;
+#[cfg(feature = "std")]
+pub use crate::cache::loading::{Loader, LoadingCache};
+#[cfg(feature = "std")]
+pub use crate::cache::local::{LocalCache, LocalLRUCache};
+#[cfg(feature = "std")]
 pub use crate::cache::lru::LRUCache;
+#[cfg(not(feature = "std"))]
+pub use crate::cache::lru_no_std::LRUCache;
+#[cfg(feature = "std")]
 pub use crate::cache::mru::MRUCache;
+#[cfg(feature = "std")]
+pub use crate::cache::negative::NegativeCache;
+#[cfg(feature = "std")]
+pub use crate::cache::policy::{EvictionPolicy, FIFOPolicy, LRUPolicy, PolicyCache};
+#[cfg(feature = "std")]
 pub use crate::cache::random_replacement::RandomReplacementCache;
-pub use crate::cache::ttl::TTLCache;
+#[cfg(feature = "std")]
+pub use crate::cache::sharded::ShardedCache;
+pub use crate::cache::sieve::SieveCache;
+#[cfg(feature = "async")]
+pub use crate::cache::single_flight::AsyncSingleFlight;
+#[cfg(feature = "std")]
+pub use crate::cache::single_flight::SingleFlight;
+#[cfg(feature = "std")]
+pub use crate::cache::slru::SLRUCache;
+#[cfg(feature = "std")]
+pub use crate::cache::tiered::{TieredCache, TieredStats};
+#[cfg(feature = "std")]
+pub use crate::cache::time::SystemClock;
+pub use crate::cache::time::{Clock, ManualClock};
+#[cfg(feature = "std")]
+pub use crate::cache::ttl::{TTLCache, TTLCacheBuilder};
+#[cfg(not(feature = "std"))]
+pub use crate::cache::ttl_no_std::TTLCache;
+#[cfg(feature = "std")]
+pub use crate::cache::two_queue::TwoQueueCache;
+pub use crate::cache::unbounded::UnboundedCache;
+#[cfg(feature = "std")]
+pub use crate::cache::weak::WeakCache;
+#[cfg(feature = "std")]
+pub use crate::cache::weighted_lru::WeightedLRUCache;
+#[cfg(feature = "std")]
+pub use crate::cache::write_back::{WriteBackCache, WriteSink};
+#[cfg(feature = "std")]
+pub use crate::cache::wtinylfu::WTinyLFUCache;
 pub use crate::cache::Cache;
 
 #[doc = include_str!("../README.md")]